@@ -0,0 +1,156 @@
+// src/commands.rs
+// REPL commands start with '/' and are handled locally, never sent to the model.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    MemoryStats,
+    MemoryList(usize),
+    MemoryTopics,
+    MemorySearch(String),
+    MemoryDelete(usize),
+    MemoryContext,
+    Sources,
+    Source(usize),
+    Review,
+    Approve(i64),
+    Reject(i64),
+    Up,
+    Down,
+    Correct(String),
+    Regenerate,
+    Conflicts,
+    /// Conflict id, and whether to keep the newer insight
+    KeepConflict(i64, bool),
+    Profile,
+    /// Field and value
+    ProfileSet(String, String),
+    /// One field, or everything
+    ProfileClear(Option<String>),
+    /// Entities, or what is known about one
+    Graph(Option<String>),
+    /// Graphviz export, to a file when one is given
+    GraphDot(Option<String>),
+    LearningStatus,
+    LearningReport,
+}
+
+impl Command {
+    /// Returns `None` when the input is not a command at all, and an error
+    /// message when it looks like one but cannot be parsed.
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let rest = input.strip_prefix('/')?;
+        let mut parts = rest.splitn(3, char::is_whitespace);
+        let name = parts.next().unwrap_or("").to_lowercase();
+        let sub = parts.next().unwrap_or("").to_lowercase();
+        let arg = parts.next().unwrap_or("").trim();
+
+        let command = match (name.as_str(), sub.as_str()) {
+            ("help", _) => Ok(Command::Help),
+            ("memory", "" | "stats") => Ok(Command::MemoryStats),
+            ("memory", "list") => {
+                if arg.is_empty() {
+                    Ok(Command::MemoryList(10))
+                } else {
+                    arg.parse()
+                        .map(Command::MemoryList)
+                        .map_err(|_| format!("Invalid count: {}", arg))
+                }
+            }
+            ("memory", "topics") => Ok(Command::MemoryTopics),
+            ("memory", "search") if !arg.is_empty() => Ok(Command::MemorySearch(arg.to_string())),
+            ("memory", "search") => Err("Usage: /memory search <text>".to_string()),
+            ("memory", "delete") => arg
+                .parse()
+                .map(Command::MemoryDelete)
+                .map_err(|_| "Usage: /memory delete <index>".to_string()),
+            ("memory", "context") => Ok(Command::MemoryContext),
+            ("sources", _) => Ok(Command::Sources),
+            ("source", n) => n
+                .parse()
+                .map(Command::Source)
+                .map_err(|_| "Usage: /source <n>".to_string()),
+            ("review", "" | "list") => Ok(Command::Review),
+            ("review", "approve") => arg
+                .parse()
+                .map(Command::Approve)
+                .map_err(|_| "Usage: /review approve <id>".to_string()),
+            ("review", "reject") => arg
+                .parse()
+                .map(Command::Reject)
+                .map_err(|_| "Usage: /review reject <id>".to_string()),
+            ("up", _) => Ok(Command::Up),
+            ("down", _) => Ok(Command::Down),
+            // Keeps the correction as typed, not lowercased
+            ("correct", _) => match rest[name.len()..].trim() {
+                "" => Err("Usage: /correct <what the answer should have said>".to_string()),
+                text => Ok(Command::Correct(text.to_string())),
+            },
+            ("regenerate", _) => Ok(Command::Regenerate),
+            ("conflicts", "" | "list") => Ok(Command::Conflicts),
+            ("conflicts", "keep") => {
+                let mut args = arg.split_whitespace();
+                match (args.next().and_then(|id| id.parse().ok()), args.next()) {
+                    (Some(id), Some("new")) => Ok(Command::KeepConflict(id, true)),
+                    (Some(id), Some("old")) => Ok(Command::KeepConflict(id, false)),
+                    _ => Err("Usage: /conflicts keep <id> old|new".to_string()),
+                }
+            }
+            ("profile", "" | "show") => Ok(Command::Profile),
+            ("profile", "set") => match arg.split_once(char::is_whitespace) {
+                Some((field, value)) => Ok(Command::ProfileSet(field.to_lowercase(), value.trim().to_string())),
+                None => Err("Usage: /profile set <field> <value>".to_string()),
+            },
+            ("profile", "clear") if arg.is_empty() => Ok(Command::ProfileClear(None)),
+            ("profile", "clear") => Ok(Command::ProfileClear(Some(arg.to_lowercase()))),
+            ("graph", "") => Ok(Command::Graph(None)),
+            ("graph", "dot") if arg.is_empty() => Ok(Command::GraphDot(None)),
+            ("graph", "dot") => Ok(Command::GraphDot(Some(arg.to_string()))),
+            ("graph", _) => Ok(Command::Graph(Some(rest[name.len()..].trim().to_string()))),
+            ("learning", "" | "status") => Ok(Command::LearningStatus),
+            ("learning", "report") => Ok(Command::LearningReport),
+            _ => Err(format!("Unknown command: {} (type /help)", input)),
+        };
+
+        Some(command)
+    }
+
+    pub fn help() -> &'static str {
+        "Commands:\n\
+         \x20 /memory stats           Short-term memory statistics\n\
+         \x20 /memory list [n]        Most recent entries with their scores\n\
+         \x20 /memory topics          Topic index\n\
+         \x20 /memory search <text>   Entries mentioning the text\n\
+         \x20 /memory delete <index>  Forget an entry (index from list)\n\
+         \x20 /memory context         Prompt sent with the last message\n\
+         \x20 /sources                Knowledge base entries used for the last answer\n\
+         \x20 /source <n>             Where source [n] of the last answer came from\n\
+         \x20 /review                 Learned content waiting for review\n\
+         \x20 /review approve <id>    Add a reviewed item to the knowledge base\n\
+         \x20 /review reject <id>     Drop a reviewed item\n\
+         \x20 /up, /down              Rate the last answer\n\
+         \x20 /correct <text>         Say what the last answer should have said\n\
+         \x20 /regenerate             Ask for another answer to the last message\n\
+         \x20 /conflicts              Learned items that contradict each other\n\
+         \x20 /conflicts keep <id> old|new  Settle a conflict\n\
+         \x20 /profile               What the agent knows about you\n\
+         \x20 /profile set <field> <value>  Set name, expertise, verbosity, language, interests or projects\n\
+         \x20 /profile clear [field]  Forget a field, or the whole profile\n\
+         \x20 /graph                  People, projects, services and tools the agent knows\n\
+         \x20 /graph <name>           What is known about one of them\n\
+         \x20 /graph dot [file]       The graph in Graphviz DOT format\n\
+         \x20 /learning status        Exchanges waiting to be learned from\n\
+         \x20 /learning report        What has been learned, in numbers\n\
+         \x20 /help                   This help"
+    }
+}
+
+/// Shortens text to one line of at most `max` characters for listings.
+pub fn preview(text: &str, max: usize) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() <= max {
+        line
+    } else {
+        format!("{}...", line.chars().take(max).collect::<String>())
+    }
+}
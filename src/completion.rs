@@ -29,4 +29,6 @@ impl From<reqwest::Error> for CompletionError {
 pub trait CompletionProvider {
     type Error: Error + Send + Sync + 'static;
     async fn complete(&self, prompt: &str) -> Result<String, Self::Error>;
+    /// Name of the model answering, recorded with what is learned from it
+    fn model(&self) -> &str;
 }
@@ -0,0 +1,219 @@
+// src/crypto.rs
+// Optional encryption at rest for the JSON stores and the database.
+//
+// The key is 32 random bytes, base64 encoded, taken from AGENT_ENCRYPTION_KEY
+// or from the file named by AGENT_KEY_FILE. Without a key everything is
+// stored in plaintext, as before.
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use thiserror::Error;
+
+// Encrypted files start with this header, followed by the nonce and the
+// AES-256-GCM ciphertext
+const MAGIC: &[u8] = b"RAAENC1\n";
+const NONCE_LEN: usize = 12;
+
+static CIPHER: OnceLock<Option<Cipher>> = OnceLock::new();
+
+#[derive(Error, Debug)]
+pub enum CryptoError {
+    #[error("Invalid encryption key: {0}")]
+    InvalidKey(String),
+    #[error("Failed to decrypt {0}: wrong key or corrupted file")]
+    Decrypt(String),
+    #[error("{0} is encrypted but no key is configured (set AGENT_ENCRYPTION_KEY or AGENT_KEY_FILE)")]
+    MissingKey(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<CryptoError> for std::io::Error {
+    fn from(err: CryptoError) -> Self {
+        match err {
+            CryptoError::Io(e) => e,
+            other => std::io::Error::new(std::io::ErrorKind::InvalidData, other.to_string()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Cipher {
+    key: [u8; 32],
+}
+
+impl Cipher {
+    pub fn from_base64(encoded: &str) -> Result<Self, CryptoError> {
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+        let key: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKey("expected 32 bytes".to_string()))?;
+        Ok(Self { key })
+    }
+
+    pub fn from_key_file(path: &str) -> Result<Self, CryptoError> {
+        Self::from_base64(&fs::read_to_string(path)?)
+    }
+
+    /// Reads the key from AGENT_ENCRYPTION_KEY or AGENT_KEY_FILE, if any.
+    pub fn from_env() -> Result<Option<Self>, CryptoError> {
+        if let Ok(key) = env::var("AGENT_ENCRYPTION_KEY") {
+            return Self::from_base64(&key).map(Some);
+        }
+        if let Ok(path) = env::var("AGENT_KEY_FILE") {
+            return Self::from_key_file(&path).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// A fresh random key, base64 encoded.
+    pub fn generate_key() -> String {
+        BASE64.encode(Aes256Gcm::generate_key(OsRng))
+    }
+
+    /// Raw key in the form SQLCipher expects for `PRAGMA key`.
+    pub fn sqlcipher_key(&self) -> String {
+        let hex: String = self.key.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("\"x'{}'\"", hex)
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key));
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .expect("AES-GCM encryption cannot fail for in-memory buffers");
+
+        let mut out = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        out
+    }
+
+    pub fn decrypt(&self, data: &[u8], name: &str) -> Result<Vec<u8>, CryptoError> {
+        let body = data
+            .strip_prefix(MAGIC)
+            .filter(|body| body.len() >= NONCE_LEN)
+            .ok_or_else(|| CryptoError::Decrypt(name.to_string()))?;
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key));
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CryptoError::Decrypt(name.to_string()))
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Sets the process-wide key used by `read_store` and `write_store`.
+/// Only the first call has an effect.
+pub fn init(cipher: Option<Cipher>) {
+    let _ = CIPHER.set(cipher);
+}
+
+pub fn cipher() -> Option<&'static Cipher> {
+    CIPHER.get().and_then(|c| c.as_ref())
+}
+
+/// Decodes a store's raw bytes, decrypting them if they are encrypted.
+pub fn decode_store(data: Vec<u8>, cipher: Option<&Cipher>, name: &str) -> Result<String, CryptoError> {
+    let plaintext = if is_encrypted(&data) {
+        match cipher {
+            Some(cipher) => cipher.decrypt(&data, name)?,
+            None => return Err(CryptoError::MissingKey(name.to_string())),
+        }
+    } else {
+        // Plaintext files are still readable, and get encrypted on next write
+        data
+    };
+    String::from_utf8(plaintext)
+        .map_err(|e| CryptoError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+}
+
+/// Encodes a store's contents, encrypting them when a key is given.
+pub fn encode_store(contents: &str, cipher: Option<&Cipher>) -> Vec<u8> {
+    match cipher {
+        Some(cipher) => cipher.encrypt(contents.as_bytes()),
+        None => contents.as_bytes().to_vec(),
+    }
+}
+
+/// Reads a JSON store with the configured key.
+pub fn read_store<P: AsRef<Path>>(path: P) -> Result<String, CryptoError> {
+    let path = path.as_ref();
+    decode_store(fs::read(path)?, cipher(), &path.display().to_string())
+}
+
+/// Writes a JSON store with the configured key.
+pub fn write_store<P: AsRef<Path>>(path: P, contents: &str) -> Result<(), CryptoError> {
+    write_atomic(path.as_ref(), &encode_store(contents, cipher()))?;
+    Ok(())
+}
+
+// Writes next to the file and renames over it, so a crash mid-write
+// leaves the old contents rather than a truncated store
+fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+/// Re-encrypts a JSON store from `old` to `new` key. `None` means plaintext.
+pub fn rotate_store(path: &str, old: Option<&Cipher>, new: Option<&Cipher>) -> Result<bool, CryptoError> {
+    if !Path::new(path).exists() {
+        return Ok(false);
+    }
+    let contents = decode_store(fs::read(path)?, old, path)?;
+    write_atomic(Path::new(path), &encode_store(&contents, new))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> Cipher {
+        Cipher::from_base64(&Cipher::generate_key()).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = key();
+        let data = encode_store("{\"users\":{}}", Some(&cipher));
+        assert!(is_encrypted(&data));
+        assert_eq!(decode_store(data, Some(&cipher), "store").unwrap(), "{\"users\":{}}");
+    }
+
+    #[test]
+    fn wrong_key_is_refused() {
+        let data = encode_store("secret", Some(&key()));
+        assert!(matches!(decode_store(data.clone(), Some(&key()), "store"), Err(CryptoError::Decrypt(_))));
+        assert!(matches!(decode_store(data, None, "store"), Err(CryptoError::MissingKey(_))));
+    }
+
+    #[test]
+    fn plaintext_stays_readable() {
+        let data = encode_store("plain", None);
+        assert!(!is_encrypted(&data));
+        assert_eq!(decode_store(data, Some(&key()), "store").unwrap(), "plain");
+    }
+
+    #[test]
+    fn short_keys_are_refused() {
+        assert!(matches!(Cipher::from_base64(&BASE64.encode([0u8; 16])), Err(CryptoError::InvalidKey(_))));
+    }
+}
@@ -0,0 +1,264 @@
+// src/database/analytics.rs
+// Aggregates over learned insights for the learning report. Insights are
+// JSON rows of the knowledge_base table, so every query starts from the
+// same view of their fields. Weeks start on Monday.
+use rusqlite::params;
+use serde::Serialize;
+use super::{Database, DatabaseError};
+
+// Insights of the owners in ?1 (a JSON array of user ids)
+const INSIGHTS: &str = "WITH insights AS (
+    SELECT json_extract(value, '$.topic') AS topic,
+           json_extract(value, '$.context') AS context,
+           json_extract(value, '$.source') AS source,
+           json_extract(value, '$.confidence') AS confidence,
+           json_extract(value, '$.timestamp') AS ts,
+           date(json_extract(value, '$.timestamp'), 'weekday 0', '-6 days') AS week,
+           json_extract(value, '$.provenance.conversation_id') AS conversation_id
+    FROM knowledge_base
+    WHERE user_id IN (SELECT value FROM json_each(?1))
+      AND substr(key, 1, 8) = 'insight:' AND json_valid(value)
+)";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TopicWeek {
+    /// Monday of the week
+    pub week: String,
+    pub topic: String,
+    pub insights: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceBand {
+    pub source: String,
+    /// Confidence band, from 0 (below 0.5) through 1 (0.5 to 0.7) and
+    /// 2 (0.7 to 0.9) to 3 (0.9 and up)
+    pub band: usize,
+    pub insights: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WeekCount {
+    pub week: String,
+    pub count: i64,
+}
+
+/// A fact with how often it was used in a prompt and learned.
+#[derive(Debug, Clone, Serialize)]
+pub struct FactUsage {
+    pub fact: String,
+    pub uses: i64,
+    pub last_used: Option<String>,
+    pub mentions: i64,
+    pub confidence: f64,
+    pub last_seen: String,
+    /// Days since it was last used, or learned if it never was
+    pub idle_days: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PersonalityCount {
+    /// "unknown" for insights not tied to a saved conversation
+    pub personality: String,
+    pub conversations: i64,
+    pub insights: i64,
+    pub facts: i64,
+    /// Preferences and corrections
+    pub user_statements: i64,
+    pub average_confidence: f64,
+}
+
+fn owners_json(owners: &[String]) -> Result<String, DatabaseError> {
+    Ok(serde_json::to_string(owners)?)
+}
+
+impl Database {
+    /// The `per_week` topics with most new insights in each of the last `weeks` weeks, newest week first.
+    pub async fn topics_by_week(&self, owners: &[String], weeks: usize, per_week: usize) -> Result<Vec<TopicWeek>, DatabaseError> {
+        let owners = owners_json(owners)?;
+        let rows = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "{} SELECT week, topic, n FROM (
+                         SELECT week, topic, COUNT(*) AS n,
+                                ROW_NUMBER() OVER (PARTITION BY week ORDER BY COUNT(*) DESC, topic) AS rank
+                         FROM insights WHERE week IS NOT NULL AND topic IS NOT NULL
+                         GROUP BY week, topic
+                     )
+                     WHERE rank <= ?3
+                       AND week IN (SELECT DISTINCT week FROM insights WHERE week IS NOT NULL ORDER BY week DESC LIMIT ?2)
+                     ORDER BY week DESC, n DESC, topic",
+                    INSIGHTS
+                ))?;
+                let rows = stmt.query_map(params![owners, weeks as i64, per_week as i64], |row| {
+                    Ok(TopicWeek { week: row.get(0)?, topic: row.get(1)?, insights: row.get(2)? })
+                })?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Insight counts by source and confidence band.
+    pub async fn insights_by_source(&self, owners: &[String]) -> Result<Vec<SourceBand>, DatabaseError> {
+        let owners = owners_json(owners)?;
+        let rows = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "{} SELECT COALESCE(source, 'unknown'),
+                            CASE WHEN confidence >= 0.9 THEN 3
+                                 WHEN confidence >= 0.7 THEN 2
+                                 WHEN confidence >= 0.5 THEN 1
+                                 ELSE 0 END AS band,
+                            COUNT(*)
+                     FROM insights GROUP BY 1, band ORDER BY 1, band",
+                    INSIGHTS
+                ))?;
+                let rows = stmt.query_map([owners], |row| {
+                    Ok(SourceBand { source: row.get(0)?, band: row.get(1)?, insights: row.get(2)? })
+                })?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// New insights per week, oldest first.
+    pub async fn insights_per_week(&self, owners: &[String]) -> Result<Vec<WeekCount>, DatabaseError> {
+        let owners = owners_json(owners)?;
+        let rows = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "{} SELECT week, COUNT(*) FROM insights WHERE week IS NOT NULL GROUP BY week ORDER BY week",
+                    INSIGHTS
+                ))?;
+                let rows = stmt.query_map([owners], |row| Ok(WeekCount { week: row.get(0)?, count: row.get(1)? }))?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// New knowledge base entries per week, oldest first.
+    pub async fn kb_entries_per_week(&self) -> Result<Vec<WeekCount>, DatabaseError> {
+        let rows = self.conn
+            .call(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT date(created_at, 'weekday 0', '-6 days') AS week, COUNT(*) FROM kb_entries
+                     WHERE week IS NOT NULL GROUP BY week ORDER BY week",
+                )?;
+                let rows = stmt.query_map([], |row| Ok(WeekCount { week: row.get(0)?, count: row.get(1)? }))?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Counts insights as used in a prompt, by owner and text.
+    pub async fn record_insight_use(&self, used: Vec<(String, String)>) -> Result<(), DatabaseError> {
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                {
+                    let mut stmt = tx.prepare(
+                        "INSERT INTO insight_usage (user_id, context, uses, last_used)
+                         VALUES (?1, lower(trim(?2)), 1, CURRENT_TIMESTAMP)
+                         ON CONFLICT (user_id, context) DO UPDATE SET uses = uses + 1, last_used = CURRENT_TIMESTAMP",
+                    )?;
+                    for (owner, context) in &used {
+                        stmt.execute(params![owner, context])?;
+                    }
+                }
+                tx.commit()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Extracted facts used most often in prompts, or with `stale_days`,
+    /// only the ones not used for that many days (or not learned again,
+    /// if never used), longest idle first.
+    pub async fn fact_usage(&self, owners: &[String], stale_days: Option<u32>, limit: usize) -> Result<Vec<FactUsage>, DatabaseError> {
+        let owners = owners_json(owners)?;
+        let (filter, order) = match stale_days {
+            Some(days) => (format!("idle_days > {}", days), "idle_days DESC, fact"),
+            None => ("uses > 0".to_string(), "uses DESC, last_used DESC"),
+        };
+        let rows = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "{}, usage AS (
+                         SELECT context, SUM(uses) AS uses, MAX(last_used) AS last_used FROM insight_usage
+                         WHERE user_id IN (SELECT value FROM json_each(?1))
+                         GROUP BY context
+                     ),
+                     facts AS (
+                         SELECT context AS fact, COUNT(*) AS mentions, MAX(confidence) AS confidence, MAX(ts) AS last_seen
+                         FROM insights WHERE source = 'fact' AND context IS NOT NULL
+                         GROUP BY lower(trim(context))
+                     )
+                     SELECT * FROM (
+                         SELECT f.fact, COALESCE(u.uses, 0) AS uses, u.last_used, f.mentions, f.confidence, f.last_seen,
+                                julianday('now') - julianday(COALESCE(u.last_used, f.last_seen)) AS idle_days
+                         FROM facts f LEFT JOIN usage u ON u.context = lower(trim(f.fact))
+                     )
+                     WHERE {}
+                     ORDER BY {} LIMIT ?2",
+                    INSIGHTS, filter, order
+                ))?;
+                let rows = stmt.query_map(params![owners, limit as i64], |row| {
+                    Ok(FactUsage {
+                        fact: row.get(0)?,
+                        uses: row.get(1)?,
+                        last_used: row.get(2)?,
+                        mentions: row.get(3)?,
+                        confidence: row.get(4)?,
+                        last_seen: row.get(5)?,
+                        idle_days: row.get::<_, Option<f64>>(6)?.unwrap_or_default(),
+                    })
+                })?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// What was learned while talking to each personality, most first.
+    pub async fn insights_by_personality(&self, owners: &[String]) -> Result<Vec<PersonalityCount>, DatabaseError> {
+        let owners = owners_json(owners)?;
+        let rows = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "{} SELECT COALESCE(c.personality, 'unknown'),
+                            COUNT(DISTINCT i.conversation_id),
+                            COUNT(*),
+                            SUM(i.source = 'fact'),
+                            SUM(i.source IN ('preference', 'correction')),
+                            AVG(i.confidence)
+                     FROM insights i LEFT JOIN conversations c ON c.id = i.conversation_id
+                     GROUP BY 1 ORDER BY 3 DESC, 1",
+                    INSIGHTS
+                ))?;
+                let rows = stmt.query_map([owners], |row| {
+                    Ok(PersonalityCount {
+                        personality: row.get(0)?,
+                        conversations: row.get(1)?,
+                        insights: row.get(2)?,
+                        facts: row.get(3)?,
+                        user_statements: row.get(4)?,
+                        average_confidence: row.get::<_, Option<f64>>(5)?.unwrap_or_default(),
+                    })
+                })?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(rows)
+    }
+}
@@ -0,0 +1,118 @@
+// src/database/conflicts.rs
+// Learned insights that contradict each other. Each conflict keeps both
+// texts, so whichever insight lost can still be looked up.
+use rusqlite::{params, OptionalExtension, Row};
+use super::{Database, DatabaseError};
+
+const CONFLICT_COLUMNS: &str =
+    "id, user_id, owner, topic, old_key, old_text, new_key, new_text, reason, status";
+
+/// A stored row of the insight_conflicts table.
+#[derive(Debug, Clone)]
+pub struct ConflictRecord {
+    pub id: i64,
+    /// Whose conversation the new insight came from
+    pub user_id: String,
+    /// Who the insights are stored under (the global user for shared facts)
+    pub owner: String,
+    pub topic: String,
+    pub old_key: String,
+    pub old_text: String,
+    pub new_key: String,
+    pub new_text: String,
+    /// Why they were found to conflict, e.g. "negation"
+    pub reason: String,
+    /// 'open', 'kept_old' or 'kept_new'
+    pub status: String,
+}
+
+fn conflict_from_row(row: &Row) -> rusqlite::Result<ConflictRecord> {
+    Ok(ConflictRecord {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        owner: row.get(2)?,
+        topic: row.get(3)?,
+        old_key: row.get(4)?,
+        old_text: row.get(5)?,
+        new_key: row.get(6)?,
+        new_text: row.get(7)?,
+        reason: row.get(8)?,
+        status: row.get(9)?,
+    })
+}
+
+impl Database {
+    /// Records a conflict, already resolved unless `status` is 'open', and
+    /// returns its id. The record's id is ignored.
+    pub async fn conflict_add(&self, record: ConflictRecord) -> Result<i64, DatabaseError> {
+        let id = self.conn
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO insight_conflicts
+                     (user_id, owner, topic, old_key, old_text, new_key, new_text, reason, status, resolved_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9,
+                             CASE WHEN ?9 = 'open' THEN NULL ELSE CURRENT_TIMESTAMP END)",
+                    params![
+                        record.user_id,
+                        record.owner,
+                        record.topic,
+                        record.old_key,
+                        record.old_text,
+                        record.new_key,
+                        record.new_text,
+                        record.reason,
+                        record.status
+                    ],
+                )?;
+                Ok(conn.last_insert_rowid())
+            })
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Conflicts of `user_id` still waiting for a decision, oldest first.
+    pub async fn conflicts_open(&self, user_id: String) -> Result<Vec<ConflictRecord>, DatabaseError> {
+        let records = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {} FROM insight_conflicts WHERE user_id = ?1 AND status = 'open' ORDER BY id",
+                    CONFLICT_COLUMNS
+                ))?;
+                let rows = stmt.query_map([user_id], conflict_from_row)?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(records)
+    }
+
+    pub async fn conflict_get(&self, id: i64) -> Result<Option<ConflictRecord>, DatabaseError> {
+        let record = self.conn
+            .call(move |conn| {
+                conn.query_row(
+                    &format!("SELECT {} FROM insight_conflicts WHERE id = ?1", CONFLICT_COLUMNS),
+                    [id],
+                    conflict_from_row,
+                )
+                .optional()
+            })
+            .await?;
+
+        Ok(record)
+    }
+
+    /// Marks an open conflict 'kept_old' or 'kept_new'. Returns false if it was no longer open.
+    pub async fn conflict_resolve(&self, id: i64, status: &'static str) -> Result<bool, DatabaseError> {
+        let updated = self.conn
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE insight_conflicts SET status = ?2, resolved_at = CURRENT_TIMESTAMP WHERE id = ?1 AND status = 'open'",
+                    params![id, status],
+                )
+            })
+            .await?;
+
+        Ok(updated > 0)
+    }
+}
@@ -0,0 +1,94 @@
+// src/database/feedback.rs
+// What users thought of answers: thumbs up or down, corrections and
+// regenerations, linked to the conversation row of the answer.
+use rusqlite::{params, Connection, OptionalExtension};
+use super::{Database, DatabaseError};
+
+impl Database {
+    /// Records feedback on the answer of a conversation and, in the same
+    /// transaction, sets the confidence of the insights learned in it to
+    /// their confidence before any feedback, moved by `net` of the kinds of
+    /// all feedback on it, within 0..=1. 'up' and 'down' are one vote per
+    /// user that replaces their earlier one; repeating it changes nothing
+    /// and returns None. Corrections are the user's own word and keep
+    /// theirs. Returns the owner and JSON of each insight that was set.
+    pub async fn feedback_apply<F>(
+        &self,
+        conversation_id: i64,
+        user_id: String,
+        kind: &'static str,
+        correction: Option<String>,
+        net: F,
+    ) -> Result<Option<Vec<(String, String)>>, DatabaseError>
+    where
+        F: Fn(&[String]) -> f32 + Send + 'static,
+    {
+        let updated = self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                if kind == "up" || kind == "down" {
+                    let previous: Option<String> = tx
+                        .query_row(
+                            "SELECT kind FROM feedback WHERE conversation_id = ?1 AND user_id = ?2 AND kind IN ('up', 'down')",
+                            params![conversation_id, user_id],
+                            |row| row.get(0),
+                        )
+                        .optional()?;
+                    if previous.as_deref() == Some(kind) {
+                        return Ok(None);
+                    }
+                    tx.execute(
+                        "INSERT INTO feedback (conversation_id, user_id, kind) VALUES (?1, ?2, ?3)
+                         ON CONFLICT (conversation_id, user_id) WHERE kind IN ('up', 'down')
+                         DO UPDATE SET kind = excluded.kind, created_at = CURRENT_TIMESTAMP",
+                        params![conversation_id, user_id, kind],
+                    )?;
+                } else {
+                    tx.execute(
+                        "INSERT INTO feedback (conversation_id, user_id, kind, correction) VALUES (?1, ?2, ?3, ?4)",
+                        params![conversation_id, user_id, kind, correction],
+                    )?;
+                }
+
+                let kinds = feedback_kinds(&tx, conversation_id)?;
+                let change = net(&kinds);
+                let updated = {
+                    let mut stmt = tx.prepare(
+                        "UPDATE knowledge_base
+                         SET value = json_set(value,
+                             '$.base_confidence', coalesce(json_extract(value, '$.base_confidence'), json_extract(value, '$.confidence')),
+                             '$.confidence', max(0.0, min(1.0,
+                                 coalesce(json_extract(value, '$.base_confidence'), json_extract(value, '$.confidence')) + ?2)))
+                         WHERE substr(key, 1, 8) = 'insight:'
+                           AND json_valid(value)
+                           AND json_extract(value, '$.source') != 'correction'
+                           AND json_extract(value, '$.provenance.conversation_id') = ?1
+                         RETURNING user_id, value",
+                    )?;
+                    let rows = stmt.query_map(params![conversation_id, change as f64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                    rows.collect::<rusqlite::Result<Vec<(String, String)>>>()?
+                };
+                tx.commit()?;
+                Ok(Some(updated))
+            })
+            .await?;
+
+        Ok(updated)
+    }
+
+    /// Kinds of feedback on one conversation ('up', 'down', 'correction'
+    /// or 'regenerate'), oldest first.
+    pub async fn feedback_for(&self, conversation_id: i64) -> Result<Vec<String>, DatabaseError> {
+        let kinds = self.conn
+            .call(move |conn| feedback_kinds(conn, conversation_id))
+            .await?;
+
+        Ok(kinds)
+    }
+}
+
+fn feedback_kinds(conn: &Connection, conversation_id: i64) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT kind FROM feedback WHERE conversation_id = ?1 ORDER BY id")?;
+    let rows = stmt.query_map([conversation_id], |row| row.get(0))?;
+    rows.collect()
+}
@@ -0,0 +1,380 @@
+// src/database/kb.rs
+// Storage for knowledge base entries. Full-text search goes through the
+// kb_fts table, which triggers keep in step with kb_entries.
+use std::collections::HashSet;
+use chrono::{DateTime, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, OptionalExtension, Row, Transaction};
+use crate::knowledge_base::knowledge_base::KnowledgeEntry;
+use super::{Database, DatabaseError};
+
+const KB_COLUMNS: &str = "id, keywords, content, source, created_at, updated_at, tags, document, provenance";
+
+// An entry with its JSON columns already encoded
+struct EntryRow {
+    id: String,
+    keywords: String,
+    content: String,
+    source: String,
+    created_at: String,
+    updated_at: String,
+    tags: String,
+    document: Option<String>,
+    provenance: Option<String>,
+}
+
+impl EntryRow {
+    fn new(entry: &KnowledgeEntry) -> Result<Self, DatabaseError> {
+        Ok(Self {
+            id: entry.id.clone(),
+            keywords: serde_json::to_string(&entry.keywords)?,
+            content: entry.content.clone(),
+            source: entry.source.clone(),
+            created_at: entry.created_at.to_rfc3339(),
+            updated_at: entry.updated_at.to_rfc3339(),
+            tags: serde_json::to_string(&entry.tags)?,
+            document: entry.document.as_ref().map(serde_json::to_string).transpose()?,
+            provenance: entry.provenance.as_ref().map(serde_json::to_string).transpose()?,
+        })
+    }
+
+    fn insert(&self, tx: &Transaction) -> rusqlite::Result<()> {
+        tx.execute(
+            &format!("INSERT INTO kb_entries ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)", KB_COLUMNS),
+            params![
+                self.id,
+                self.keywords,
+                self.content,
+                self.source,
+                self.created_at,
+                self.updated_at,
+                self.tags,
+                self.document,
+                self.provenance
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+// Reads the KB_COLUMNS of a row, starting at column `first`
+fn entry_from_row(row: &Row, first: usize) -> rusqlite::Result<KnowledgeEntry> {
+    fn json<T: serde::de::DeserializeOwned>(row: &Row, i: usize) -> rusqlite::Result<T> {
+        let text: String = row.get(i)?;
+        serde_json::from_str(&text).map_err(|e| rusqlite::Error::FromSqlConversionFailure(i, Type::Text, Box::new(e)))
+    }
+    fn time(row: &Row, i: usize) -> rusqlite::Result<DateTime<Utc>> {
+        let text: String = row.get(i)?;
+        DateTime::parse_from_rfc3339(&text)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(i, Type::Text, Box::new(e)))
+    }
+
+    fn optional_json<T: serde::de::DeserializeOwned>(row: &Row, i: usize) -> rusqlite::Result<Option<T>> {
+        let text: Option<String> = row.get(i)?;
+        text.map(|t| serde_json::from_str(&t))
+            .transpose()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(i, Type::Text, Box::new(e)))
+    }
+
+    Ok(KnowledgeEntry {
+        id: row.get(first)?,
+        keywords: json(row, first + 1)?,
+        content: row.get(first + 2)?,
+        source: row.get(first + 3)?,
+        created_at: time(row, first + 4)?,
+        updated_at: time(row, first + 5)?,
+        tags: json(row, first + 6)?,
+        document: optional_json(row, first + 7)?,
+        provenance: optional_json(row, first + 8)?,
+    })
+}
+
+// Gives entries without a usable id a fresh "kb-N": empty ids, ids already
+// in the table and ids repeated within the batch
+fn assign_ids(tx: &Transaction, entries: &mut [KnowledgeEntry]) -> rusqlite::Result<()> {
+    let mut taken = HashSet::new();
+    let mut needs_id = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let exists = !entry.id.is_empty()
+            && tx
+                .query_row("SELECT 1 FROM kb_entries WHERE id = ?1", [&entry.id], |_| Ok(()))
+                .optional()?
+                .is_some();
+        if entry.id.is_empty() || exists || !taken.insert(entry.id.clone()) {
+            needs_id.push(i);
+        }
+    }
+    if needs_id.is_empty() {
+        return Ok(());
+    }
+
+    let number = |id: &str| id.strip_prefix("kb-").and_then(|n| n.parse::<i64>().ok());
+    let stored: Option<i64> = tx.query_row(
+        "SELECT MAX(CAST(SUBSTR(id, 4) AS INTEGER)) FROM kb_entries WHERE id GLOB 'kb-[0-9]*'",
+        [],
+        |row| row.get(0),
+    )?;
+    // Highest number ever handed out, so ids of deleted entries are not reused
+    let issued: Option<i64> = tx
+        .query_row("SELECT CAST(value AS INTEGER) FROM metadata WHERE key = 'kb_last_id'", [], |row| row.get(0))
+        .optional()?;
+    let mut next = taken.iter().filter_map(|id| number(id)).chain(stored).chain(issued).max().unwrap_or(0);
+    for i in needs_id {
+        next += 1;
+        entries[i].id = format!("kb-{}", next);
+    }
+    tx.execute("INSERT OR REPLACE INTO metadata (key, value) VALUES ('kb_last_id', ?1)", [next.to_string()])?;
+    Ok(())
+}
+
+impl Database {
+    /// Every knowledge base entry, oldest first.
+    pub async fn kb_entries(&self) -> Result<Vec<KnowledgeEntry>, DatabaseError> {
+        let entries = self.conn
+            .call(|conn| {
+                let mut stmt = conn.prepare(&format!("SELECT {} FROM kb_entries ORDER BY seq", KB_COLUMNS))?;
+                let rows = stmt.query_map([], |row| entry_from_row(row, 0))?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(entries)
+    }
+
+    pub async fn kb_get(&self, id: String) -> Result<Option<KnowledgeEntry>, DatabaseError> {
+        let entry = self.conn
+            .call(move |conn| {
+                conn.query_row(
+                    &format!("SELECT {} FROM kb_entries WHERE id = ?1", KB_COLUMNS),
+                    [&id],
+                    |row| entry_from_row(row, 0),
+                )
+                .optional()
+            })
+            .await?;
+
+        Ok(entry)
+    }
+
+    /// Full-text search with an FTS5 `MATCH` expression, best match first.
+    /// Scores are BM25 with keywords weighted double; higher is better.
+    pub async fn kb_search(&self, query: String, limit: usize) -> Result<Vec<(KnowledgeEntry, f32)>, DatabaseError> {
+        let hits = self.conn
+            .call(move |conn| {
+                let columns = KB_COLUMNS.split(", ").map(|c| format!("e.{}", c)).collect::<Vec<_>>().join(", ");
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT -bm25(kb_fts, 2.0, 1.0, 1.0) AS score, {}
+                     FROM kb_fts JOIN kb_entries e ON e.seq = kb_fts.rowid
+                     WHERE kb_fts MATCH ?1
+                     ORDER BY score DESC, e.seq
+                     LIMIT ?2",
+                    columns
+                ))?;
+                let rows = stmt.query_map(params![query, limit.min(i64::MAX as usize) as i64], |row| {
+                    Ok((entry_from_row(row, 1)?, row.get::<_, f64>(0)? as f32))
+                })?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(hits)
+    }
+
+    /// Adds entries in one transaction and returns them with their ids.
+    /// Ids that are missing or already used are replaced by fresh ones.
+    pub async fn kb_insert(&self, mut entries: Vec<KnowledgeEntry>) -> Result<Vec<KnowledgeEntry>, DatabaseError> {
+        let entries = self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                assign_ids(&tx, &mut entries)?;
+                for entry in &entries {
+                    EntryRow::new(entry)
+                        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
+                        .insert(&tx)?;
+                }
+                tx.commit()?;
+                Ok(entries)
+            })
+            .await?;
+
+        Ok(entries)
+    }
+
+    /// Rewrites the entry with the same id. Returns false if there is none.
+    pub async fn kb_update(&self, entry: KnowledgeEntry) -> Result<bool, DatabaseError> {
+        let row = EntryRow::new(&entry)?;
+        let updated = self.conn
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE kb_entries
+                     SET keywords = ?2, content = ?3, source = ?4, updated_at = ?5, tags = ?6, document = ?7, provenance = ?8
+                     WHERE id = ?1",
+                    params![row.id, row.keywords, row.content, row.source, row.updated_at, row.tags, row.document, row.provenance],
+                )
+            })
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    pub async fn kb_delete(&self, id: String) -> Result<bool, DatabaseError> {
+        let deleted = self.conn
+            .call(move |conn| conn.execute("DELETE FROM kb_entries WHERE id = ?1", [&id]))
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Replaces the whole knowledge base in one transaction.
+    pub async fn kb_replace_all(&self, mut entries: Vec<KnowledgeEntry>) -> Result<(), DatabaseError> {
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute("DELETE FROM kb_entries", [])?;
+                assign_ids(&tx, &mut entries)?;
+                for entry in &entries {
+                    EntryRow::new(entry)
+                        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
+                        .insert(&tx)?;
+                }
+                tx.commit()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Deletes the entries ingested from any of `paths` and adds `entries`,
+    /// in one transaction. Returns how many entries were deleted.
+    pub async fn kb_replace_documents(&self, paths: Vec<String>, mut entries: Vec<KnowledgeEntry>) -> Result<usize, DatabaseError> {
+        let deleted = self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let mut deleted = 0;
+                for path in &paths {
+                    deleted += tx.execute(
+                        "DELETE FROM kb_entries WHERE json_extract(document, '$.path') = ?1",
+                        [path],
+                    )?;
+                }
+                assign_ids(&tx, &mut entries)?;
+                for entry in &entries {
+                    EntryRow::new(entry)
+                        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
+                        .insert(&tx)?;
+                }
+                tx.commit()?;
+                Ok(deleted)
+            })
+            .await?;
+
+        Ok(deleted)
+    }
+
+    /// Changes whenever another connection (such as another process)
+    /// commits to the database, but not with this connection's own writes.
+    pub async fn data_version(&self) -> Result<i64, DatabaseError> {
+        let version = self.conn
+            .call(|conn| conn.query_row("PRAGMA data_version", [], |row| row.get(0)))
+            .await?;
+
+        Ok(version)
+    }
+
+    pub async fn get_metadata(&self, key: String) -> Result<Option<String>, DatabaseError> {
+        let value = self.conn
+            .call(move |conn| {
+                conn.query_row("SELECT value FROM metadata WHERE key = ?1", [&key], |row| row.get(0))
+                    .optional()
+            })
+            .await?;
+
+        Ok(value)
+    }
+
+    pub async fn set_metadata(&self, key: String, value: String) -> Result<(), DatabaseError> {
+        self.conn
+            .call(move |conn| {
+                conn.execute("INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)", [&key, &value])
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Entries with no vector from `model` yet, with their row numbers.
+    pub async fn kb_missing_embeddings(&self, model: String) -> Result<Vec<(i64, KnowledgeEntry)>, DatabaseError> {
+        let missing = self.conn
+            .call(move |conn| {
+                let columns = KB_COLUMNS.split(", ").map(|c| format!("e.{}", c)).collect::<Vec<_>>().join(", ");
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT e.seq, {}
+                     FROM kb_entries e LEFT JOIN kb_embeddings v ON v.seq = e.seq
+                     WHERE v.seq IS NULL OR v.model != ?1",
+                    columns
+                ))?;
+                let rows = stmt.query_map([&model], |row| Ok((row.get(0)?, entry_from_row(row, 1)?)))?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(missing)
+    }
+
+    pub async fn kb_store_embeddings(&self, model: String, vectors: Vec<(i64, Vec<u8>)>) -> Result<(), DatabaseError> {
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                {
+                    let mut stmt = tx.prepare("INSERT OR REPLACE INTO kb_embeddings (seq, model, vector) VALUES (?1, ?2, ?3)")?;
+                    for (seq, vector) in &vectors {
+                        stmt.execute(params![seq, model, vector])?;
+                    }
+                }
+                tx.commit()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Every stored vector from `model`, by entry id.
+    pub async fn kb_embeddings(&self, model: String) -> Result<Vec<(String, Vec<u8>)>, DatabaseError> {
+        let vectors = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT e.id, v.vector FROM kb_embeddings v JOIN kb_entries e ON e.seq = v.seq WHERE v.model = ?1"
+                )?;
+                let rows = stmt.query_map([&model], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(vectors)
+    }
+
+    /// Rewrites `updates` and deletes `deletes` in one transaction.
+    pub async fn kb_apply(&self, updates: Vec<KnowledgeEntry>, deletes: Vec<String>) -> Result<(), DatabaseError> {
+        let rows = updates.iter().map(EntryRow::new).collect::<Result<Vec<_>, _>>()?;
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                for row in &rows {
+                    tx.execute(
+                        "UPDATE kb_entries
+                         SET keywords = ?2, content = ?3, source = ?4, updated_at = ?5, tags = ?6, document = ?7, provenance = ?8
+                         WHERE id = ?1",
+                        params![row.id, row.keywords, row.content, row.source, row.updated_at, row.tags, row.document, row.provenance],
+                    )?;
+                }
+                for id in &deletes {
+                    tx.execute("DELETE FROM kb_entries WHERE id = ?1", [id])?;
+                }
+                tx.commit()
+            })
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+// src/database/learned.rs
+// What a batch of exchanges taught, written in one transaction: insights,
+// profile updates and the review queue. Each exchange gets a savepoint,
+// so one that fails is rolled back alone and the rest are still stored.
+use rusqlite::{params, Connection};
+use crate::knowledge_base::knowledge_base::Provenance;
+use crate::learning::UserProfile;
+use super::pending::{corroborate_pending, insert_pending, pending_vectors, store_pending_vector};
+use super::profile::edit_profile;
+use super::{Database, DatabaseError};
+
+/// Content of an exchange for the review queue.
+#[derive(Debug, Clone)]
+pub struct QueuedContent {
+    pub topics: Vec<String>,
+    pub content: String,
+    /// Vector of the content, to match it against the queue
+    pub vector: Vec<u8>,
+    pub provenance: Provenance,
+}
+
+/// What one exchange taught, ready to store.
+#[derive(Debug, Clone)]
+pub struct LearnedTurn {
+    pub user_id: String,
+    pub conversation_id: Option<i64>,
+    /// Insight keys and their JSON
+    pub insights: Vec<(String, String)>,
+    pub profile: UserProfile,
+    /// Whose review queue to look in for the same content; None for everyone's
+    pub queue_owner: Option<String>,
+    /// Vector of what the user said themselves, which backs queued items
+    /// saying the same
+    pub stated: Option<Vec<u8>>,
+    pub queue: Option<QueuedContent>,
+}
+
+impl Database {
+    /// Stores a batch of learned turns. `similar` picks, from the vectors
+    /// of queued items by `model`, the item saying the same as a vector,
+    /// which is then corroborated instead of queued again. For each turn,
+    /// returns the queue items it backed with their corroborations, or why
+    /// it failed.
+    pub async fn save_learned<F>(
+        &self,
+        turns: Vec<LearnedTurn>,
+        model: String,
+        similar: F,
+    ) -> Result<Vec<Result<Vec<(i64, u32)>, DatabaseError>>, DatabaseError>
+    where
+        F: Fn(&[u8], &[(i64, Vec<u8>)]) -> Option<i64> + Send + 'static,
+    {
+        let results = self.conn
+            .call(move |conn| {
+                let mut tx = conn.transaction()?;
+                let mut results = Vec::with_capacity(turns.len());
+                for turn in &turns {
+                    // Dropping the savepoint without committing rolls the turn back
+                    let savepoint = tx.savepoint()?;
+                    match save_turn(&savepoint, turn, &model, &similar) {
+                        Ok(queued) => {
+                            savepoint.commit()?;
+                            results.push(Ok(queued));
+                        }
+                        Err(e) => results.push(Err(e)),
+                    }
+                }
+                tx.commit()?;
+                Ok(results)
+            })
+            .await?;
+
+        Ok(results
+            .into_iter()
+            .map(|result| result.map_err(|e| DatabaseError::from(tokio_rusqlite::Error::Rusqlite(e))))
+            .collect())
+    }
+}
+
+fn save_turn<F>(conn: &Connection, turn: &LearnedTurn, model: &str, similar: &F) -> rusqlite::Result<Vec<(i64, u32)>>
+where
+    F: Fn(&[u8], &[(i64, Vec<u8>)]) -> Option<i64>,
+{
+    {
+        let mut stmt = conn.prepare("INSERT OR REPLACE INTO knowledge_base (user_id, key, value) VALUES (?1, ?2, ?3)")?;
+        for (key, value) in &turn.insights {
+            stmt.execute(params![turn.user_id, key, value])?;
+        }
+    }
+
+    if turn.profile != UserProfile::default() {
+        let update = turn.profile.clone();
+        edit_profile(conn, &turn.user_id, |profile| profile.merge(update))?;
+    }
+
+    let mut backed = Vec::new();
+    if turn.stated.is_none() && turn.queue.is_none() {
+        return Ok(backed);
+    }
+    let pending = pending_vectors(conn, turn.queue_owner.as_deref(), model)?;
+
+    // Checked before this turn's answer is queued, so it can't back itself
+    if let Some(id) = turn.stated.as_ref().and_then(|stated| similar(stated, &pending)) {
+        backed.push((id, corroborate_pending(conn, id, turn.conversation_id, &turn.user_id, true)?));
+    }
+
+    if let Some(queued) = &turn.queue {
+        match similar(&queued.vector, &pending) {
+            Some(id) => backed.push((id, corroborate_pending(conn, id, turn.conversation_id, &turn.user_id, false)?)),
+            None => {
+                let topics = serde_json::to_string(&queued.topics).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                let provenance = serde_json::to_string(&queued.provenance).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                let id = insert_pending(conn, &turn.user_id, &topics, &queued.content, Some(&provenance), turn.conversation_id)?;
+                store_pending_vector(conn, id, model, &queued.vector)?;
+                backed.push((id, 1));
+            }
+        }
+    }
+    Ok(backed)
+}
@@ -1,8 +1,230 @@
 use tokio_rusqlite::Connection;
 use std::path::Path;
-use log::{info, error};
+use log::info;
 use thiserror::Error;
+use rusqlite::OptionalExtension;
+use std::collections::HashMap;
 use std::sync::Arc;
+use crate::crypto::{self, Cipher};
+
+mod analytics;
+mod conflicts;
+mod feedback;
+mod kb;
+mod learned;
+mod pending;
+mod portable;
+mod profile;
+
+pub use analytics::{FactUsage, PersonalityCount, SourceBand, TopicWeek};
+pub use conflicts::ConflictRecord;
+pub use learned::{LearnedTurn, QueuedContent};
+pub use pending::PendingRecord;
+pub use portable::{remap_provenance, ConflictRow, FeedbackRow, LearningRecords, PendingRow, UsageRow};
+
+/// User id used when no identity is supplied.
+pub const DEFAULT_USER: &str = "default";
+/// Pseudo user owning knowledge shared by every user.
+pub const GLOBAL_USER: &str = "global";
+
+// Schema migrations, applied in order on top of the original tables.
+// `PRAGMA user_version` records how many of them have already run.
+const MIGRATIONS: &[&str] = &[
+    // 1: per-user conversations and knowledge
+    "ALTER TABLE conversations ADD COLUMN user_id TEXT NOT NULL DEFAULT 'default';
+    CREATE INDEX IF NOT EXISTS idx_conversations_user ON conversations (user_id, timestamp);
+    CREATE TABLE knowledge_base_new (
+        id INTEGER PRIMARY KEY,
+        user_id TEXT NOT NULL DEFAULT 'default',
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+        UNIQUE (user_id, key)
+    );
+    INSERT INTO knowledge_base_new (id, key, value, timestamp)
+        SELECT id, key, value, timestamp FROM knowledge_base;
+    DROP TABLE knowledge_base;
+    ALTER TABLE knowledge_base_new RENAME TO knowledge_base;",
+    // 2: short-term memory partition (character) each conversation belongs to
+    "ALTER TABLE conversations ADD COLUMN memory_partition TEXT NOT NULL DEFAULT '';
+    CREATE INDEX IF NOT EXISTS idx_conversations_partition ON conversations (user_id, memory_partition, timestamp);",
+    // 3: knowledge base entries with a full-text index and a keyword lookup
+    // table, both maintained by triggers
+    "CREATE TABLE kb_entries (
+        seq INTEGER PRIMARY KEY,
+        id TEXT UNIQUE NOT NULL,
+        keywords TEXT NOT NULL DEFAULT '[]',
+        content TEXT NOT NULL,
+        source TEXT NOT NULL DEFAULT '',
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        tags TEXT NOT NULL DEFAULT '[]',
+        document TEXT
+    );
+    CREATE INDEX idx_kb_entries_document ON kb_entries (json_extract(document, '$.path'));
+    CREATE TABLE kb_keywords (
+        keyword TEXT NOT NULL COLLATE NOCASE,
+        seq INTEGER NOT NULL,
+        PRIMARY KEY (keyword, seq)
+    );
+    CREATE VIRTUAL TABLE kb_fts USING fts5(
+        keywords, content, tags,
+        content = 'kb_entries', content_rowid = 'seq', tokenize = 'porter unicode61'
+    );
+    CREATE TRIGGER kb_entries_insert AFTER INSERT ON kb_entries BEGIN
+        INSERT INTO kb_fts (rowid, keywords, content, tags) VALUES (new.seq, new.keywords, new.content, new.tags);
+        INSERT OR IGNORE INTO kb_keywords (keyword, seq) SELECT value, new.seq FROM json_each(new.keywords);
+    END;
+    CREATE TRIGGER kb_entries_delete AFTER DELETE ON kb_entries BEGIN
+        INSERT INTO kb_fts (kb_fts, rowid, keywords, content, tags) VALUES ('delete', old.seq, old.keywords, old.content, old.tags);
+        DELETE FROM kb_keywords WHERE seq = old.seq;
+    END;
+    CREATE TRIGGER kb_entries_update AFTER UPDATE ON kb_entries BEGIN
+        INSERT INTO kb_fts (kb_fts, rowid, keywords, content, tags) VALUES ('delete', old.seq, old.keywords, old.content, old.tags);
+        DELETE FROM kb_keywords WHERE seq = old.seq;
+        INSERT INTO kb_fts (rowid, keywords, content, tags) VALUES (new.seq, new.keywords, new.content, new.tags);
+        INSERT OR IGNORE INTO kb_keywords (keyword, seq) SELECT value, new.seq FROM json_each(new.keywords);
+    END;
+    CREATE TABLE metadata (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    // 4: knowledge base embeddings; dropped when their entry changes so
+    // they get recomputed
+    "CREATE TABLE kb_embeddings (
+        seq INTEGER PRIMARY KEY,
+        model TEXT NOT NULL,
+        vector BLOB NOT NULL
+    );
+    CREATE TRIGGER kb_embeddings_delete AFTER DELETE ON kb_entries BEGIN
+        DELETE FROM kb_embeddings WHERE seq = old.seq;
+    END;
+    CREATE TRIGGER kb_embeddings_update AFTER UPDATE OF keywords, content ON kb_entries BEGIN
+        DELETE FROM kb_embeddings WHERE seq = old.seq;
+    END;",
+    // 5: where each knowledge base entry came from; ingested entries
+    // already know their file
+    "ALTER TABLE kb_entries ADD COLUMN provenance TEXT;
+    UPDATE kb_entries
+        SET provenance = json_object('kind', 'file', 'path', json_extract(document, '$.path'), 'line', NULL)
+        WHERE document IS NOT NULL;",
+    // 6: learned content waiting for review before it reaches the
+    // knowledge base
+    "CREATE TABLE kb_pending (
+        id INTEGER PRIMARY KEY,
+        user_id TEXT NOT NULL,
+        topics TEXT NOT NULL DEFAULT '[]',
+        content TEXT NOT NULL,
+        provenance TEXT,
+        corroborations INTEGER NOT NULL DEFAULT 1,
+        status TEXT NOT NULL DEFAULT 'pending',
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+    CREATE INDEX idx_kb_pending_status ON kb_pending (status, user_id);",
+    // 7: user feedback on answers
+    "CREATE TABLE feedback (
+        id INTEGER PRIMARY KEY,
+        conversation_id INTEGER NOT NULL,
+        user_id TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        correction TEXT,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+    CREATE INDEX idx_feedback_conversation ON feedback (conversation_id);",
+    // 8: learned insights that contradict each other, and which one was kept
+    "CREATE TABLE insight_conflicts (
+        id INTEGER PRIMARY KEY,
+        user_id TEXT NOT NULL,
+        owner TEXT NOT NULL,
+        topic TEXT NOT NULL,
+        old_key TEXT NOT NULL,
+        old_text TEXT NOT NULL,
+        new_key TEXT NOT NULL,
+        new_text TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        status TEXT NOT NULL DEFAULT 'open',
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        resolved_at DATETIME
+    );
+    CREATE INDEX idx_insight_conflicts_user ON insight_conflicts (user_id, status);",
+    // 9: what was learned about each user
+    "CREATE TABLE user_profiles (
+        user_id TEXT PRIMARY KEY,
+        profile TEXT NOT NULL,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );",
+    // 10: the conversations backing each queued item, so one conversation
+    // saying the same thing twice counts once
+    "CREATE TABLE kb_pending_sources (
+        pending_id INTEGER NOT NULL,
+        conversation_id INTEGER NOT NULL,
+        user_id TEXT NOT NULL,
+        PRIMARY KEY (pending_id, conversation_id)
+    );
+    INSERT INTO kb_pending_sources (pending_id, conversation_id, user_id)
+        SELECT id, json_extract(provenance, '$.conversation_id'), user_id FROM kb_pending
+        WHERE json_extract(provenance, '$.conversation_id') IS NOT NULL;",
+    // 11: one up or down vote per user and answer, the latest one
+    "DELETE FROM feedback WHERE kind IN ('up', 'down') AND id NOT IN (
+        SELECT MAX(id) FROM feedback WHERE kind IN ('up', 'down') GROUP BY conversation_id, user_id
+    );
+    CREATE UNIQUE INDEX idx_feedback_vote ON feedback (conversation_id, user_id) WHERE kind IN ('up', 'down');",
+    // 12: how often each insight was put in a prompt, by its text
+    "CREATE TABLE insight_usage (
+        user_id TEXT NOT NULL,
+        context TEXT NOT NULL,
+        uses INTEGER NOT NULL DEFAULT 0,
+        last_used TIMESTAMP,
+        PRIMARY KEY (user_id, context)
+    );",
+    // 13: kb_keywords lost its last reader to the FTS5 index; the entry
+    // triggers keep only the index up to date
+    "DROP TRIGGER kb_entries_insert;
+    DROP TRIGGER kb_entries_delete;
+    DROP TRIGGER kb_entries_update;
+    DROP TABLE kb_keywords;
+    CREATE TRIGGER kb_entries_insert AFTER INSERT ON kb_entries BEGIN
+        INSERT INTO kb_fts (rowid, keywords, content, tags) VALUES (new.seq, new.keywords, new.content, new.tags);
+    END;
+    CREATE TRIGGER kb_entries_delete AFTER DELETE ON kb_entries BEGIN
+        INSERT INTO kb_fts (kb_fts, rowid, keywords, content, tags) VALUES ('delete', old.seq, old.keywords, old.content, old.tags);
+    END;
+    CREATE TRIGGER kb_entries_update AFTER UPDATE ON kb_entries BEGIN
+        INSERT INTO kb_fts (kb_fts, rowid, keywords, content, tags) VALUES ('delete', old.seq, old.keywords, old.content, old.tags);
+        INSERT INTO kb_fts (rowid, keywords, content, tags) VALUES (new.seq, new.keywords, new.content, new.tags);
+    END;",
+    // 14: sources of queued items say whether the user stated it
+    // themselves, and items are recounted by user, since every exchange
+    // is a conversation of its own
+    "CREATE TABLE kb_pending_sources_new (
+        pending_id INTEGER NOT NULL,
+        conversation_id INTEGER NOT NULL,
+        user_id TEXT NOT NULL,
+        stated INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (pending_id, conversation_id, stated)
+    );
+    INSERT INTO kb_pending_sources_new (pending_id, conversation_id, user_id)
+        SELECT pending_id, conversation_id, user_id FROM kb_pending_sources;
+    DROP TABLE kb_pending_sources;
+    ALTER TABLE kb_pending_sources_new RENAME TO kb_pending_sources;
+    UPDATE kb_pending SET corroborations = max(1, (
+        SELECT COUNT(DISTINCT user_id) FROM kb_pending_sources WHERE pending_id = kb_pending.id
+    )) WHERE status = 'pending';",
+    // 15: vectors of queued items, so new content is matched against the
+    // queue without embedding all of it again; only kept while pending
+    "CREATE TABLE kb_pending_embeddings (
+        pending_id INTEGER PRIMARY KEY,
+        model TEXT NOT NULL,
+        vector BLOB NOT NULL
+    );
+    CREATE TRIGGER kb_pending_embeddings_delete AFTER DELETE ON kb_pending BEGIN
+        DELETE FROM kb_pending_embeddings WHERE pending_id = old.id;
+    END;
+    CREATE TRIGGER kb_pending_embeddings_done AFTER UPDATE OF status ON kb_pending WHEN new.status != 'pending' BEGIN
+        DELETE FROM kb_pending_embeddings WHERE pending_id = old.id;
+    END;",
+];
 
 #[derive(Error, Debug)]
 pub enum DatabaseError {
@@ -10,6 +232,29 @@ pub enum DatabaseError {
     Sqlite(#[from] tokio_rusqlite::Error),
     #[error("Database connection error: {0}")]
     Connection(String),
+    #[error("Database encryption error: {0}")]
+    Encryption(String),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// A stored row of the conversations table.
+#[derive(Debug, Clone)]
+pub struct ConversationRecord {
+    pub id: i64,
+    pub timestamp: String,
+    pub user_input: String,
+    pub ai_response: String,
+    pub personality: String,
+    pub memory_partition: String,
+}
+
+/// A stored row of the knowledge_base table.
+#[derive(Debug, Clone)]
+pub struct KnowledgeRecord {
+    pub key: String,
+    pub value: String,
+    pub timestamp: String,
 }
 
 #[derive(Clone)]
@@ -24,10 +269,82 @@ impl Database {
             .map_err(|e| DatabaseError::Connection(e.to_string()))?;
         
         let db = Self { conn: Arc::new(conn) };
+        if let Some(cipher) = crypto::cipher() {
+            db.apply_key(cipher).await?;
+        }
         db.initialize().await?;
         Ok(db)
     }
 
+    // Unlocks a SQLCipher database; must run before any other statement.
+    // Without SQLCipher the key is refused rather than leaving the
+    // database in plaintext behind the user's back.
+    async fn apply_key(&self, cipher: &Cipher) -> Result<(), DatabaseError> {
+        if !cfg!(feature = "sqlcipher") {
+            return Err(DatabaseError::Encryption(
+                "an encryption key is set but this build has no 'sqlcipher' feature, so the database cannot be encrypted; \
+                 build with --features sqlcipher, or decrypt with `crypto rotate --plaintext` and unset the key"
+                    .to_string(),
+            ));
+        }
+
+        let pragma = format!("PRAGMA key = {};", cipher.sqlcipher_key());
+        self.conn
+            .call(move |conn| {
+                conn.execute_batch(&pragma)?;
+                // Fails here rather than later if the key is wrong
+                conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))
+            })
+            .await
+            .map_err(|e| DatabaseError::Encryption(format!("cannot open database with this key: {}", e)))?;
+        Ok(())
+    }
+
+    /// Re-encrypts the database file at `path` from `old` to `new` key,
+    /// where `None` means plaintext. Needs the 'sqlcipher' feature.
+    pub fn rotate_key(path: &str, old: Option<&Cipher>, new: Option<&Cipher>) -> Result<(), DatabaseError> {
+        if !cfg!(feature = "sqlcipher") {
+            return Err(DatabaseError::Encryption("built without the 'sqlcipher' feature".to_string()));
+        }
+        if !Path::new(path).exists() || (old.is_none() && new.is_none()) {
+            return Ok(());
+        }
+
+        let to_encryption = |e: rusqlite::Error| DatabaseError::Encryption(e.to_string());
+        let conn = rusqlite::Connection::open(path).map_err(to_encryption)?;
+        if let Some(old) = old {
+            conn.execute_batch(&format!("PRAGMA key = {};", old.sqlcipher_key())).map_err(to_encryption)?;
+        }
+
+        match (old, new) {
+            (Some(_), Some(new)) => {
+                conn.execute_batch(&format!("PRAGMA rekey = {};", new.sqlcipher_key())).map_err(to_encryption)?;
+            }
+            _ => {
+                // Switching between plaintext and encrypted needs a full copy
+                let tmp = format!("{}.rotate", path);
+                let key = new.map(|c| c.sqlcipher_key()).unwrap_or_else(|| "''".to_string());
+                // sqlcipher_export does not carry the schema version over
+                let version: i64 = conn
+                    .query_row("PRAGMA user_version", [], |row| row.get(0))
+                    .map_err(to_encryption)?;
+                conn.execute_batch(&format!(
+                    "ATTACH DATABASE '{}' AS rotated KEY {};
+                     SELECT sqlcipher_export('rotated');
+                     PRAGMA rotated.user_version = {};
+                     DETACH DATABASE rotated;",
+                    tmp.replace('\'', "''"),
+                    key,
+                    version
+                )).map_err(to_encryption)?;
+                drop(conn);
+                std::fs::rename(&tmp, path).map_err(|e| DatabaseError::Encryption(e.to_string()))?;
+            }
+        }
+
+        Ok(())
+    }
+
     async fn initialize(&self) -> Result<(), DatabaseError> {
         // Create tables if they don't exist
         self.conn.call(|conn| {
@@ -45,7 +362,19 @@ impl Database {
                     value TEXT NOT NULL,
                     timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
                 );"
-            )
+            )?;
+
+            // Bring the schema up to date
+            let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+            for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+                let tx = conn.transaction()?;
+                tx.execute_batch(migration)?;
+                tx.execute_batch(&format!("PRAGMA user_version = {}", i + 1))?;
+                tx.commit()?;
+                info!("Applied database migration {}", i + 1);
+            }
+
+            Ok(())
         })
         .await?;
 
@@ -55,32 +384,50 @@ impl Database {
 
     pub async fn save_conversation(
         &self,
+        user_id: String,
         user_input: String,
         ai_response: String,
         personality: String,
-    ) -> Result<(), DatabaseError> {
-        self.conn
+        memory_partition: String,
+    ) -> Result<i64, DatabaseError> {
+        let id = self.conn
             .call(move |conn| {
                 conn.execute(
-                    "INSERT INTO conversations (user_input, ai_response, personality) VALUES (?1, ?2, ?3)",
-                    [&user_input, &ai_response, &personality],
-                )
+                    "INSERT INTO conversations (user_id, user_input, ai_response, personality, memory_partition) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    [&user_id, &user_input, &ai_response, &personality, &memory_partition],
+                )?;
+                Ok(conn.last_insert_rowid())
             })
             .await?;
         
-        Ok(())
+        Ok(id)
+    }
+
+    pub async fn delete_conversation(&self, id: i64) -> Result<bool, DatabaseError> {
+        let deleted = self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute("DELETE FROM feedback WHERE conversation_id = ?", [id])?;
+                let deleted = tx.execute("DELETE FROM conversations WHERE id = ?", [id])?;
+                tx.commit()?;
+                Ok(deleted)
+            })
+            .await?;
+
+        Ok(deleted > 0)
     }
 
     pub async fn save_knowledge(
         &self,
+        user_id: String,
         key: String,
         value: String,
     ) -> Result<(), DatabaseError> {
         self.conn
             .call(move |conn| {
                 conn.execute(
-                    "INSERT OR REPLACE INTO knowledge_base (key, value) VALUES (?1, ?2)",
-                    [&key, &value],
+                    "INSERT OR REPLACE INTO knowledge_base (user_id, key, value) VALUES (?1, ?2, ?3)",
+                    [&user_id, &key, &value],
                 )
             })
             .await?;
@@ -88,23 +435,36 @@ impl Database {
         Ok(())
     }
 
-    pub async fn get_recent_conversations(&self, limit: i64) -> Result<Vec<(String, String, String, String)>, DatabaseError> {
+    /// Returns false if there was no such row.
+    pub async fn delete_knowledge(&self, user_id: String, key: String) -> Result<bool, DatabaseError> {
+        let deleted = self.conn
+            .call(move |conn| conn.execute("DELETE FROM knowledge_base WHERE user_id = ?1 AND key = ?2", [&user_id, &key]))
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Most recent conversations of `user_id` in one memory partition, newest first.
+    pub async fn get_partition_conversations(&self, user_id: String, memory_partition: String, limit: i64) -> Result<Vec<ConversationRecord>, DatabaseError> {
         let result = self.conn
             .call(move |conn| {
                 let mut stmt = conn.prepare(
-                    "SELECT timestamp, user_input, ai_response, personality 
+                    "SELECT id, timestamp, user_input, ai_response, personality, memory_partition 
                      FROM conversations 
-                     ORDER BY timestamp DESC 
-                     LIMIT ?"
+                     WHERE user_id = ?1 AND memory_partition = ?2
+                     ORDER BY timestamp DESC, id DESC 
+                     LIMIT ?3"
                 )?;
                 
-                let rows = stmt.query_map([limit], |row| {
-                    Ok((
-                        row.get::<_, String>(0)?,
-                        row.get::<_, String>(1)?,
-                        row.get::<_, String>(2)?,
-                        row.get::<_, String>(3)?,
-                    ))
+                let rows = stmt.query_map(rusqlite::params![user_id, memory_partition, limit], |row| {
+                    Ok(ConversationRecord {
+                        id: row.get(0)?,
+                        timestamp: row.get(1)?,
+                        user_input: row.get(2)?,
+                        ai_response: row.get(3)?,
+                        personality: row.get(4)?,
+                        memory_partition: row.get(5)?,
+                    })
                 })?;
 
                 let mut conversations = Vec::new();
@@ -119,11 +479,37 @@ impl Database {
         Ok(result)
     }
 
-    pub async fn get_knowledge(&self, key: String) -> Result<Option<String>, DatabaseError> {
+    pub async fn get_conversation(&self, id: i64) -> Result<Option<ConversationRecord>, DatabaseError> {
         let result = self.conn
             .call(move |conn| {
-                let mut stmt = conn.prepare("SELECT value FROM knowledge_base WHERE key = ?")?;
-                let mut rows = stmt.query([&key])?;
+                conn.query_row(
+                    "SELECT id, timestamp, user_input, ai_response, personality, memory_partition
+                     FROM conversations
+                     WHERE id = ?1",
+                    [id],
+                    |row| {
+                        Ok(ConversationRecord {
+                            id: row.get(0)?,
+                            timestamp: row.get(1)?,
+                            user_input: row.get(2)?,
+                            ai_response: row.get(3)?,
+                            personality: row.get(4)?,
+                            memory_partition: row.get(5)?,
+                        })
+                    },
+                )
+                .optional()
+            })
+            .await?;
+
+        Ok(result)
+    }
+
+    pub async fn get_knowledge(&self, user_id: String, key: String) -> Result<Option<String>, DatabaseError> {
+        let result = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare("SELECT value FROM knowledge_base WHERE user_id = ?1 AND key = ?2")?;
+                let mut rows = stmt.query([&user_id, &key])?;
                 
                 if let Some(row) = rows.next()? {
                     Ok(Some(row.get::<_, String>(0)?))
@@ -135,4 +521,204 @@ impl Database {
             
         Ok(result)
     }
+
+    /// Every conversation of `user_id`, oldest first.
+    pub async fn get_all_conversations(&self, user_id: String) -> Result<Vec<ConversationRecord>, DatabaseError> {
+        let result = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, timestamp, user_input, ai_response, personality, memory_partition 
+                     FROM conversations 
+                     WHERE user_id = ?1
+                     ORDER BY timestamp, id"
+                )?;
+
+                let rows = stmt.query_map([&user_id], |row| {
+                    Ok(ConversationRecord {
+                        id: row.get(0)?,
+                        timestamp: row.get(1)?,
+                        user_input: row.get(2)?,
+                        ai_response: row.get(3)?,
+                        personality: row.get(4)?,
+                        memory_partition: row.get(5)?,
+                    })
+                })?;
+
+                rows.collect()
+            })
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Inserts conversations in one transaction, skipping exact duplicates.
+    /// Returns how many were added, and the id each record has here by its
+    /// own `id` (the one it had where it was exported).
+    pub async fn import_conversations(
+        &self,
+        user_id: String,
+        records: Vec<ConversationRecord>,
+    ) -> Result<(usize, HashMap<i64, i64>), DatabaseError> {
+        let imported = self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let mut inserted = 0;
+                let mut ids = HashMap::new();
+                {
+                    let mut stmt = tx.prepare(
+                        "INSERT INTO conversations (user_id, timestamp, user_input, ai_response, personality, memory_partition)
+                         SELECT ?1, ?2, ?3, ?4, ?5, ?6
+                         WHERE NOT EXISTS (
+                             SELECT 1 FROM conversations
+                             WHERE user_id = ?1 AND timestamp = ?2 AND user_input = ?3 AND ai_response = ?4
+                         )"
+                    )?;
+                    let mut existing = tx.prepare(
+                        "SELECT id FROM conversations
+                         WHERE user_id = ?1 AND timestamp = ?2 AND user_input = ?3 AND ai_response = ?4"
+                    )?;
+                    for record in &records {
+                        let added = stmt.execute([
+                            &user_id,
+                            &record.timestamp,
+                            &record.user_input,
+                            &record.ai_response,
+                            &record.personality,
+                            &record.memory_partition,
+                        ])?;
+                        let id = if added > 0 {
+                            tx.last_insert_rowid()
+                        } else {
+                            existing.query_row([&user_id, &record.timestamp, &record.user_input, &record.ai_response], |row| row.get(0))?
+                        };
+                        inserted += added;
+                        ids.insert(record.id, id);
+                    }
+                }
+                tx.commit()?;
+                Ok((inserted, ids))
+            })
+            .await?;
+
+        Ok(imported)
+    }
+
+    /// Knowledge rows of `user_id` whose key starts with `prefix`.
+    pub async fn get_knowledge_by_prefix(&self, user_id: String, prefix: String) -> Result<Vec<KnowledgeRecord>, DatabaseError> {
+        let result = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT key, value, timestamp FROM knowledge_base
+                     WHERE user_id = ?1 AND substr(key, 1, length(?2)) = ?2
+                     ORDER BY key"
+                )?;
+
+                let rows = stmt.query_map([&user_id, &prefix], |row| {
+                    Ok(KnowledgeRecord {
+                        key: row.get(0)?,
+                        value: row.get(1)?,
+                        timestamp: row.get(2)?,
+                    })
+                })?;
+
+                rows.collect()
+            })
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Topics `user_id` has insights on, with how many, most first.
+    pub async fn insight_topics(&self, user_id: String) -> Result<Vec<(String, i64)>, DatabaseError> {
+        let result = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT json_extract(value, '$.topic') AS topic, COUNT(*) AS n FROM knowledge_base
+                     WHERE user_id = ?1 AND substr(key, 1, 8) = 'insight:' AND json_valid(value)
+                     GROUP BY topic HAVING topic IS NOT NULL
+                     ORDER BY n DESC, topic"
+                )?;
+                let rows = stmt.query_map([&user_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Every knowledge row of `user_id`, ordered by key.
+    pub async fn get_all_knowledge(&self, user_id: String) -> Result<Vec<KnowledgeRecord>, DatabaseError> {
+        let result = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT key, value, timestamp FROM knowledge_base WHERE user_id = ?1 ORDER BY key"
+                )?;
+
+                let rows = stmt.query_map([&user_id], |row| {
+                    Ok(KnowledgeRecord {
+                        key: row.get(0)?,
+                        value: row.get(1)?,
+                        timestamp: row.get(2)?,
+                    })
+                })?;
+
+                rows.collect()
+            })
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Inserts knowledge rows in one transaction. Existing keys are kept
+    /// unless `replace` is set. Returns how many rows were written.
+    pub async fn import_knowledge(&self, user_id: String, records: Vec<KnowledgeRecord>, replace: bool) -> Result<usize, DatabaseError> {
+        let written = self.conn
+            .call(move |conn| {
+                let sql = if replace {
+                    "INSERT OR REPLACE INTO knowledge_base (user_id, key, value, timestamp) VALUES (?1, ?2, ?3, ?4)"
+                } else {
+                    "INSERT OR IGNORE INTO knowledge_base (user_id, key, value, timestamp) VALUES (?1, ?2, ?3, ?4)"
+                };
+
+                let tx = conn.transaction()?;
+                let mut written = 0;
+                {
+                    let mut stmt = tx.prepare(sql)?;
+                    for record in &records {
+                        written += stmt.execute([&user_id, &record.key, &record.value, &record.timestamp])?;
+                    }
+                }
+                tx.commit()?;
+                Ok(written)
+            })
+            .await?;
+
+        Ok(written)
+    }
+
+    /// Removes all conversations, feedback, knowledge, queued learning,
+    /// conflicts, insight usage and the profile belonging to `user_id`.
+    pub async fn clear_user(&self, user_id: String) -> Result<(), DatabaseError> {
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute("DELETE FROM conversations WHERE user_id = ?", [&user_id])?;
+                tx.execute("DELETE FROM knowledge_base WHERE user_id = ?", [&user_id])?;
+                tx.execute("DELETE FROM insight_usage WHERE user_id = ?", [&user_id])?;
+                // Sources of their own items, and what they backed of others'
+                tx.execute(
+                    "DELETE FROM kb_pending_sources
+                     WHERE user_id = ?1 OR pending_id IN (SELECT id FROM kb_pending WHERE user_id = ?1)",
+                    [&user_id],
+                )?;
+                tx.execute("DELETE FROM kb_pending WHERE user_id = ?", [&user_id])?;
+                tx.execute("DELETE FROM feedback WHERE user_id = ?", [&user_id])?;
+                tx.execute("DELETE FROM insight_conflicts WHERE user_id = ?", [&user_id])?;
+                tx.execute("DELETE FROM user_profiles WHERE user_id = ?", [&user_id])?;
+                tx.commit()
+            })
+            .await?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,220 @@
+// src/database/pending.rs
+// Review queue for learned content. Items wait here as 'pending' until
+// they are promoted into the knowledge base or rejected.
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use crate::knowledge_base::knowledge_base::Provenance;
+use super::{Database, DatabaseError};
+
+const PENDING_COLUMNS: &str = "id, user_id, topics, content, provenance, corroborations, status, created_at";
+
+/// A stored row of the kb_pending table.
+#[derive(Debug, Clone)]
+pub struct PendingRecord {
+    pub id: i64,
+    pub user_id: String,
+    pub topics: Vec<String>,
+    pub content: String,
+    pub provenance: Option<Provenance>,
+    /// How many interactions said the same thing, counting the first
+    pub corroborations: u32,
+    /// 'pending', 'promoted' or 'rejected'
+    pub status: String,
+    pub created_at: String,
+}
+
+fn pending_from_row(row: &Row) -> rusqlite::Result<PendingRecord> {
+    let json_error = |i: usize, e: serde_json::Error| rusqlite::Error::FromSqlConversionFailure(i, Type::Text, Box::new(e));
+    let topics: String = row.get(2)?;
+    let provenance: Option<String> = row.get(4)?;
+    Ok(PendingRecord {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        topics: serde_json::from_str(&topics).map_err(|e| json_error(2, e))?,
+        content: row.get(3)?,
+        provenance: provenance
+            .map(|p| serde_json::from_str(&p))
+            .transpose()
+            .map_err(|e| json_error(4, e))?,
+        corroborations: row.get(5)?,
+        status: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+// Inserts an item with the conversation it came from, returning its id
+pub(super) fn insert_pending(
+    conn: &Connection,
+    user_id: &str,
+    topics: &str,
+    content: &str,
+    provenance: Option<&str>,
+    conversation_id: Option<i64>,
+) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO kb_pending (user_id, topics, content, provenance) VALUES (?1, ?2, ?3, ?4)",
+        params![user_id, topics, content, provenance],
+    )?;
+    let id = conn.last_insert_rowid();
+    if let Some(conversation_id) = conversation_id {
+        conn.execute(
+            "INSERT INTO kb_pending_sources (pending_id, conversation_id, user_id, stated) VALUES (?1, ?2, ?3, 0)",
+            params![id, conversation_id, user_id],
+        )?;
+    }
+    Ok(id)
+}
+
+// Records a conversation backing an item and returns its new count. Only
+// independent sources count: model answers once per user, however often
+// they repeat themselves, and each time a user `stated` it in their own
+// words. Never less than one, for the item itself.
+pub(super) fn corroborate_pending(
+    conn: &Connection,
+    id: i64,
+    conversation_id: Option<i64>,
+    user_id: &str,
+    stated: bool,
+) -> rusqlite::Result<u32> {
+    // A conversation that was never saved can't be told apart from others
+    if let Some(conversation_id) = conversation_id {
+        conn.execute(
+            "INSERT OR IGNORE INTO kb_pending_sources (pending_id, conversation_id, user_id, stated) VALUES (?1, ?2, ?3, ?4)",
+            params![id, conversation_id, user_id, stated],
+        )?;
+    }
+    conn.query_row(
+        "UPDATE kb_pending
+         SET corroborations = max(1, (
+                 SELECT COUNT(DISTINCT CASE WHEN stated THEN 'conversation:' || conversation_id ELSE 'user:' || user_id END)
+                 FROM kb_pending_sources WHERE pending_id = ?1
+             )),
+             updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?1
+         RETURNING corroborations",
+        [id],
+        |row| row.get(0),
+    )
+}
+
+// Stores the vector of an item's content
+pub(super) fn store_pending_vector(conn: &Connection, id: i64, model: &str, vector: &[u8]) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO kb_pending_embeddings (pending_id, model, vector) VALUES (?1, ?2, ?3)",
+        params![id, model, vector],
+    )?;
+    Ok(())
+}
+
+// Vectors from `model` of items still pending, only those of `user_id`
+// when one is given
+pub(super) fn pending_vectors(conn: &Connection, user_id: Option<&str>, model: &str) -> rusqlite::Result<Vec<(i64, Vec<u8>)>> {
+    let mut stmt = conn.prepare(
+        "SELECT p.id, v.vector FROM kb_pending p JOIN kb_pending_embeddings v ON v.pending_id = p.id
+         WHERE p.status = 'pending' AND (?1 IS NULL OR p.user_id = ?1) AND v.model = ?2
+         ORDER BY p.id",
+    )?;
+    let rows = stmt.query_map(params![user_id, model], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+// Items still pending, only those of `user_id` when one is given
+pub(super) fn list_pending(conn: &Connection, user_id: Option<&str>) -> rusqlite::Result<Vec<PendingRecord>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM kb_pending
+         WHERE status = 'pending' AND (?1 IS NULL OR user_id = ?1)
+         ORDER BY id",
+        PENDING_COLUMNS
+    ))?;
+    let rows = stmt.query_map([user_id], pending_from_row)?;
+    rows.collect()
+}
+
+impl Database {
+    /// Items still waiting for review, oldest first; only those of
+    /// `user_id` when one is given.
+    pub async fn pending_list(&self, user_id: Option<String>) -> Result<Vec<PendingRecord>, DatabaseError> {
+        let records = self.conn
+            .call(move |conn| list_pending(conn, user_id.as_deref()))
+            .await?;
+
+        Ok(records)
+    }
+
+    /// Content of pending items with no vector from `model` yet.
+    pub async fn pending_missing_embeddings(&self, model: String) -> Result<Vec<(i64, String)>, DatabaseError> {
+        let missing = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT p.id, p.content FROM kb_pending p LEFT JOIN kb_pending_embeddings v ON v.pending_id = p.id
+                     WHERE p.status = 'pending' AND (v.pending_id IS NULL OR v.model != ?1)",
+                )?;
+                let rows = stmt.query_map([&model], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(missing)
+    }
+
+    pub async fn pending_store_embeddings(&self, model: String, vectors: Vec<(i64, Vec<u8>)>) -> Result<(), DatabaseError> {
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                for (id, vector) in &vectors {
+                    store_pending_vector(&tx, *id, &model, vector)?;
+                }
+                tx.commit()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Items still waiting for review that were learned in a conversation.
+    pub async fn pending_for_conversation(&self, conversation_id: i64) -> Result<Vec<PendingRecord>, DatabaseError> {
+        let records = self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {} FROM kb_pending
+                     WHERE status = 'pending' AND json_extract(provenance, '$.conversation_id') = ?1
+                     ORDER BY id",
+                    PENDING_COLUMNS
+                ))?;
+                let rows = stmt.query_map([conversation_id], pending_from_row)?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(records)
+    }
+
+    pub async fn pending_get(&self, id: i64) -> Result<Option<PendingRecord>, DatabaseError> {
+        let record = self.conn
+            .call(move |conn| {
+                conn.query_row(
+                    &format!("SELECT {} FROM kb_pending WHERE id = ?1", PENDING_COLUMNS),
+                    [id],
+                    pending_from_row,
+                )
+                .optional()
+            })
+            .await?;
+
+        Ok(record)
+    }
+
+    /// Marks an item promoted or rejected. Returns false if it was no longer pending.
+    pub async fn pending_set_status(&self, id: i64, status: &'static str) -> Result<bool, DatabaseError> {
+        let updated = self.conn
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE kb_pending SET status = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?1 AND status = 'pending'",
+                    params![id, status],
+                )
+            })
+            .await?;
+
+        Ok(updated > 0)
+    }
+}
@@ -0,0 +1,341 @@
+// src/database/portable.rs
+// What was learned about a user beyond their conversations and knowledge
+// rows (profile, feedback, review queue, conflicts and insight usage), as
+// plain rows that memory bundles carry to another database. Conversation
+// ids are those of the exporting database; importing points them at the
+// conversations they became there.
+use std::collections::HashMap;
+use rusqlite::{params, Connection};
+use serde::{Serialize, Deserialize};
+use crate::learning::UserProfile;
+use super::profile::edit_profile;
+use super::{Database, DatabaseError};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedbackRow {
+    pub conversation_id: i64,
+    pub kind: String,
+    pub correction: Option<String>,
+    pub created_at: String,
+}
+
+/// A conversation backing a queued item.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceRow {
+    pub conversation_id: i64,
+    /// None for the user the item belongs to
+    pub user_id: Option<String>,
+    pub stated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingRow {
+    pub topics: Vec<String>,
+    pub content: String,
+    pub provenance: Option<serde_json::Value>,
+    pub corroborations: u32,
+    pub status: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub sources: Vec<SourceRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConflictRow {
+    /// None when the insights are stored under the user themselves
+    pub owner: Option<String>,
+    pub topic: String,
+    pub old_key: String,
+    pub old_text: String,
+    pub new_key: String,
+    pub new_text: String,
+    pub reason: String,
+    pub status: String,
+    pub created_at: String,
+    pub resolved_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRow {
+    pub context: String,
+    pub uses: i64,
+    pub last_used: Option<String>,
+}
+
+/// Everything a bundle carries about a user's learning besides
+/// conversations and knowledge rows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LearningRecords {
+    pub profile: Option<UserProfile>,
+    pub feedback: Vec<FeedbackRow>,
+    pub pending: Vec<PendingRow>,
+    pub conflicts: Vec<ConflictRow>,
+    pub usage: Vec<UsageRow>,
+}
+
+impl LearningRecords {
+    pub fn count(&self) -> usize {
+        self.profile.iter().count() + self.feedback.len() + self.pending.len() + self.conflicts.len() + self.usage.len()
+    }
+}
+
+fn json_error(i: usize, e: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(i, rusqlite::types::Type::Text, Box::new(e))
+}
+
+fn to_json<T: Serialize>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// Points the conversation id of a provenance at the conversation it
+/// became, or at none when that one wasn't imported.
+pub fn remap_provenance(provenance: &mut serde_json::Value, ids: &HashMap<i64, i64>) {
+    if let Some(id) = provenance.get_mut("conversation_id") {
+        *id = id
+            .as_i64()
+            .and_then(|old| ids.get(&old))
+            .map_or(serde_json::Value::Null, |new| (*new).into());
+    }
+}
+
+// Some(user) unless it is `user_id` itself
+fn other(user_id: &str, user: String) -> Option<String> {
+    Some(user).filter(|user| user != user_id)
+}
+
+fn export(conn: &Connection, user_id: &str) -> rusqlite::Result<LearningRecords> {
+    let mut records = LearningRecords::default();
+
+    let mut stmt = conn.prepare("SELECT profile FROM user_profiles WHERE user_id = ?1")?;
+    let mut rows = stmt.query([user_id])?;
+    if let Some(row) = rows.next()? {
+        let json: String = row.get(0)?;
+        records.profile = Some(serde_json::from_str(&json).map_err(|e| json_error(0, e))?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT conversation_id, kind, correction, created_at FROM feedback WHERE user_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([user_id], |row| {
+        Ok(FeedbackRow {
+            conversation_id: row.get(0)?,
+            kind: row.get(1)?,
+            correction: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    })?;
+    records.feedback = rows.collect::<rusqlite::Result<_>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, topics, content, provenance, corroborations, status, created_at, updated_at
+         FROM kb_pending WHERE user_id = ?1 ORDER BY id",
+    )?;
+    let mut sources = conn.prepare(
+        "SELECT conversation_id, user_id, stated FROM kb_pending_sources WHERE pending_id = ?1 ORDER BY conversation_id, stated",
+    )?;
+    let mut rows = stmt.query([user_id])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let topics: String = row.get(1)?;
+        let provenance: Option<String> = row.get(3)?;
+        let item_sources = sources
+            .query_map([id], |row| {
+                Ok(SourceRow {
+                    conversation_id: row.get(0)?,
+                    user_id: other(user_id, row.get(1)?),
+                    stated: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        records.pending.push(PendingRow {
+            topics: serde_json::from_str(&topics).map_err(|e| json_error(1, e))?,
+            content: row.get(2)?,
+            provenance: provenance.map(|p| serde_json::from_str(&p)).transpose().map_err(|e| json_error(3, e))?,
+            corroborations: row.get(4)?,
+            status: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+            sources: item_sources,
+        });
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT owner, topic, old_key, old_text, new_key, new_text, reason, status, created_at, resolved_at
+         FROM insight_conflicts WHERE user_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([user_id], |row| {
+        Ok(ConflictRow {
+            owner: other(user_id, row.get(0)?),
+            topic: row.get(1)?,
+            old_key: row.get(2)?,
+            old_text: row.get(3)?,
+            new_key: row.get(4)?,
+            new_text: row.get(5)?,
+            reason: row.get(6)?,
+            status: row.get(7)?,
+            created_at: row.get(8)?,
+            resolved_at: row.get(9)?,
+        })
+    })?;
+    records.conflicts = rows.collect::<rusqlite::Result<_>>()?;
+
+    let mut stmt = conn.prepare("SELECT context, uses, last_used FROM insight_usage WHERE user_id = ?1 ORDER BY context")?;
+    let rows = stmt.query_map([user_id], |row| {
+        Ok(UsageRow {
+            context: row.get(0)?,
+            uses: row.get(1)?,
+            last_used: row.get(2)?,
+        })
+    })?;
+    records.usage = rows.collect::<rusqlite::Result<_>>()?;
+
+    Ok(records)
+}
+
+// Rows that are already there are skipped, and with `replace` unset so
+// are the profile and usage counts of a user who has their own. Feedback
+// and sources of conversations that weren't imported are dropped.
+fn import(conn: &Connection, user_id: &str, records: &LearningRecords, ids: &HashMap<i64, i64>, replace: bool) -> rusqlite::Result<usize> {
+    let mut written = 0;
+
+    if let Some(imported) = &records.profile {
+        let imported = imported.clone();
+        let edited = edit_profile(conn, user_id, |profile| {
+            if !replace && *profile != UserProfile::default() {
+                return false;
+            }
+            *profile = imported;
+            true
+        })?;
+        written += edited.iter().count();
+    }
+
+    let mut stmt = conn.prepare(
+        "INSERT OR IGNORE INTO feedback (conversation_id, user_id, kind, correction, created_at)
+         SELECT ?1, ?2, ?3, ?4, ?5
+         WHERE NOT EXISTS (
+             SELECT 1 FROM feedback WHERE conversation_id = ?1 AND user_id = ?2 AND kind = ?3 AND created_at = ?5
+         )",
+    )?;
+    for row in &records.feedback {
+        let Some(conversation_id) = ids.get(&row.conversation_id) else { continue };
+        written += stmt.execute(params![conversation_id, user_id, row.kind, row.correction, row.created_at])?;
+    }
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO kb_pending (user_id, topics, content, provenance, corroborations, status, created_at, updated_at)
+         SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8
+         WHERE NOT EXISTS (SELECT 1 FROM kb_pending WHERE user_id = ?1 AND content = ?3)",
+    )?;
+    let mut sources = conn.prepare(
+        "INSERT OR IGNORE INTO kb_pending_sources (pending_id, conversation_id, user_id, stated) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for row in &records.pending {
+        let provenance = match &row.provenance {
+            Some(provenance) => {
+                let mut provenance = provenance.clone();
+                remap_provenance(&mut provenance, ids);
+                Some(to_json(&provenance)?)
+            }
+            None => None,
+        };
+        let inserted = stmt.execute(params![
+            user_id,
+            to_json(&row.topics)?,
+            row.content,
+            provenance,
+            row.corroborations,
+            row.status,
+            row.created_at,
+            row.updated_at,
+        ])?;
+        if inserted == 0 {
+            continue;
+        }
+        written += 1;
+        let id = conn.last_insert_rowid();
+        for source in &row.sources {
+            // Other users' conversations keep their ids: only their user counts
+            let conversation_id = match &source.user_id {
+                None => match ids.get(&source.conversation_id) {
+                    Some(id) => *id,
+                    None => continue,
+                },
+                Some(_) => source.conversation_id,
+            };
+            let source_user = source.user_id.as_deref().unwrap_or(user_id);
+            sources.execute(params![id, conversation_id, source_user, source.stated])?;
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO insight_conflicts
+             (user_id, owner, topic, old_key, old_text, new_key, new_text, reason, status, created_at, resolved_at)
+         SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11
+         WHERE NOT EXISTS (
+             SELECT 1 FROM insight_conflicts WHERE user_id = ?1 AND old_key = ?4 AND new_key = ?6
+         )",
+    )?;
+    for row in &records.conflicts {
+        written += stmt.execute(params![
+            user_id,
+            row.owner.as_deref().unwrap_or(user_id),
+            row.topic,
+            row.old_key,
+            row.old_text,
+            row.new_key,
+            row.new_text,
+            row.reason,
+            row.status,
+            row.created_at,
+            row.resolved_at,
+        ])?;
+    }
+
+    let sql = if replace {
+        "INSERT OR REPLACE INTO insight_usage (user_id, context, uses, last_used) VALUES (?1, ?2, ?3, ?4)"
+    } else {
+        "INSERT OR IGNORE INTO insight_usage (user_id, context, uses, last_used) VALUES (?1, ?2, ?3, ?4)"
+    };
+    let mut stmt = conn.prepare(sql)?;
+    for row in &records.usage {
+        written += stmt.execute(params![user_id, row.context, row.uses, row.last_used])?;
+    }
+
+    Ok(written)
+}
+
+impl Database {
+    /// What was learned about `user_id` besides conversations and
+    /// knowledge rows.
+    pub async fn export_learning(&self, user_id: String) -> Result<LearningRecords, DatabaseError> {
+        let records = self.conn
+            .call(move |conn| export(conn, &user_id))
+            .await?;
+
+        Ok(records)
+    }
+
+    /// Stores exported learning records for `user_id` in one transaction.
+    /// `ids` maps the conversation ids of the exporting database to the
+    /// ones here. Existing rows are kept, and so are the profile and usage
+    /// counts unless `replace` is set. Returns how many rows were written.
+    pub async fn import_learning(
+        &self,
+        user_id: String,
+        records: LearningRecords,
+        ids: HashMap<i64, i64>,
+        replace: bool,
+    ) -> Result<usize, DatabaseError> {
+        let written = self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let written = import(&tx, &user_id, &records, &ids, replace)?;
+                tx.commit()?;
+                Ok(written)
+            })
+            .await?;
+
+        Ok(written)
+    }
+}
@@ -0,0 +1,65 @@
+// src/database/profile.rs
+// One learned profile per user, stored as JSON.
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use rusqlite::types::Type;
+use crate::learning::UserProfile;
+use super::{Database, DatabaseError};
+
+// Reads, edits and saves a profile on a connection that is already in a
+// transaction. Returns the profile if `edit` changed it.
+pub(super) fn edit_profile<F>(conn: &Connection, user_id: &str, edit: F) -> rusqlite::Result<Option<UserProfile>>
+where
+    F: FnOnce(&mut UserProfile) -> bool,
+{
+    let json: Option<String> = conn
+        .query_row("SELECT profile FROM user_profiles WHERE user_id = ?1", [user_id], |row| row.get(0))
+        .optional()?;
+    let mut profile: UserProfile = match json {
+        Some(json) => serde_json::from_str(&json)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))?,
+        None => UserProfile::default(),
+    };
+    if !edit(&mut profile) {
+        return Ok(None);
+    }
+    let json = serde_json::to_string(&profile).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT INTO user_profiles (user_id, profile) VALUES (?1, ?2)
+         ON CONFLICT (user_id) DO UPDATE SET profile = ?2, updated_at = CURRENT_TIMESTAMP",
+        params![user_id, json],
+    )?;
+    Ok(Some(profile))
+}
+
+impl Database {
+    pub async fn get_profile(&self, user_id: String) -> Result<Option<UserProfile>, DatabaseError> {
+        let json: Option<String> = self.conn
+            .call(move |conn| {
+                conn.query_row("SELECT profile FROM user_profiles WHERE user_id = ?1", [user_id], |row| row.get(0))
+                    .optional()
+            })
+            .await?;
+
+        Ok(json.map(|json| serde_json::from_str(&json)).transpose()?)
+    }
+
+    /// Reads, edits and saves a user's profile in one transaction, so
+    /// learning and edits by hand can't undo each other. `edit` returns
+    /// whether it changed anything; the saved profile is returned if so.
+    pub async fn update_profile<F>(&self, user_id: String, edit: F) -> Result<Option<UserProfile>, DatabaseError>
+    where
+        F: FnOnce(&mut UserProfile) -> bool + Send + 'static,
+    {
+        let profile = self.conn
+            .call(move |conn| {
+                // Takes the write lock up front, before reading
+                let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+                let profile = edit_profile(&tx, &user_id, edit)?;
+                tx.commit()?;
+                Ok(profile)
+            })
+            .await?;
+
+        Ok(profile)
+    }
+}
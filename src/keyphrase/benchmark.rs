@@ -0,0 +1,98 @@
+// src/keyphrase/benchmark.rs
+// Checks topic extraction against texts with hand-picked topics
+// (data/benchmarks/keyphrases.json).
+use std::fmt;
+use std::fs;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Corpus {
+    cases: Vec<Case>,
+}
+
+#[derive(Deserialize)]
+struct Case {
+    text: String,
+    expected: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct BenchResult {
+    pub cases: usize,
+    /// Share of extracted topics that were expected
+    pub precision: f32,
+    /// Share of expected topics that were extracted
+    pub recall: f32,
+    /// Expected topics that were missed, with what was extracted instead
+    pub misses: Vec<(String, Vec<String>)>,
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} texts  precision {:.2}  recall {:.2}  {} topics missed",
+            self.cases,
+            self.precision,
+            self.recall,
+            self.misses.len()
+        )
+    }
+}
+
+/// Extracts `top_k` topics from every text of the corpus at `path`.
+pub fn run(path: &str, top_k: usize) -> Result<BenchResult, Box<dyn std::error::Error>> {
+    let corpus: Corpus = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let (mut precision, mut recall) = (0.0, 0.0);
+    let mut misses = Vec::new();
+
+    for case in &corpus.cases {
+        let topics = super::extract(&case.text, top_k);
+        let relevant = topics.iter().filter(|t| case.expected.iter().any(|e| matches(t, e))).count();
+        precision += relevant as f32 / topics.len().max(1) as f32;
+
+        let found = case.expected.iter().filter(|e| topics.iter().any(|t| matches(t, e))).count();
+        recall += found as f32 / case.expected.len().max(1) as f32;
+        for expected in case.expected.iter().filter(|e| !topics.iter().any(|t| matches(t, e))) {
+            misses.push((expected.clone(), topics.clone()));
+        }
+    }
+
+    let count = corpus.cases.len().max(1) as f32;
+    Ok(BenchResult {
+        cases: corpus.cases.len(),
+        precision: precision / count,
+        recall: recall / count,
+        misses,
+    })
+}
+
+// A topic matches when one contains the other as whole words, so
+// "tokio" and "tokio runtime" both count for "tokio runtime"
+fn matches(topic: &str, expected: &str) -> bool {
+    let contains = |long: &str, short: &str| format!(" {} ", long).contains(&format!(" {} ", short));
+    contains(topic, expected) || contains(expected, topic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/data/benchmarks/keyphrases.json");
+
+    #[test]
+    fn topics_stay_above_the_floor() {
+        // A little under what extraction scores today, with five topics
+        let result = run(CORPUS, 5).unwrap();
+        assert!(result.cases > 0);
+        assert!(result.precision >= 0.78, "{}", result);
+        assert!(result.recall >= 0.95, "{}", result);
+    }
+
+    #[test]
+    fn topics_match_as_whole_words() {
+        assert!(matches("tokio", "tokio runtime"));
+        assert!(matches("tokio runtime", "tokio"));
+        assert!(!matches("tok", "tokio runtime"));
+    }
+}
@@ -0,0 +1,225 @@
+// src/keyphrase/mod.rs
+// Keyphrase extraction for conversation and learning topics. Candidate
+// phrases are runs of content words between stopwords and punctuation
+// (RAKE), cut down to noun-phrase length and lemmatized, then scored by
+// how connected and frequent their words are.
+use std::collections::HashMap;
+use crate::text;
+
+pub mod benchmark;
+
+// Longer runs keep their last words, where English puts the head noun
+const MAX_PHRASE_WORDS: usize = 3;
+// Names and code identifiers are usually what a text is about
+const NAME_BOOST: f32 = 1.5;
+// Extra weight for each repeat of a phrase
+const REPEAT_BOOST: f32 = 0.5;
+
+// Words that are not stopwords but never make a topic: conversational
+// filler, vague nouns and common verbs
+const FILLER: &[&str] = &[
+    "across", "actually", "along", "also", "always", "among", "another", "anyone", "anything",
+    "around", "ask", "asked", "back", "basically", "best", "better", "bit", "can't", "cannot",
+    "certain", "come", "could", "different", "don't", "done", "else", "etc", "even", "ever",
+    "every", "everything", "example", "explain", "find", "first", "get", "gets", "getting", "give",
+    "go", "goes", "going", "good", "got", "great", "hello", "help", "hey", "hi", "however", "idea",
+    "instead", "keep", "keeps", "kind", "know", "last", "least", "less", "let", "lets", "like",
+    "little", "lot", "lots", "make", "makes", "making", "many", "may", "maybe", "mean", "might",
+    "much", "must", "need", "needs", "new", "next", "nice", "ok", "okay", "one", "ones", "please",
+    "pretty", "probably", "quite", "rather", "really", "right", "say", "says", "see", "seem",
+    "seems", "shall", "show", "since", "something", "sometimes", "sort", "still", "sure", "take",
+    "tell", "thank", "thanks", "thing", "things", "think", "though", "thus", "today", "try", "two",
+    "upon", "us", "use", "used", "uses", "using", "via", "want", "wants", "way", "ways", "well",
+    "whether", "whose", "within", "without", "work", "works", "yes", "yet",
+];
+
+// Adverb endings; adverbs end a noun phrase
+const ADVERB_ENDINGS: &[&str] = &["ably", "ally", "antly", "ately", "ently", "fully", "ibly", "ively", "ously"];
+
+struct Token {
+    word: String,
+    // Capitalized mid-sentence, or shaped like a code identifier
+    name: bool,
+    // Ends in -s like a plural or a verb ("tasks", "schedules")
+    inflected: bool,
+}
+
+/// The `max` best keyphrases of `text`, lowercased and lemmatized, best first.
+pub fn extract(text: &str, max: usize) -> Vec<String> {
+    let candidates = candidates(text);
+
+    // RAKE word score: degree (summed length of the phrases a word is in)
+    // over frequency, favouring words that occur in longer phrases
+    let mut frequency: HashMap<&str, f32> = HashMap::new();
+    let mut degree: HashMap<&str, f32> = HashMap::new();
+    for phrase in &candidates {
+        for token in phrase {
+            *frequency.entry(&token.word).or_default() += 1.0;
+            *degree.entry(&token.word).or_default() += phrase.len() as f32;
+        }
+    }
+
+    // Phrase -> (best score, occurrences), in order of first appearance
+    let mut order: Vec<String> = Vec::new();
+    let mut phrases: HashMap<String, (f32, usize)> = HashMap::new();
+    for phrase in &candidates {
+        let mut score: f32 = phrase.iter().map(|t| degree[t.word.as_str()] / frequency[t.word.as_str()]).sum();
+        if phrase.iter().any(|t| t.name) {
+            score *= NAME_BOOST;
+        }
+        let key = phrase.iter().map(|t| t.word.as_str()).collect::<Vec<_>>().join(" ");
+        let entry = phrases.entry(key.clone()).or_insert_with(|| {
+            order.push(key);
+            (0.0, 0)
+        });
+        entry.0 = entry.0.max(score);
+        entry.1 += 1;
+    }
+
+    let mut ranked: Vec<(String, f32)> = order
+        .into_iter()
+        .map(|key| {
+            let (score, count) = phrases[&key];
+            (key, score * (1.0 + REPEAT_BOOST * (count - 1) as f32))
+        })
+        .collect();
+    // Stable, so equal scores keep their order of appearance
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.into_iter().take(max).map(|(key, _)| key).collect()
+}
+
+// Runs of content words, split at stopwords, filler and punctuation
+fn candidates(text: &str) -> Vec<Vec<Token>> {
+    let mut candidates = Vec::new();
+    let mut current = Vec::new();
+    let mut sentence_start = true;
+
+    for raw in text.split_whitespace() {
+        let raw = raw.replace('\u{2019}', "'");
+        if raw.starts_with(['(', '[', '{', '"', '\'']) {
+            flush(&mut current, &mut candidates);
+        }
+
+        match clean(&raw) {
+            Some(word) if is_content(word) => {
+                let name = !sentence_start && is_name(word);
+                let lower = word.to_lowercase();
+                // Names keep their form; "Rails" is not a plural
+                let word = if name { lower.clone() } else { text::lemma(&lower) };
+                let inflected = word != lower;
+                current.push(Token { word, name, inflected });
+            }
+            _ => flush(&mut current, &mut candidates),
+        }
+
+        if raw.ends_with([',', ';', ':', '.', '!', '?', ')', ']', '}', '"', '\'']) {
+            flush(&mut current, &mut candidates);
+        }
+        sentence_start = raw.trim_end_matches([')', ']', '}', '"', '\'']).ends_with(['.', '!', '?']);
+    }
+    flush(&mut current, &mut candidates);
+    candidates
+}
+
+// Ends the current run. Inside a run an inflected word followed by more
+// words is most likely a verb ("Tokio schedules async tasks"), since
+// plural nouns rarely modify others, so the run is split there.
+fn flush(current: &mut Vec<Token>, candidates: &mut Vec<Vec<Token>>) {
+    let mut phrase = Vec::new();
+    let count = current.len();
+    for (i, token) in current.drain(..).enumerate() {
+        let verb = token.inflected && i + 1 < count;
+        if !verb {
+            phrase.push(token);
+        }
+        if verb || i + 1 == count {
+            if !phrase.is_empty() {
+                let start = phrase.len().saturating_sub(MAX_PHRASE_WORDS);
+                candidates.push(phrase.split_off(start));
+            }
+            phrase.clear();
+        }
+    }
+}
+
+// The word without surrounding punctuation or a possessive "'s"; keeps
+// trailing + and # for names like C++ and C#
+fn clean(raw: &str) -> Option<&str> {
+    let word = raw
+        .trim_start_matches(|c: char| !c.is_alphanumeric())
+        .trim_end_matches(|c: char| !c.is_alphanumeric() && c != '+' && c != '#');
+    let word = word.strip_suffix("'s").unwrap_or(word);
+    if word.is_empty() {
+        None
+    } else {
+        Some(word)
+    }
+}
+
+fn is_content(word: &str) -> bool {
+    let lower = word.to_lowercase();
+    !(text::is_stopword(&lower)
+        || FILLER.binary_search(&lower.as_str()).is_ok()
+        // Contractions like "it's" and "we'll"
+        || lower.contains('\'')
+        || lower.chars().count() < 2
+        || lower.chars().all(|c| !c.is_alphabetic())
+        || ADVERB_ENDINGS.iter().any(|end| lower.ends_with(end)))
+}
+
+fn is_name(word: &str) -> bool {
+    let mut chars = word.chars();
+    let first_upper = chars.next().is_some_and(|c| c.is_uppercase());
+    let identifier = chars.any(|c| c.is_uppercase())
+        || word.contains('_')
+        || (word.chars().any(|c| c.is_ascii_digit()) && word.chars().any(|c| c.is_alphabetic()));
+    (first_upper && word != "I") || identifier
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(word: &str, inflected: bool) -> Token {
+        Token { word: word.to_string(), name: false, inflected }
+    }
+
+    fn words(candidates: &[Vec<Token>]) -> Vec<String> {
+        candidates
+            .iter()
+            .map(|phrase| phrase.iter().map(|t| t.word.as_str()).collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn flush_splits_at_an_inflected_word_inside_a_run() {
+        // "Tokio schedules async tasks": "schedules" is the verb
+        let mut current = vec![token("tokio", false), token("schedule", true), token("async", false), token("task", true)];
+        let mut candidates = Vec::new();
+        flush(&mut current, &mut candidates);
+        assert!(current.is_empty());
+        assert_eq!(words(&candidates), ["tokio", "async task"]);
+    }
+
+    #[test]
+    fn flush_keeps_an_inflected_last_word() {
+        let mut current = vec![token("rust", false), token("crate", true)];
+        let mut candidates = Vec::new();
+        flush(&mut current, &mut candidates);
+        assert_eq!(words(&candidates), ["rust crate"]);
+    }
+
+    #[test]
+    fn flush_keeps_the_last_words_of_long_runs() {
+        let mut current = ["one", "two", "three", "four", "five"].iter().map(|w| token(w, false)).collect();
+        let mut candidates = Vec::new();
+        flush(&mut current, &mut candidates);
+        assert_eq!(words(&candidates), ["three four five"]);
+    }
+
+    #[test]
+    fn candidates_split_at_verbs_and_punctuation() {
+        let found = candidates("Tokio schedules async tasks. Borrow checker rules, lifetimes");
+        assert_eq!(words(&found), ["tokio", "async task", "borrow checker rule", "lifetime"]);
+    }
+}
@@ -0,0 +1,120 @@
+// src/knowledge_base/benchmark.rs
+// Measures search quality on a fixed corpus (data/benchmarks/kb_search.json)
+// in each search mode, using a throwaway in-memory database.
+use std::fmt;
+use std::fs;
+use std::time::Instant;
+use serde::Deserialize;
+use crate::database::Database;
+use super::knowledge_base::{KnowledgeBaseHandler, KnowledgeEntry, SearchMode, SearchOptions};
+
+#[derive(Deserialize)]
+struct Corpus {
+    documents: Vec<KnowledgeEntry>,
+    queries: Vec<Query>,
+}
+
+#[derive(Deserialize)]
+struct Query {
+    query: String,
+    relevant: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct ModeResult {
+    pub mode: SearchMode,
+    /// Share of relevant entries found in the top k
+    pub recall: f32,
+    /// Mean reciprocal rank of the first relevant entry
+    pub mrr: f32,
+    pub avg_ms: f32,
+    /// Queries whose results contained no relevant entry
+    pub misses: Vec<String>,
+}
+
+impl fmt::Display for ModeResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<8} recall {:.2}  MRR {:.2}  {:.2} ms/query  {} missed",
+            format!("{:?}", self.mode).to_lowercase(),
+            self.recall,
+            self.mrr,
+            self.avg_ms,
+            self.misses.len()
+        )
+    }
+}
+
+/// Runs every query of the corpus at `path` in each mode, keeping `top_k` results.
+pub async fn run(path: &str, top_k: usize) -> Result<Vec<ModeResult>, Box<dyn std::error::Error>> {
+    let corpus: Corpus = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let knowledge_base = KnowledgeBaseHandler::with_database(Database::new(":memory:").await?);
+    knowledge_base.save_entries(corpus.documents).await?;
+
+    let mut results = Vec::new();
+    for mode in [SearchMode::Keyword, SearchMode::Vector, SearchMode::Hybrid] {
+        let options = SearchOptions { mode, top_k, ..SearchOptions::default() };
+        let (mut recall, mut mrr, mut elapsed) = (0.0, 0.0, 0.0);
+        let mut misses = Vec::new();
+
+        for query in &corpus.queries {
+            let start = Instant::now();
+            let hits = knowledge_base.search(&query.query, options).await?;
+            elapsed += start.elapsed().as_secs_f32() * 1000.0;
+
+            let found = query.relevant.iter().filter(|id| hits.iter().any(|h| &h.entry.id == *id)).count();
+            recall += found as f32 / query.relevant.len() as f32;
+            match hits.iter().position(|h| query.relevant.contains(&h.entry.id)) {
+                Some(rank) => mrr += 1.0 / (rank + 1) as f32,
+                None => misses.push(query.query.clone()),
+            }
+        }
+
+        let count = corpus.queries.len().max(1) as f32;
+        results.push(ModeResult {
+            mode,
+            recall: recall / count,
+            mrr: mrr / count,
+            avg_ms: elapsed / count,
+            misses,
+        });
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/data/benchmarks/kb_search.json");
+
+    // Floors a little under what each mode scores today, at k = 5
+    fn floor(mode: SearchMode) -> (f32, f32) {
+        match mode {
+            SearchMode::Keyword => (0.85, 0.85),
+            SearchMode::Vector => (0.85, 0.85),
+            SearchMode::Hybrid => (0.90, 0.90),
+        }
+    }
+
+    #[tokio::test]
+    async fn search_quality_stays_above_the_floor() {
+        let results = run(CORPUS, 5).await.unwrap();
+        assert_eq!(results.len(), 3);
+        for result in &results {
+            let (recall, mrr) = floor(result.mode);
+            assert!(result.recall >= recall, "{} (recall floor {})", result, recall);
+            assert!(result.mrr >= mrr, "{} (MRR floor {})", result, mrr);
+        }
+    }
+
+    #[tokio::test]
+    async fn hybrid_is_at_least_as_good_as_either_mode() {
+        let results = run(CORPUS, 5).await.unwrap();
+        let hybrid = results.iter().find(|r| r.mode == SearchMode::Hybrid).unwrap();
+        for result in &results {
+            assert!(hybrid.recall >= result.recall, "{} beats {}", result, hybrid);
+        }
+    }
+}
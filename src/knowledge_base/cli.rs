@@ -0,0 +1,415 @@
+// src/knowledge_base/cli.rs
+// `kb` subcommands for curating the knowledge base from the shell.
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use clap::Subcommand;
+use serde::Serialize;
+use crate::commands::preview;
+use crate::output::{OutputFormat, Table};
+use super::knowledge_base::{KnowledgeBaseHandler, KnowledgeEntry, Provenance, SearchMode, SearchOptions};
+
+#[derive(Subcommand, Debug)]
+pub enum KbCommand {
+    /// Add an entry
+    Add {
+        /// The text of the entry, or - to read it from stdin
+        content: String,
+        #[arg(long, value_delimiter = ',')]
+        keywords: Vec<String>,
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+        #[arg(long, default_value = "curated")]
+        source: String,
+    },
+    /// List entries, oldest first
+    List {
+        /// Only entries from this source (curated, learned, ingested, ...)
+        #[arg(long)]
+        source: Option<String>,
+        /// Only entries with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Show one entry in full
+    Show {
+        id: String,
+    },
+    /// Search the way the chat does
+    Search {
+        query: String,
+        #[arg(long, value_enum, default_value_t = SearchMode::Hybrid)]
+        mode: SearchMode,
+        #[arg(long, default_value_t = 5)]
+        top_k: usize,
+    },
+    /// Change parts of an entry; fields not given are kept
+    Edit {
+        id: String,
+        /// New text, or - to read it from stdin
+        #[arg(long)]
+        content: Option<String>,
+        #[arg(long, value_delimiter = ',')]
+        keywords: Option<Vec<String>>,
+        #[arg(long, value_delimiter = ',')]
+        tags: Option<Vec<String>>,
+        #[arg(long)]
+        source: Option<String>,
+    },
+    /// Delete entries
+    Delete {
+        #[arg(required = true)]
+        ids: Vec<String>,
+    },
+    /// Load entries from a knowledge base JSON file
+    Import {
+        path: String,
+        /// Replace the whole knowledge base instead of adding new entries
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Write every entry to a JSON file
+    Export {
+        path: String,
+    },
+    /// Counts by source and tag
+    Stats,
+    /// Merge entries with the same content
+    Dedupe {
+        /// Only report what would be merged
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Serialize)]
+struct Stats {
+    entries: usize,
+    documents: usize,
+    keywords: usize,
+    average_length: usize,
+    by_source: BTreeMap<String, usize>,
+    by_tag: BTreeMap<String, usize>,
+}
+
+#[derive(Serialize)]
+struct SearchHit<'a> {
+    rank: usize,
+    score: f32,
+    #[serde(flatten)]
+    entry: &'a KnowledgeEntry,
+}
+
+#[derive(Serialize)]
+struct Merge<'a> {
+    kept: &'a str,
+    removed: &'a [String],
+}
+
+pub async fn run(command: KbCommand, format: OutputFormat, knowledge_base: &KnowledgeBaseHandler, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        KbCommand::Add { content, keywords, tags, source } => {
+            let mut entry = KnowledgeEntry::new(clean(keywords), &read_content(content)?, &source);
+            entry.tags = clean(tags);
+            entry.provenance = Some(Provenance::Manual { user_id: user_id.to_string() });
+            let entry = knowledge_base.insert(entry).await?;
+            match format {
+                OutputFormat::Json => print_json(&entry)?,
+                OutputFormat::Csv => entry_table(&entry).print(format),
+                OutputFormat::Table => println!("Added {}", entry.id),
+            }
+        }
+        KbCommand::List { source, tag, limit } => {
+            let entries: Vec<_> = knowledge_base
+                .entries()
+                .await?
+                .into_iter()
+                .filter(|e| source.as_ref().is_none_or(|s| &e.source == s))
+                .filter(|e| tag.as_ref().is_none_or(|t| e.tags.contains(t)))
+                .take(limit.unwrap_or(usize::MAX))
+                .collect();
+            match format {
+                OutputFormat::Json => print_json(&entries)?,
+                OutputFormat::Table | OutputFormat::Csv => {
+                    let mut table = Table::new(&["ID", "SOURCE", "UPDATED", "KEYWORDS", "CONTENT"]);
+                    for entry in &entries {
+                        table.add_row(vec![
+                            entry.id.clone(),
+                            entry.source.clone(),
+                            entry.updated_at.format("%Y-%m-%d").to_string(),
+                            preview(&entry.keywords.join(", "), 30),
+                            preview(&entry.content, 50),
+                        ]);
+                    }
+                    table.print(format);
+                }
+            }
+        }
+        KbCommand::Show { id } => {
+            let entry = knowledge_base.get(&id).await?.ok_or_else(|| format!("No entry with id {}", id))?;
+            match format {
+                OutputFormat::Json => print_json(&entry)?,
+                OutputFormat::Csv => entry_table(&entry).print(format),
+                OutputFormat::Table => print_entry(&entry),
+            }
+        }
+        KbCommand::Search { query, mode, top_k } => {
+            let options = SearchOptions { mode, top_k, ..SearchOptions::default() };
+            let hits = knowledge_base.search(&query, options).await?;
+            match format {
+                OutputFormat::Json => {
+                    let hits: Vec<_> = hits
+                        .iter()
+                        .enumerate()
+                        .map(|(i, hit)| SearchHit { rank: i + 1, score: hit.score, entry: &hit.entry })
+                        .collect();
+                    print_json(&hits)?;
+                }
+                OutputFormat::Table | OutputFormat::Csv => {
+                    let mut table = Table::new(&["#", "ID", "SCORE", "CONTENT"]);
+                    for (i, hit) in hits.iter().enumerate() {
+                        table.add_row(vec![
+                            (i + 1).to_string(),
+                            hit.entry.id.clone(),
+                            format!("{:.3}", hit.score),
+                            preview(&hit.entry.content, 60),
+                        ]);
+                    }
+                    table.print(format);
+                }
+            }
+        }
+        KbCommand::Edit { id, content, keywords, tags, source } => {
+            if content.is_none() && keywords.is_none() && tags.is_none() && source.is_none() {
+                return Err("Nothing to change: give --content, --keywords, --tags or --source".into());
+            }
+            let mut entry = knowledge_base.get(&id).await?.ok_or_else(|| format!("No entry with id {}", id))?;
+            if let Some(content) = content {
+                entry.content = read_content(content)?;
+            }
+            if let Some(keywords) = keywords {
+                entry.keywords = clean(keywords);
+            }
+            if let Some(tags) = tags {
+                entry.tags = clean(tags);
+            }
+            if let Some(source) = source {
+                entry.source = source;
+            }
+            knowledge_base.update(entry).await?;
+            let entry = knowledge_base.get(&id).await?.ok_or_else(|| format!("No entry with id {}", id))?;
+            match format {
+                OutputFormat::Json => print_json(&entry)?,
+                OutputFormat::Csv => entry_table(&entry).print(format),
+                OutputFormat::Table => println!("Updated {}", id),
+            }
+        }
+        KbCommand::Delete { ids } => {
+            let mut deleted = Vec::new();
+            for id in ids {
+                if knowledge_base.delete(&id).await? {
+                    deleted.push(id);
+                } else {
+                    eprintln!("No entry with id {}", id);
+                }
+            }
+            match format {
+                OutputFormat::Json => print_json(&deleted)?,
+                OutputFormat::Csv => {
+                    let mut table = Table::new(&["ID"]);
+                    for id in &deleted {
+                        table.add_row(vec![id.clone()]);
+                    }
+                    table.print(format);
+                }
+                OutputFormat::Table => println!("Deleted {} entries", deleted.len()),
+            }
+        }
+        KbCommand::Import { path, replace } => {
+            let count = knowledge_base.import_json(&path, replace).await?;
+            match format {
+                OutputFormat::Json => print_json(&serde_json::json!({ "imported": count }))?,
+                OutputFormat::Csv => {
+                    let mut table = Table::new(&["IMPORTED", "PATH"]);
+                    table.add_row(vec![count.to_string(), path]);
+                    table.print(format);
+                }
+                OutputFormat::Table => println!("Imported {} entries from {}", count, path),
+            }
+        }
+        KbCommand::Export { path } => {
+            let count = knowledge_base.export_json(&path).await?;
+            match format {
+                OutputFormat::Json => print_json(&serde_json::json!({ "exported": count }))?,
+                OutputFormat::Csv => {
+                    let mut table = Table::new(&["EXPORTED", "PATH"]);
+                    table.add_row(vec![count.to_string(), path]);
+                    table.print(format);
+                }
+                OutputFormat::Table => println!("Exported {} entries to {}", count, path),
+            }
+        }
+        KbCommand::Stats => {
+            let stats = stats(&knowledge_base.entries().await?);
+            match format {
+                OutputFormat::Json => print_json(&stats)?,
+                // Each table starts with a `# name` line, as in the learning report
+                OutputFormat::Csv => {
+                    let mut totals = Table::new(&["ENTRIES", "DOCUMENTS", "KEYWORDS", "AVERAGE LENGTH"]);
+                    totals.add_row(vec![
+                        stats.entries.to_string(),
+                        stats.documents.to_string(),
+                        stats.keywords.to_string(),
+                        stats.average_length.to_string(),
+                    ]);
+                    let mut by_source = Table::new(&["SOURCE", "ENTRIES"]);
+                    for (source, count) in &stats.by_source {
+                        by_source.add_row(vec![source.clone(), count.to_string()]);
+                    }
+                    let mut by_tag = Table::new(&["TAG", "ENTRIES"]);
+                    for (tag, count) in &stats.by_tag {
+                        by_tag.add_row(vec![tag.clone(), count.to_string()]);
+                    }
+                    for (i, (name, table)) in [("totals", totals), ("sources", by_source), ("tags", by_tag)].iter().enumerate() {
+                        if i > 0 {
+                            println!();
+                        }
+                        println!("# {}", name);
+                        table.print(format);
+                    }
+                }
+                OutputFormat::Table => {
+                    println!(
+                        "{} entries, {} ingested documents, {} distinct keywords, {} characters on average",
+                        stats.entries, stats.documents, stats.keywords, stats.average_length
+                    );
+                    let mut table = Table::new(&["SOURCE", "ENTRIES"]);
+                    for (source, count) in &stats.by_source {
+                        table.add_row(vec![source.clone(), count.to_string()]);
+                    }
+                    println!();
+                    table.print(format);
+                    if !stats.by_tag.is_empty() {
+                        let mut table = Table::new(&["TAG", "ENTRIES"]);
+                        for (tag, count) in &stats.by_tag {
+                            table.add_row(vec![tag.clone(), count.to_string()]);
+                        }
+                        println!();
+                        table.print(format);
+                    }
+                }
+            }
+        }
+        KbCommand::Dedupe { dry_run } => {
+            let merges = knowledge_base.dedupe(dry_run).await?;
+            let mut table = Table::new(&["KEPT", "MERGED"]);
+            for (kept, removed) in &merges {
+                table.add_row(vec![kept.clone(), removed.join(", ")]);
+            }
+            match format {
+                OutputFormat::Json => {
+                    let merges: Vec<_> = merges.iter().map(|(kept, removed)| Merge { kept, removed }).collect();
+                    print_json(&merges)?;
+                }
+                OutputFormat::Csv => table.print(format),
+                OutputFormat::Table => {
+                    if !table.is_empty() {
+                        table.print(format);
+                    }
+                    let removed: usize = merges.iter().map(|(_, removed)| removed.len()).sum();
+                    let verb = if dry_run { "Would remove" } else { "Removed" };
+                    println!("{} {} duplicate entries", verb, removed);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn stats(entries: &[KnowledgeEntry]) -> Stats {
+    let mut by_source = BTreeMap::new();
+    let mut by_tag = BTreeMap::new();
+    let mut documents = HashSet::new();
+    let mut keywords = HashSet::new();
+    for entry in entries {
+        *by_source.entry(entry.source.clone()).or_insert(0) += 1;
+        for tag in &entry.tags {
+            *by_tag.entry(tag.clone()).or_insert(0) += 1;
+        }
+        if let Some(document) = &entry.document {
+            documents.insert(document.path.clone());
+        }
+        for keyword in &entry.keywords {
+            keywords.insert(keyword.to_lowercase());
+        }
+    }
+
+    let total: usize = entries.iter().map(|e| e.content.chars().count()).sum();
+    Stats {
+        entries: entries.len(),
+        documents: documents.len(),
+        keywords: keywords.len(),
+        average_length: total / entries.len().max(1),
+        by_source,
+        by_tag,
+    }
+}
+
+fn print_entry(entry: &KnowledgeEntry) {
+    println!("ID:       {}", entry.id);
+    println!("Source:   {}", entry.source);
+    println!("Origin:   {}", entry.origin());
+    println!("Keywords: {}", entry.keywords.join(", "));
+    if !entry.tags.is_empty() {
+        println!("Tags:     {}", entry.tags.join(", "));
+    }
+    if let Some(document) = &entry.document {
+        println!("Document: {} (bytes {}-{})", document.path, document.start, document.end);
+    }
+    println!("Created:  {}", entry.created_at.format("%Y-%m-%d %H:%M:%S"));
+    println!("Updated:  {}", entry.updated_at.format("%Y-%m-%d %H:%M:%S"));
+    println!("\n{}", entry.content);
+}
+
+// One entry as a CSV row, with its full content
+fn entry_table(entry: &KnowledgeEntry) -> Table {
+    let mut table = Table::new(&["ID", "SOURCE", "ORIGIN", "KEYWORDS", "TAGS", "CREATED", "UPDATED", "CONTENT"]);
+    table.add_row(vec![
+        entry.id.clone(),
+        entry.source.clone(),
+        entry.origin(),
+        entry.keywords.join(", "),
+        entry.tags.join(", "),
+        entry.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        entry.updated_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        entry.content.clone(),
+    ]);
+    table
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), serde_json::Error> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+// "-" reads the text from stdin, so long entries can be piped in
+fn read_content(content: String) -> Result<String, Box<dyn std::error::Error>> {
+    let content = if content == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        content
+    };
+    let content = content.trim().to_string();
+    if content.is_empty() {
+        return Err("Entry content is empty".into());
+    }
+    Ok(content)
+}
+
+fn clean(values: Vec<String>) -> Vec<String> {
+    values.into_iter().map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()
+}
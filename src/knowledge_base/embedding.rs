@@ -0,0 +1,72 @@
+// src/knowledge_base/embedding.rs
+// Hashed word and trigram vectors for fuzzy lexical matching. Words and
+// their character trigrams are hashed into a fixed number of dimensions,
+// so texts sharing word stems or spellings end up close together. This
+// only sees spelling, not meaning: "car" and "automobile" are unrelated.
+use crate::text;
+
+/// Identifies how stored vectors were made; change it whenever the
+/// hashing changes so old vectors get recomputed.
+pub const EMBEDDING_MODEL: &str = "hashing-v1-256";
+const DIMENSIONS: usize = 256;
+
+// Trigrams matter less than whole words
+const WORD_WEIGHT: f32 = 1.0;
+const TRIGRAM_WEIGHT: f32 = 0.5;
+
+/// Unit-length vector of `text` (all zeros if it has no words).
+pub fn embed(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0; DIMENSIONS];
+
+    for word in text::tokenize(text) {
+        add_feature(&mut vector, &word, WORD_WEIGHT);
+
+        let padded: Vec<char> = format!("<{}>", word).chars().collect();
+        for trigram in padded.windows(3) {
+            add_feature(&mut vector, &trigram.iter().collect::<String>(), TRIGRAM_WEIGHT);
+        }
+    }
+
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for x in vector.iter_mut() {
+            *x /= norm;
+        }
+    }
+    vector
+}
+
+/// Cosine similarity of two unit-length vectors.
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+pub fn to_bytes(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+// The hash picks a dimension and a sign, so collisions tend to cancel out
+fn add_feature(vector: &mut [f32], feature: &str, weight: f32) {
+    let hash = fnv1a(feature.as_bytes());
+    let index = (hash % DIMENSIONS as u64) as usize;
+    let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+    vector[index] += sign * weight;
+}
+
+// FNV-1a: stable across Rust versions, unlike std's DefaultHasher, which
+// matters because vectors are stored
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
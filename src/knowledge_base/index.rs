@@ -0,0 +1,39 @@
+// src/knowledge_base/index.rs
+// BM25 index over knowledge base entries. The postings live in the
+// database's kb_fts table (porter-stemmed keywords, content and tags),
+// which triggers update with every write to an entry, so the index stays
+// incremental and nothing has to be loaded into memory to search it.
+use std::collections::HashSet;
+use crate::database::{Database, DatabaseError};
+use crate::text;
+use super::knowledge_base::KnowledgeEntry;
+
+#[derive(Clone)]
+pub struct Bm25Index {
+    db: Database,
+}
+
+impl Bm25Index {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Best matching entries with their scores, at most `top_k` of them
+    /// and none scoring below `min_score`. Keywords count double.
+    pub async fn search(&self, query: &str, top_k: usize, min_score: f32) -> Result<Vec<(KnowledgeEntry, f32)>, DatabaseError> {
+        // Quoted words joined with OR, so user input is never read as FTS syntax
+        let mut seen = HashSet::new();
+        let terms: Vec<String> = text::words(query)
+            .into_iter()
+            .filter(|w| !text::is_stopword(w) && seen.insert(w.clone()))
+            .map(|w| format!("\"{}\"", w))
+            .collect();
+        if terms.is_empty() || top_k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut hits = self.db.kb_search(terms.join(" OR "), top_k).await?;
+        hits.retain(|(_, score)| *score >= min_score);
+        Ok(hits)
+    }
+}
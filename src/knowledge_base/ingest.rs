@@ -0,0 +1,889 @@
+// src/knowledge_base/ingest.rs
+// Turns documents on disk (Markdown, text, HTML, source code, JSON, CSV)
+// into knowledge base entries, one per chunk.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+use crate::text;
+use super::knowledge_base::{DocumentSpan, KnowledgeBaseHandler, KnowledgeEntry, Provenance};
+
+// Bigger files are almost certainly not documentation
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_KEYWORDS: usize = 8;
+
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "ts", "go", "java", "c", "h", "cpp", "hpp", "cs", "rb", "sh", "sql", "kt",
+    "swift", "php", "toml", "yaml", "yml",
+];
+
+pub struct IngestOptions {
+    /// Target chunk length in bytes
+    pub chunk_size: usize,
+    /// How much of the end of a chunk is repeated at the start of the next
+    pub overlap: usize,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        Self { chunk_size: 1000, overlap: 200 }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct IngestReport {
+    pub ingested: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub chunks: usize,
+    /// Files that could not be ingested, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+impl fmt::Display for IngestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} files ingested ({} chunks), {} unchanged, {} removed, {} skipped",
+            self.ingested,
+            self.chunks,
+            self.unchanged,
+            self.removed,
+            self.skipped.len()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Markdown,
+    Text,
+    Html,
+    Code,
+    Json,
+    Csv,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "md" | "markdown" => Some(Format::Markdown),
+            "txt" | "text" => Some(Format::Text),
+            "html" | "htm" => Some(Format::Html),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            ext if CODE_EXTENSIONS.contains(&ext) => Some(Format::Code),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Markdown => "markdown",
+            Format::Text => "text",
+            Format::Html => "html",
+            Format::Code => "code",
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockKind {
+    Heading(usize),
+    // Never split unless far too long
+    Code,
+    Text,
+}
+
+// A piece of a document and the bytes of the file it came from
+#[derive(Debug, Clone)]
+struct Block {
+    text: String,
+    start: usize,
+    end: usize,
+    kind: BlockKind,
+    // True when `text` is exactly the file content at start..end
+    exact: bool,
+}
+
+#[derive(Debug)]
+struct Chunk {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+/// Ingests files and directories into the knowledge base. Files already
+/// ingested with the same content are left alone; changed files have their
+/// chunks replaced, and files gone from an ingested directory are dropped.
+pub async fn ingest(
+    paths: &[String],
+    options: &IngestOptions,
+    knowledge_base: &KnowledgeBaseHandler,
+) -> Result<IngestReport, Box<dyn std::error::Error>> {
+    if options.chunk_size == 0 || options.overlap >= options.chunk_size {
+        return Err("overlap must be smaller than the chunk size".into());
+    }
+
+    let mut report = IngestReport::default();
+    let mut files = Vec::new();
+    let mut directories = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            directories.push(normalize(path));
+            let walker = WalkDir::new(path)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'));
+            for entry in walker {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        let path = e.path().map(normalize).unwrap_or_else(|| normalize(path));
+                        report.skipped.push((path, e.to_string()));
+                        continue;
+                    }
+                };
+                if entry.file_type().is_file() && Format::from_path(entry.path()).is_some() {
+                    files.push(entry.into_path());
+                }
+            }
+        } else if path.is_file() {
+            files.push(path.to_path_buf());
+        } else {
+            return Err(format!("{} does not exist", path.display()).into());
+        }
+    }
+
+    // Hash of every document already in the knowledge base
+    let known: HashMap<String, String> = knowledge_base
+        .entries()
+        .await?
+        .into_iter()
+        .filter_map(|e| e.document.map(|doc| (doc.path, doc.hash)))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut replaced = HashSet::new();
+    let mut entries = Vec::new();
+    for file in files {
+        let path = normalize(&file);
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+
+        let Some(format) = Format::from_path(&file) else {
+            report.skipped.push((path, "unsupported file type".to_string()));
+            continue;
+        };
+        match fs::metadata(&file) {
+            Ok(metadata) if metadata.len() > MAX_FILE_SIZE => {
+                report.skipped.push((path, "file too large".to_string()));
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                report.skipped.push((path, e.to_string()));
+                continue;
+            }
+        }
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) => {
+                report.skipped.push((path, e.to_string()));
+                continue;
+            }
+        };
+
+        let hash = format!("{:x}", Sha256::digest(content.as_bytes()));
+        if known.get(&path) == Some(&hash) {
+            report.unchanged += 1;
+            continue;
+        }
+
+        let chunks = chunk(parse(format, &content), options);
+        let stem = file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        for chunk in chunks {
+            let mut entry = KnowledgeEntry::new(derive_keywords(&stem, &chunk.text), &chunk.text, "ingested");
+            entry.tags = vec![format.name().to_string()];
+            entry.document = Some(DocumentSpan {
+                path: path.clone(),
+                start: chunk.start,
+                end: chunk.end,
+                hash: hash.clone(),
+            });
+            entry.provenance = Some(Provenance::File { path: path.clone(), line: Some(line_at(&content, chunk.start)) });
+            entries.push(entry);
+            report.chunks += 1;
+        }
+        replaced.insert(path);
+        report.ingested += 1;
+    }
+
+    // Documents that disappeared from an ingested directory
+    for path in known.keys() {
+        let inside = directories.iter().any(|dir| Path::new(path).starts_with(dir));
+        if inside && !seen.contains(path) {
+            replaced.insert(path.clone());
+            report.removed += 1;
+        }
+    }
+
+    if !replaced.is_empty() {
+        knowledge_base.replace_documents(&replaced, entries).await?;
+    }
+    Ok(report)
+}
+
+// 1-based line number of a byte offset
+fn line_at(content: &str, offset: usize) -> usize {
+    content.as_bytes()[..offset.min(content.len())].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+// The same file reached as "./docs/a.md" or "docs/a.md" must match
+fn normalize(path: &Path) -> String {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn parse(format: Format, content: &str) -> Vec<Block> {
+    match format {
+        Format::Markdown => parse_lines(content, true),
+        Format::Text | Format::Code => parse_lines(content, false),
+        Format::Html => parse_html(content),
+        Format::Json => parse_json(content),
+        Format::Csv => parse_csv(content),
+    }
+}
+
+// Paragraphs separated by blank lines; with `markdown`, also headings and
+// fenced code blocks
+fn parse_lines(content: &str, markdown: bool) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Option<(usize, usize)> = None;
+    let mut fence: Option<(String, usize)> = None;
+    let mut offset = 0;
+
+    let push = |blocks: &mut Vec<Block>, start: usize, end: usize, kind: BlockKind| {
+        let raw = &content[start..end];
+        let trimmed = raw.trim();
+        if trimmed.is_empty() {
+            return;
+        }
+        let start = start + (raw.len() - raw.trim_start().len());
+        blocks.push(Block {
+            text: trimmed.to_string(),
+            start,
+            end: start + trimmed.len(),
+            kind,
+            exact: true,
+        });
+    };
+
+    for line in content.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let trimmed = line.trim();
+
+        if let Some((marker, fence_start)) = &fence {
+            if trimmed.starts_with(marker.as_str()) {
+                push(&mut blocks, *fence_start, offset, BlockKind::Code);
+                fence = None;
+            }
+            continue;
+        }
+
+        if markdown && (trimmed.starts_with("```") || trimmed.starts_with("~~~")) {
+            if let Some((start, end)) = paragraph.take() {
+                push(&mut blocks, start, end, BlockKind::Text);
+            }
+            fence = Some((trimmed[..3].to_string(), line_start));
+            continue;
+        }
+
+        if markdown {
+            if let Some(level) = heading_level(line) {
+                if let Some((start, end)) = paragraph.take() {
+                    push(&mut blocks, start, end, BlockKind::Text);
+                }
+                let title = trimmed.trim_start_matches('#').trim();
+                let title_start = line_start + line.find(title).unwrap_or(0);
+                if !title.is_empty() {
+                    blocks.push(Block {
+                        text: title.to_string(),
+                        start: title_start,
+                        end: title_start + title.len(),
+                        kind: BlockKind::Heading(level),
+                        exact: true,
+                    });
+                }
+                continue;
+            }
+        }
+
+        if trimmed.is_empty() {
+            if let Some((start, end)) = paragraph.take() {
+                push(&mut blocks, start, end, BlockKind::Text);
+            }
+        } else {
+            let start = paragraph.map(|(start, _)| start).unwrap_or(line_start);
+            paragraph = Some((start, offset));
+        }
+    }
+
+    if let Some((_, start)) = fence {
+        // Unterminated fence: keep what is there
+        push(&mut blocks, start, offset, BlockKind::Code);
+    }
+    if let Some((start, end)) = paragraph {
+        push(&mut blocks, start, end, BlockKind::Text);
+    }
+    blocks
+}
+
+// "## Title" is a level 2 heading; up to three spaces of indent are allowed
+fn heading_level(line: &str) -> Option<usize> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let level = rest.len() - rest.trim_start_matches('#').len();
+    let after = &rest[level..];
+    ((1..=6).contains(&level) && (after.is_empty() || after.starts_with([' ', '\t', '\n', '\r']))).then_some(level)
+}
+
+// Tags that end a run of text in HTML
+const HTML_BLOCK_TAGS: &[&str] = &[
+    "p", "div", "br", "li", "ul", "ol", "tr", "td", "th", "table", "section", "article", "header",
+    "footer", "nav", "aside", "blockquote", "hr", "title", "dd", "dt", "dl", "main", "body",
+];
+
+fn parse_html(content: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut text = String::new();
+    let mut span: Option<(usize, usize)> = None;
+    let mut kind = BlockKind::Text;
+    let mut i = 0;
+
+    let flush = |blocks: &mut Vec<Block>, text: &mut String, span: &mut Option<(usize, usize)>, kind: BlockKind| {
+        let decoded = decode_entities(text);
+        let cleaned = if kind == BlockKind::Code {
+            decoded.trim_matches('\n').to_string()
+        } else {
+            decoded.split_whitespace().collect::<Vec<_>>().join(" ")
+        };
+        if let Some((start, end)) = span.take() {
+            if !cleaned.trim().is_empty() {
+                blocks.push(Block { text: cleaned, start, end, kind, exact: false });
+            }
+        }
+        text.clear();
+    };
+
+    while i < content.len() {
+        let rest = &content[i..];
+        if rest.starts_with("<!--") {
+            i += rest.find("-->").map(|p| p + 3).unwrap_or(rest.len());
+            continue;
+        }
+        if rest.starts_with('<') {
+            let Some(close) = rest.find('>') else { break };
+            let tag = &rest[1..close];
+            let closing = tag.starts_with('/');
+            let name: String = tag
+                .trim_start_matches('/')
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_lowercase();
+            i += close + 1;
+
+            if !closing && (name == "script" || name == "style") {
+                // Skip the element body entirely
+                let end_tag = format!("</{}", name);
+                let body = content[i..].to_ascii_lowercase();
+                i += body.find(&end_tag).unwrap_or(body.len());
+                continue;
+            }
+
+            let heading = name.len() == 2 && name.starts_with('h') && name.as_bytes()[1].is_ascii_digit();
+            if heading || name == "pre" {
+                flush(&mut blocks, &mut text, &mut span, kind);
+                kind = match (closing, heading) {
+                    (true, _) => BlockKind::Text,
+                    (false, true) => BlockKind::Heading((name.as_bytes()[1] - b'0') as usize),
+                    (false, false) => BlockKind::Code,
+                };
+            } else if HTML_BLOCK_TAGS.contains(&name.as_str()) && kind == BlockKind::Text {
+                flush(&mut blocks, &mut text, &mut span, kind);
+            }
+            continue;
+        }
+
+        let next = rest.find('<').unwrap_or(rest.len());
+        let piece = &rest[..next];
+        if !piece.trim().is_empty() || !text.is_empty() {
+            text.push_str(piece);
+            let end = i + piece.trim_end().len();
+            span = Some(match span {
+                Some((start, _)) => (start, end.max(start)),
+                None => (i + (piece.len() - piece.trim_start().len()), end),
+            });
+        }
+        i += next;
+    }
+    flush(&mut blocks, &mut text, &mut span, kind);
+    blocks
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+// One block per element of a top-level array or member of a top-level
+// object, flattened to "path: value" lines
+fn parse_json(content: &str) -> Vec<Block> {
+    let spans = top_level_spans(content);
+    let object = content.trim_start().starts_with('{');
+
+    let mut blocks = Vec::new();
+    for (start, end) in spans {
+        let slice = &content[start..end];
+        let parsed = if object {
+            serde_json::from_str::<serde_json::Map<String, Value>>(&format!("{{{}}}", slice)).map(Value::Object)
+        } else {
+            serde_json::from_str::<Value>(slice)
+        };
+        let Ok(value) = parsed else { continue };
+
+        let mut lines = Vec::new();
+        flatten_json("", &value, &mut lines);
+        if !lines.is_empty() {
+            blocks.push(Block { text: lines.join("\n"), start, end, kind: BlockKind::Text, exact: false });
+        }
+    }
+
+    // Scalars, or a file we could not split
+    if blocks.is_empty() {
+        if let Ok(value) = serde_json::from_str::<Value>(content) {
+            let mut lines = Vec::new();
+            flatten_json("", &value, &mut lines);
+            if !lines.is_empty() {
+                blocks.push(Block {
+                    text: lines.join("\n"),
+                    start: 0,
+                    end: content.len(),
+                    kind: BlockKind::Text,
+                    exact: false,
+                });
+            }
+        }
+    }
+    blocks
+}
+
+// Byte ranges of the items directly inside the outermost [] or {}
+fn top_level_spans(content: &str) -> Vec<(usize, usize)> {
+    let bytes = content.as_bytes();
+    let mut spans = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut item_start = None;
+
+    let close = |spans: &mut Vec<(usize, usize)>, start: Option<usize>, end: usize| {
+        if let Some(start) = start {
+            let item = content[start..end].trim_end();
+            if !item.is_empty() {
+                spans.push((start, start + item.len()));
+            }
+        }
+    };
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        if depth == 1 && item_start.is_none() && !byte.is_ascii_whitespace() && !matches!(byte, b',' | b']' | b'}') {
+            item_start = Some(i);
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => depth += 1,
+            b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close(&mut spans, item_start.take(), i);
+                    break;
+                }
+            }
+            b',' if depth == 1 => close(&mut spans, item_start.take(), i),
+            _ => {}
+        }
+    }
+    spans
+}
+
+fn flatten_json(prefix: &str, value: &Value, lines: &mut Vec<String>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten_json(&join(key), value, lines);
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten_json(&format!("{}[{}]", prefix, i), value, lines);
+            }
+        }
+        Value::Null => {}
+        Value::String(s) if prefix.is_empty() => lines.push(s.clone()),
+        Value::String(s) => lines.push(format!("{}: {}", prefix, s)),
+        other if prefix.is_empty() => lines.push(other.to_string()),
+        other => lines.push(format!("{}: {}", prefix, other)),
+    }
+}
+
+// One block per row, as "column: value" lines
+fn parse_csv(content: &str) -> Vec<Block> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers = reader.headers().cloned().unwrap_or_default();
+
+    let mut rows = Vec::new();
+    for record in reader.records().flatten() {
+        let start = record.position().map(|p| p.byte() as usize).unwrap_or(0);
+        let lines: Vec<_> = record
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| !field.trim().is_empty())
+            .map(|(i, field)| match headers.get(i) {
+                Some(header) if !header.is_empty() => format!("{}: {}", header, field.trim()),
+                _ => field.trim().to_string(),
+            })
+            .collect();
+        rows.push((start, lines.join("\n")));
+    }
+
+    let mut blocks = Vec::new();
+    for (i, (start, text)) in rows.iter().enumerate() {
+        let end = rows.get(i + 1).map(|(next, _)| *next).unwrap_or(content.len());
+        if !text.is_empty() {
+            let end = start + content[*start..end].trim_end().len();
+            blocks.push(Block { text: text.clone(), start: *start, end, kind: BlockKind::Text, exact: false });
+        }
+    }
+    blocks
+}
+
+// Groups blocks into chunks of about `chunk_size` bytes. Headings start a
+// new chunk and are repeated at the top of every chunk in their section;
+// code blocks are kept whole.
+fn chunk(blocks: Vec<Block>, options: &IngestOptions) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut current: Vec<Block> = Vec::new();
+    let mut length = 0;
+
+    for block in blocks {
+        if let BlockKind::Heading(level) = block.kind {
+            emit(&mut chunks, &headings, &current);
+            current.clear();
+            length = 0;
+            headings.retain(|(l, _)| *l < level);
+            headings.push((level, block.text));
+            continue;
+        }
+
+        for piece in split_block(block, options.chunk_size) {
+            if !current.is_empty() && length + piece.text.len() > options.chunk_size {
+                emit(&mut chunks, &headings, &current);
+                current = overlap_tail(&current, options.overlap);
+                length = current.iter().map(|b| b.text.len()).sum();
+                if length + piece.text.len() > options.chunk_size {
+                    current.clear();
+                    length = 0;
+                }
+            }
+            length += piece.text.len();
+            current.push(piece);
+        }
+    }
+    emit(&mut chunks, &headings, &current);
+    chunks
+}
+
+fn emit(chunks: &mut Vec<Chunk>, headings: &[(usize, String)], blocks: &[Block]) {
+    if blocks.is_empty() {
+        return;
+    }
+    let body = blocks.iter().map(|b| b.text.as_str()).collect::<Vec<_>>().join("\n\n");
+    let text = if headings.is_empty() {
+        body
+    } else {
+        let path = headings.iter().map(|(_, h)| h.as_str()).collect::<Vec<_>>().join(" > ");
+        format!("{}\n\n{}", path, body)
+    };
+    chunks.push(Chunk {
+        text,
+        start: blocks.iter().map(|b| b.start).min().unwrap_or(0),
+        end: blocks.iter().map(|b| b.end).max().unwrap_or(0),
+    });
+}
+
+// The trailing blocks of a chunk that fit into `overlap` bytes. When even
+// the last block is too long, its last sentences or words are used instead.
+fn overlap_tail(blocks: &[Block], overlap: usize) -> Vec<Block> {
+    if overlap == 0 {
+        return Vec::new();
+    }
+
+    let mut tail = Vec::new();
+    let mut length = 0;
+    for block in blocks.iter().rev() {
+        if length + block.text.len() > overlap || tail.len() + 1 == blocks.len() {
+            break;
+        }
+        length += block.text.len();
+        tail.insert(0, block.clone());
+    }
+
+    if tail.is_empty() {
+        let last = blocks.last().unwrap();
+        if last.kind == BlockKind::Code {
+            return tail;
+        }
+        let mut cut = last.text.len().saturating_sub(overlap);
+        while !last.text.is_char_boundary(cut) {
+            cut += 1;
+        }
+        // Start on a word, or on a whole "key: value" line for structured data
+        let boundary = |c: char| if last.exact { c.is_whitespace() } else { c == '\n' };
+        let cut = last.text[cut..].find(boundary).map(|p| cut + p).unwrap_or(last.text.len());
+        let suffix = last.text[cut..].trim_start();
+        if !suffix.is_empty() {
+            let start = if last.exact { last.end - suffix.len() } else { last.start };
+            tail.push(Block { text: suffix.to_string(), start, end: last.end, kind: last.kind, exact: last.exact });
+        }
+    }
+    tail
+}
+
+// Cuts blocks longer than `size` at line, then sentence, then word
+// boundaries. Code is allowed to run to twice the size before being cut.
+fn split_block(block: Block, size: usize) -> Vec<Block> {
+    let limit = if block.kind == BlockKind::Code { size * 2 } else { size };
+    if block.text.len() <= limit {
+        return vec![block];
+    }
+
+    let text = &block.text;
+    let mut pieces = Vec::new();
+    let push = |pieces: &mut Vec<Block>, from: usize, to: usize| {
+        let raw = &text[from..to];
+        let trimmed = raw.trim();
+        if trimmed.is_empty() {
+            return;
+        }
+        let (start, end) = if block.exact {
+            let start = block.start + from + (raw.len() - raw.trim_start().len());
+            (start, start + trimmed.len())
+        } else {
+            (block.start, block.end)
+        };
+        pieces.push(Block { text: trimmed.to_string(), start, end, kind: block.kind, exact: block.exact });
+    };
+
+    // Positions just after the last line end, sentence end and space
+    let mut piece_start = 0;
+    let (mut line, mut sentence, mut space) = (0, 0, 0);
+    for (i, c) in text.char_indices() {
+        if i - piece_start >= size {
+            // Prefer the strongest boundary that keeps the piece at least half full
+            let half = piece_start + size / 2;
+            let cut = [line, sentence]
+                .into_iter()
+                .find(|&b| b > half)
+                .or((space > piece_start).then_some(space))
+                .unwrap_or(i);
+            push(&mut pieces, piece_start, cut);
+            piece_start = cut;
+        }
+
+        let after = i + c.len_utf8();
+        if c == '\n' {
+            line = after;
+        } else if matches!(c, '.' | '!' | '?') && text[after..].starts_with(char::is_whitespace) {
+            sentence = after;
+        } else if c.is_whitespace() {
+            space = after;
+        }
+    }
+    push(&mut pieces, piece_start, text.len());
+    pieces
+}
+
+// The file name plus the most frequent meaningful words of the chunk
+fn derive_keywords(file_stem: &str, text: &str) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+    for word in text::words(file_stem) {
+        if word.len() > 2 && !text::is_stopword(&word) && !keywords.contains(&word) {
+            keywords.push(word);
+        }
+    }
+
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for (position, word) in text::words(text).into_iter().enumerate() {
+        if word.len() < 3 || text::is_stopword(&word) || word.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        counts.entry(word).or_insert((0, position)).0 += 1;
+    }
+    let mut ranked: Vec<_> = counts.into_iter().collect();
+    // Most frequent first, earlier mentions breaking ties
+    ranked.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.1 .1.cmp(&b.1 .1)));
+
+    for (word, _) in ranked {
+        if keywords.len() >= MAX_KEYWORDS {
+            break;
+        }
+        if !keywords.contains(&word) {
+            keywords.push(word);
+        }
+    }
+    keywords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(chunk_size: usize, overlap: usize) -> IngestOptions {
+        IngestOptions { chunk_size, overlap }
+    }
+
+    // Ten numbered paragraphs of about 100 bytes each
+    fn paragraphs() -> String {
+        (1..=10)
+            .map(|i| format!("Paragraph {} talks about topic number {} and keeps going for a while to fill about one hundred bytes.", i, i))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    #[test]
+    fn blocks_point_at_their_text() {
+        let content = "# Title\n\nFirst paragraph\nstill first.\n\n  Second one.  \n\n```\nlet x = 1;\n```\n";
+        let blocks = parse(Format::Markdown, content);
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks[0].kind, BlockKind::Heading(1));
+        assert_eq!(blocks[3].kind, BlockKind::Code);
+        for block in &blocks {
+            assert_eq!(&content[block.start..block.end], block.text);
+        }
+    }
+
+    #[test]
+    fn chunks_stay_under_the_size_without_overlap() {
+        let content = paragraphs();
+        let chunks = chunk(parse(Format::Text, &content), &options(250, 0));
+        assert!(chunks.len() >= 4);
+        for pair in chunks.windows(2) {
+            assert!(pair[0].end <= pair[1].start, "chunks overlap");
+        }
+        for chunk in &chunks {
+            assert!(chunk.text.len() <= 250, "chunk of {} bytes", chunk.text.len());
+            assert!(content[chunk.start..chunk.end].starts_with("Paragraph"));
+        }
+        // Every paragraph ends up in exactly one chunk
+        for i in 1..=10 {
+            let needle = format!("Paragraph {} ", i);
+            assert_eq!(chunks.iter().filter(|c| c.text.contains(&needle)).count(), 1);
+        }
+    }
+
+    #[test]
+    fn overlap_repeats_the_end_of_the_previous_chunk() {
+        let content = paragraphs();
+        let chunks = chunk(parse(Format::Text, &content), &options(250, 120));
+        assert!(chunks.len() >= 2);
+        for pair in chunks.windows(2) {
+            assert!(pair[1].start < pair[0].end, "no overlap between chunks");
+            let repeated = &content[pair[1].start..pair[0].end];
+            assert!(pair[0].text.contains(repeated.trim()));
+            assert!(pair[1].text.contains(repeated.trim()));
+        }
+    }
+
+    #[test]
+    fn long_paragraphs_are_cut_at_sentences() {
+        let sentence = "This sentence is exactly about fifty bytes long. ";
+        let content = sentence.repeat(10);
+        let chunks = chunk(parse(Format::Text, &content), &options(120, 0));
+        assert!(chunks.len() >= 4);
+        for chunk in &chunks {
+            assert!(chunk.text.len() <= 120);
+            assert!(chunk.text.ends_with('.'), "cut inside a sentence: {:?}", chunk.text);
+            assert_eq!(&content[chunk.start..chunk.end], chunk.text);
+        }
+    }
+
+    #[test]
+    fn headings_start_chunks_and_are_repeated() {
+        let content = format!("# Guide\n\n## Setup\n\n{}\n\n## Usage\n\nRun it.", paragraphs());
+        let chunks = chunk(parse(Format::Markdown, &content), &options(250, 0));
+        let usage = chunks.last().unwrap();
+        assert_eq!(usage.text, "Guide > Usage\n\nRun it.");
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.text.starts_with("Guide > Setup\n\n"));
+        }
+    }
+
+    #[test]
+    fn code_blocks_are_kept_whole() {
+        let code = format!("```\n{}```", "let value = compute(); // step\n".repeat(10));
+        let content = format!("Intro.\n\n{}\n\nOutro.", code);
+        let chunks = chunk(parse(Format::Markdown, &content), &options(200, 50));
+        assert!(chunks.iter().any(|c| c.text == code));
+    }
+
+    #[test]
+    fn line_numbers_of_offsets() {
+        let content = "one\ntwo\n\nfour";
+        assert_eq!(line_at(content, 0), 1);
+        assert_eq!(line_at(content, 4), 2);
+        assert_eq!(line_at(content, content.find("four").unwrap()), 4);
+        assert_eq!(line_at(content, 1000), 4);
+    }
+}
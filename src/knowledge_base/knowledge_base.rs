@@ -1,93 +1,591 @@
 // src/knowledge_base/knowledge_base.rs
-use serde::Deserialize;
-use std::fs;
-use tokio::fs as tokio_fs;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Serialize, Deserialize};
 use serde_json;
+use sha2::{Digest, Sha256};
+use crate::crypto;
+use crate::database::Database;
+use super::embedding::{self, EMBEDDING_MODEL};
+use super::index::Bm25Index;
 
-#[derive(Debug, Deserialize, Clone)]
+/// Current version of the knowledge base JSON format.
+pub const KNOWLEDGE_BASE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct KnowledgeEntry {
+    #[serde(default)]
+    pub id: String,
     pub keywords: Vec<String>,
     pub content: String,
+    #[serde(default)]
+    pub source: String,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Where an ingested entry was cut from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<DocumentSpan>,
+    /// Where the content came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Location of a chunk in the document it was ingested from.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DocumentSpan {
+    pub path: String,
+    /// Byte offsets of the chunk in the file
+    pub start: usize,
+    pub end: usize,
+    /// SHA-256 of the whole file when it was ingested
+    pub hash: String,
+}
+
+/// Where a piece of knowledge came from.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Provenance {
+    /// Read from a file; `line` is 1-based when known
+    File { path: String, line: Option<usize> },
+    /// Said by a user in a conversation
+    Conversation { conversation_id: Option<i64>, user_id: String },
+    /// Written by a model in answer to a user
+    Model { conversation_id: Option<i64>, user_id: String, model: String },
+    /// Added by hand, e.g. with `kb add`
+    Manual { user_id: String },
+}
+
+impl Provenance {
+    /// The conversation the knowledge was learned in, if any.
+    pub fn conversation_id(&self) -> Option<i64> {
+        match self {
+            Provenance::Conversation { conversation_id, .. } | Provenance::Model { conversation_id, .. } => *conversation_id,
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // "conversation #12" or just "a conversation" if it was never saved
+        let conversation = |id: &Option<i64>| match id {
+            Some(id) => format!("conversation #{}", id),
+            None => "a conversation".to_string(),
+        };
+        match self {
+            Provenance::File { path, line: Some(line) } => write!(f, "{}:{}", path, line),
+            Provenance::File { path, line: None } => write!(f, "{}", path),
+            Provenance::Conversation { conversation_id, user_id } => {
+                write!(f, "{} in {}", user_id, conversation(conversation_id))
+            }
+            Provenance::Model { conversation_id, user_id, model } => {
+                write!(f, "{} (model) answering {} in {}", model, user_id, conversation(conversation_id))
+            }
+            Provenance::Manual { user_id } => write!(f, "added by {}", user_id),
+        }
+    }
+}
+
+impl KnowledgeEntry {
+    pub fn new(keywords: Vec<String>, content: &str, source: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: String::new(),
+            keywords,
+            content: content.to_string(),
+            source: source.to_string(),
+            created_at: now,
+            updated_at: now,
+            tags: Vec::new(),
+            document: None,
+            provenance: None,
+        }
+    }
+
+    /// Provenance for citing the entry, falling back to its source for
+    /// entries stored before provenance was recorded.
+    pub fn origin(&self) -> String {
+        match &self.provenance {
+            Some(provenance) => provenance.to_string(),
+            None => self.source.clone(),
+        }
+    }
+}
+
+/// A knowledge base hit with its relevance score.
+#[derive(Debug, Clone)]
+pub struct RetrievedEntry {
+    pub entry: KnowledgeEntry,
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SearchMode {
+    /// Full-text BM25 ranking only
+    Keyword,
+    /// Similarity of hashed word and trigram vectors only
+    Vector,
+    /// Both, merged with reciprocal rank fusion
+    Hybrid,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    pub mode: SearchMode,
+    pub top_k: usize,
+    /// Weight of each ranking in hybrid mode
+    pub keyword_weight: f32,
+    pub vector_weight: f32,
+    /// Keyword hits scoring below this BM25 score are dropped
+    pub min_keyword_score: f32,
+    /// Vector hits less similar than this are dropped
+    pub min_similarity: f32,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            mode: SearchMode::Hybrid,
+            top_k: 5,
+            keyword_weight: 1.0,
+            vector_weight: 1.0,
+            min_keyword_score: 0.0,
+            min_similarity: 0.2,
+        }
+    }
 }
 
+// Reciprocal rank fusion constant; damps the influence of the top ranks
+const RRF_K: f32 = 60.0;
+
+// Layout of knowledge base JSON files
+#[derive(Serialize, Deserialize)]
+struct KnowledgeBaseFile {
+    version: u32,
+    entries: Vec<KnowledgeEntry>,
+}
+
+// Entry ids with their trigram vectors
+type Vectors = Arc<Vec<(String, Vec<f32>)>>;
+
+// Metadata keys: set once the seed file was first loaded, and the hash of
+// the seed file as last synced
+const SEEDED_KEY: &str = "kb_seeded_from";
+const SEED_HASH_KEY: &str = "kb_seed_hash";
+// Ids of the entries the seed file held at the last sync
+const SEED_IDS_KEY: &str = "kb_seed_ids";
+
+/// The knowledge base, stored in the database. The JSON seed file is synced
+/// into it whenever it changes; other JSON files are for import/export.
 #[derive(Clone)]
 pub struct KnowledgeBaseHandler {
-    knowledge_base: Vec<KnowledgeEntry>,
-    file_path: String,
+    db: Database,
+    index: Bm25Index,
+    // Trigram vectors by entry id, loaded on first vector search and dropped on writes
+    vectors: Arc<RwLock<Option<Vectors>>>,
 }
 
 impl KnowledgeBaseHandler {
-    pub fn new(file_path: &str) -> Self {
-        // Load the knowledge base from the file
-        let knowledge_base = fs::read_to_string(file_path)
-            .map_err(|e| {
-                eprintln!("Failed to read knowledge base file: {}", e);
-                std::process::exit(1);
-            })
-            .and_then(|data| {
-                serde_json::from_str(&data).map_err(|e| {
-                    eprintln!("Failed to parse knowledge base file: {}", e);
-                    std::process::exit(1);
+    /// Uses whatever the database holds, without seeding it.
+    pub fn with_database(db: Database) -> Self {
+        Self { index: Bm25Index::new(db.clone()), db, vectors: Arc::new(RwLock::new(None)) }
+    }
+
+    /// Opens the knowledge base and brings in changes to `seed_path`. A
+    /// bad seed file is reported but does not stop the knowledge base
+    /// from opening.
+    pub async fn new(db: Database, seed_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let handler = Self::with_database(db);
+
+        // Seeded before file changes were tracked: assume it is in step
+        let untracked = handler.db.get_metadata(SEED_HASH_KEY.to_string()).await?.is_none()
+            && handler.db.get_metadata(SEEDED_KEY.to_string()).await?.is_some();
+        if untracked && Path::new(seed_path).exists() {
+            handler.db.set_metadata(SEED_HASH_KEY.to_string(), file_hash(seed_path)?).await?;
+        }
+        // Synced before its ids were kept: the file's entries are the ones
+        // it holds now
+        let ids_untracked = handler.db.get_metadata(SEED_IDS_KEY.to_string()).await?.is_none()
+            && handler.db.get_metadata(SEED_HASH_KEY.to_string()).await?.is_some();
+        if ids_untracked {
+            if let Ok(entries) = Self::read_json(seed_path) {
+                let stored: HashSet<String> = handler.entries().await?.into_iter().map(|e| e.id).collect();
+                let mut ids: Vec<String> = entries.into_iter().map(|e| e.id).filter(|id| stored.contains(id)).collect();
+                ids.sort();
+                handler.db.set_metadata(SEED_IDS_KEY.to_string(), serde_json::to_string(&ids)?).await?;
+            }
+        }
+
+        match handler.sync_file(seed_path).await {
+            Ok((0, 0, 0)) => {}
+            Ok((added, updated, removed)) => {
+                info!("Synced {}: {} entries added, {} updated, {} removed", seed_path, added, updated, removed)
+            }
+            Err(e) => error!("Knowledge base file {} is invalid, keeping the stored entries: {}", seed_path, e),
+        }
+        handler.db.set_metadata(SEEDED_KEY.to_string(), seed_path.to_string()).await?;
+
+        Ok(handler)
+    }
+
+    /// Brings changes to the seed file at `path` into the knowledge base,
+    /// unless it is unchanged since the last sync. Returns how many entries
+    /// were added, updated and removed.
+    pub async fn sync_file(&self, path: &str) -> Result<(usize, usize, usize), Box<dyn std::error::Error>> {
+        if !Path::new(path).exists() {
+            return Ok((0, 0, 0));
+        }
+        let hash = file_hash(path)?;
+        if self.db.get_metadata(SEED_HASH_KEY.to_string()).await?.as_deref() == Some(hash.as_str()) {
+            return Ok((0, 0, 0));
+        }
+
+        let counts = self.sync_json(path).await?;
+        self.db.set_metadata(SEED_HASH_KEY.to_string(), hash).await?;
+        Ok(counts)
+    }
+
+    /// Upserts the entries of a JSON file: entries whose id is known are
+    /// overwritten where they differ, the others are added unless their
+    /// content is already present. Entries the file held at the last sync
+    /// and no longer does are deleted; entries from anywhere else are
+    /// never touched. Returns how many were added, updated and removed.
+    pub async fn sync_json(&self, path: &str) -> Result<(usize, usize, usize), Box<dyn std::error::Error>> {
+        let entries = Self::read_json(path)?;
+        let existing: HashMap<String, KnowledgeEntry> = self.entries().await?.into_iter().map(|e| (e.id.clone(), e)).collect();
+        let mut contents: HashSet<String> = existing.values().map(|e| e.content.clone()).collect();
+
+        let previous: HashSet<String> = match self.db.get_metadata(SEED_IDS_KEY.to_string()).await? {
+            Some(ids) => serde_json::from_str(&ids)?,
+            None => HashSet::new(),
+        };
+
+        let mut synced = HashSet::new();
+        let mut updates = Vec::new();
+        let mut new_entries = Vec::new();
+        for entry in entries {
+            match existing.get(&entry.id) {
+                Some(current) => {
+                    synced.insert(entry.id.clone());
+                    let changed = current.content != entry.content
+                        || current.keywords != entry.keywords
+                        || current.tags != entry.tags
+                        || current.source != entry.source;
+                    if changed {
+                        updates.push(KnowledgeEntry {
+                            created_at: current.created_at,
+                            updated_at: Utc::now(),
+                            ..entry
+                        });
+                    }
+                }
+                None if contents.insert(entry.content.clone()) => new_entries.push(entry),
+                None => {}
+            }
+        }
+
+        let removed: Vec<String> = previous.into_iter().filter(|id| !synced.contains(id) && existing.contains_key(id)).collect();
+
+        let counts = (new_entries.len(), updates.len(), removed.len());
+        if !updates.is_empty() || !removed.is_empty() {
+            self.db.kb_apply(updates, removed).await?;
+        }
+        if !new_entries.is_empty() {
+            synced.extend(self.db.kb_insert(new_entries).await?.into_iter().map(|e| e.id));
+        }
+        let mut synced: Vec<String> = synced.into_iter().collect();
+        synced.sort();
+        self.db.set_metadata(SEED_IDS_KEY.to_string(), serde_json::to_string(&synced)?).await?;
+        self.invalidate();
+        Ok(counts)
+    }
+
+    /// Drops cached search data, for when another process changed the database.
+    pub fn reload(&self) {
+        self.invalidate();
+    }
+
+    /// Reads a knowledge base JSON file in the current or an older layout.
+    pub fn read_json(path: &str) -> Result<Vec<KnowledgeEntry>, Box<dyn std::error::Error>> {
+        let data = crypto::read_store(path)?;
+        let value: serde_json::Value = serde_json::from_str(&data)?;
+        let mut entries = match value {
+            // Version 0: a bare array of curated {keywords, content}
+            serde_json::Value::Array(_) => {
+                let mut entries: Vec<KnowledgeEntry> = serde_json::from_value(value)?;
+                for entry in entries.iter_mut().filter(|e| e.source.is_empty()) {
+                    entry.source = "curated".to_string();
+                }
+                entries
+            }
+            serde_json::Value::Object(ref map) if map.contains_key("version") => {
+                let file: KnowledgeBaseFile = serde_json::from_value(value)?;
+                if file.version > KNOWLEDGE_BASE_VERSION {
+                    return Err(format!(
+                        "knowledge base version {} is newer than supported version {}",
+                        file.version, KNOWLEDGE_BASE_VERSION
+                    ).into());
+                }
+                file.entries
+            }
+            // Written by the old add_entry: an object of topic -> content
+            serde_json::Value::Object(map) => map
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    value.as_str().map(|content| KnowledgeEntry::new(vec![key.clone()], content, "learned"))
                 })
-            })
-            .unwrap_or_else(|_| vec![]);
+                .collect(),
+            _ => return Err("knowledge base must be a JSON object or array".into()),
+        };
 
-        Self {
-            knowledge_base,
-            file_path: file_path.to_string(),
+        // Entries that don't say where they came from came from this file
+        for entry in entries.iter_mut().filter(|e| e.provenance.is_none()) {
+            entry.provenance = Some(Provenance::File { path: path.to_string(), line: None });
         }
+        Ok(entries)
     }
 
-    pub fn retrieve_information(&self, query: &str) -> String {
-        // Tokenize the query into words
-        let query_words: Vec<_> = query.split_whitespace().map(|s| s.to_lowercase()).collect();
+    /// Loads a JSON file. With `replace` it becomes the whole knowledge
+    /// base; otherwise entries whose content is already present are
+    /// skipped. Returns how many entries were added.
+    pub async fn import_json(&self, path: &str, replace: bool) -> Result<usize, Box<dyn std::error::Error>> {
+        let entries = Self::read_json(path)?;
+        if replace {
+            let count = entries.len();
+            self.db.kb_replace_all(entries).await?;
+            self.invalidate();
+            return Ok(count);
+        }
 
-        // Find entries with matching keywords
-        let relevant_entries: Vec<_> = self
-            .knowledge_base
-            .iter()
-            .filter(|entry| {
-                entry
-                    .keywords
-                    .iter()
-                    .any(|kw| query_words.contains(&kw.to_lowercase()))
-            })
-            .collect();
+        self.merge_entries(entries).await
+    }
 
-        // Combine the content of relevant entries
-        relevant_entries
-            .iter()
-            .map(|entry| entry.content.clone())
-            .collect::<Vec<_>>()
-            .join("\n")
-    }
-
-    pub async fn get_entry(&self, key: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
-        let content = tokio_fs::read_to_string(&self.file_path).await?;
-        let data: serde_json::Value = serde_json::from_str(&content)?;
-        
-        if let Some(value) = data.get(key) {
-            if let Some(str_value) = value.as_str() {
-                return Ok(Some(str_value.to_string()));
+    /// Adds the entries whose content is not present yet. Returns how
+    /// many were added.
+    pub async fn merge_entries(&self, entries: Vec<KnowledgeEntry>) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut seen: HashSet<String> = self.entries().await?.into_iter().map(|e| e.content).collect();
+        let new_entries: Vec<_> = entries.into_iter().filter(|e| seen.insert(e.content.clone())).collect();
+        if new_entries.is_empty() {
+            return Ok(0);
+        }
+        let count = self.db.kb_insert(new_entries).await?.len();
+        self.invalidate();
+        Ok(count)
+    }
+
+    /// Writes every entry to a JSON file, encrypted like the other stores
+    /// when a key is configured. Returns how many there were.
+    pub async fn export_json(&self, path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let entries = self.entries().await?;
+        let count = entries.len();
+        let serialized = serde_json::to_string_pretty(&KnowledgeBaseFile {
+            version: KNOWLEDGE_BASE_VERSION,
+            entries,
+        })?;
+        crypto::write_store(path, &serialized)?;
+        Ok(count)
+    }
+
+    /// Entries most relevant to the query by keyword ranking, trigram
+    /// vector similarity or both. Hybrid scores are reciprocal rank fusion scores,
+    /// the others are BM25 scores or cosine similarities.
+    pub async fn search(&self, query: &str, options: SearchOptions) -> Result<Vec<RetrievedEntry>, Box<dyn std::error::Error>> {
+        if options.top_k == 0 {
+            return Ok(Vec::new());
+        }
+        // Each ranking contributes more candidates than needed, for fusion
+        let depth = match options.mode {
+            SearchMode::Hybrid => options.top_k.saturating_mul(4).max(20),
+            _ => options.top_k,
+        };
+
+        let keyword_hits = if options.mode != SearchMode::Vector {
+            self.index.search(query, depth, options.min_keyword_score).await?
+        } else {
+            Vec::new()
+        };
+        let vector_hits = if options.mode != SearchMode::Keyword {
+            self.vector_search(query, depth, options.min_similarity).await?
+        } else {
+            Vec::new()
+        };
+
+        let ranked: Vec<(String, f32)> = match options.mode {
+            SearchMode::Keyword => keyword_hits.iter().map(|(e, score)| (e.id.clone(), *score)).collect(),
+            SearchMode::Vector => vector_hits,
+            SearchMode::Hybrid => {
+                let mut fused: HashMap<String, f32> = HashMap::new();
+                for (rank, (entry, _)) in keyword_hits.iter().enumerate() {
+                    *fused.entry(entry.id.clone()).or_default() += options.keyword_weight / (RRF_K + rank as f32 + 1.0);
+                }
+                for (rank, (id, _)) in vector_hits.iter().enumerate() {
+                    *fused.entry(id.clone()).or_default() += options.vector_weight / (RRF_K + rank as f32 + 1.0);
+                }
+                let mut fused: Vec<_> = fused.into_iter().collect();
+                fused.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+                fused
+            }
+        };
+
+        let mut known: HashMap<String, KnowledgeEntry> = keyword_hits.into_iter().map(|(e, _)| (e.id.clone(), e)).collect();
+        let mut results = Vec::new();
+        for (id, score) in ranked.into_iter().take(options.top_k) {
+            let entry = match known.remove(&id) {
+                Some(entry) => Some(entry),
+                None => self.db.kb_get(id).await?,
+            };
+            if let Some(entry) = entry {
+                results.push(RetrievedEntry { entry, score });
             }
         }
-        
-        Ok(None)
+        Ok(results)
     }
 
-    pub async fn add_entry(&self, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let content = tokio_fs::read_to_string(&self.file_path).await?;
-        let mut data: serde_json::Value = serde_json::from_str(&content)?;
-        
-        if let serde_json::Value::Object(ref mut map) = data {
-            map.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+    // Brute-force cosine similarity over every stored vector
+    async fn vector_search(&self, query: &str, limit: usize, min_similarity: f32) -> Result<Vec<(String, f32)>, Box<dyn std::error::Error>> {
+        let query_vector = embedding::embed(query);
+        let vectors = self.load_vectors().await?;
+
+        let mut hits: Vec<(String, f32)> = vectors
+            .iter()
+            .map(|(id, vector)| (id.clone(), embedding::similarity(&query_vector, vector)))
+            .filter(|(_, similarity)| *similarity >= min_similarity)
+            .collect();
+        hits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    // Vectorizes entries that have no vector yet, then returns all vectors
+    async fn load_vectors(&self) -> Result<Vectors, Box<dyn std::error::Error>> {
+        let missing = self.db.kb_missing_embeddings(EMBEDDING_MODEL.to_string()).await?;
+        if !missing.is_empty() {
+            let vectors = missing
+                .iter()
+                .map(|(seq, entry)| {
+                    let text = format!("{} {}", entry.keywords.join(" "), entry.content);
+                    (*seq, embedding::to_bytes(&embedding::embed(&text)))
+                })
+                .collect();
+            self.db.kb_store_embeddings(EMBEDDING_MODEL.to_string(), vectors).await?;
+            self.invalidate();
+        }
+
+        if let Some(vectors) = self.vectors.read().unwrap().as_ref() {
+            return Ok(vectors.clone());
         }
-        
-        tokio_fs::write(&self.file_path, serde_json::to_string_pretty(&data)?).await?;
+        let vectors: Vec<_> = self.db
+            .kb_embeddings(EMBEDDING_MODEL.to_string())
+            .await?
+            .into_iter()
+            .map(|(id, bytes)| (id, embedding::from_bytes(&bytes)))
+            .collect();
+        let vectors = Arc::new(vectors);
+        *self.vectors.write().unwrap() = Some(vectors.clone());
+        Ok(vectors)
+    }
+
+    // Cached vectors are stale after any write
+    fn invalidate(&self) {
+        *self.vectors.write().unwrap() = None;
+    }
+
+    pub async fn entries(&self) -> Result<Vec<KnowledgeEntry>, Box<dyn std::error::Error>> {
+        Ok(self.db.kb_entries().await?)
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<KnowledgeEntry>, Box<dyn std::error::Error>> {
+        Ok(self.db.kb_get(id.to_string()).await?)
+    }
+
+    /// Adds a new entry and returns it with its assigned id.
+    pub async fn insert(&self, mut entry: KnowledgeEntry) -> Result<KnowledgeEntry, Box<dyn std::error::Error>> {
+        entry.id.clear();
+        let mut inserted = self.db.kb_insert(vec![entry]).await?;
+        self.invalidate();
+        Ok(inserted.remove(0))
+    }
+
+    /// Replaces the entry with the same id. Returns false if there is none.
+    pub async fn update(&self, mut entry: KnowledgeEntry) -> Result<bool, Box<dyn std::error::Error>> {
+        entry.updated_at = Utc::now();
+        let updated = self.db.kb_update(entry).await?;
+        self.invalidate();
+        Ok(updated)
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let deleted = self.db.kb_delete(id.to_string()).await?;
+        self.invalidate();
+        Ok(deleted)
+    }
+
+    /// Replaces all entries.
+    pub async fn save_entries(&self, entries: Vec<KnowledgeEntry>) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.kb_replace_all(entries).await?;
+        self.invalidate();
         Ok(())
     }
 
-    pub async fn update_entry(&self, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
-        self.add_entry(key, value).await
+    /// Drops every entry ingested from one of `paths` and adds `entries`
+    /// in their place. Returns how many entries were dropped.
+    pub async fn replace_documents(&self, paths: &HashSet<String>, entries: Vec<KnowledgeEntry>) -> Result<usize, Box<dyn std::error::Error>> {
+        let removed = self.db.kb_replace_documents(paths.iter().cloned().collect(), entries).await?;
+        self.invalidate();
+        Ok(removed)
+    }
+
+    /// Merges entries with the same content (ignoring case and spacing)
+    /// into the oldest one, which collects their keywords and tags. Returns
+    /// the kept id and the removed ids of each group.
+    pub async fn dedupe(&self, dry_run: bool) -> Result<Vec<(String, Vec<String>)>, Box<dyn std::error::Error>> {
+        let mut groups: Vec<(KnowledgeEntry, Vec<String>)> = Vec::new();
+        let mut by_content: HashMap<String, usize> = HashMap::new();
+        for entry in self.entries().await? {
+            let normalized = entry.content.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+            match by_content.get(&normalized) {
+                Some(&i) => {
+                    let (kept, removed) = &mut groups[i];
+                    for keyword in &entry.keywords {
+                        if !kept.keywords.iter().any(|k| k.eq_ignore_ascii_case(keyword)) {
+                            kept.keywords.push(keyword.clone());
+                        }
+                    }
+                    for tag in &entry.tags {
+                        if !kept.tags.contains(tag) {
+                            kept.tags.push(tag.clone());
+                        }
+                    }
+                    removed.push(entry.id);
+                }
+                None => {
+                    by_content.insert(normalized, groups.len());
+                    groups.push((entry, Vec::new()));
+                }
+            }
+        }
+
+        groups.retain(|(_, removed)| !removed.is_empty());
+        if !dry_run && !groups.is_empty() {
+            let now = Utc::now();
+            let updates = groups.iter().map(|(kept, _)| KnowledgeEntry { updated_at: now, ..kept.clone() }).collect();
+            let deletes = groups.iter().flat_map(|(_, removed)| removed.clone()).collect();
+            self.db.kb_apply(updates, deletes).await?;
+            self.invalidate();
+        }
+        Ok(groups.into_iter().map(|(kept, removed)| (kept.id, removed)).collect())
     }
-}
\ No newline at end of file
+
+}
+
+// SHA-256 of a file's bytes, to notice when it changed
+fn file_hash(path: &str) -> std::io::Result<String> {
+    Ok(format!("{:x}", Sha256::digest(std::fs::read(path)?)))
+}
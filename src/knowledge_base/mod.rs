@@ -1 +1,7 @@
+#[allow(clippy::module_inception)]
 pub mod knowledge_base;
+pub mod index;
+pub mod ingest;
+pub mod embedding;
+pub mod benchmark;
+pub mod cli;
@@ -0,0 +1,306 @@
+// src/learning/cache.rs
+// Recently used insights, one list per owner and topic, so a prompt
+// doesn't have to parse every stored insight again. The database is the
+// source of truth: a topic that isn't cached is loaded from there, and
+// the least recently used topics are dropped once the cache holds too
+// many or too much. Per owner it also keeps which topics have insights,
+// their promoted content and their graph, so a prompt doesn't scan for
+// those every time either.
+//
+// Loading happens without holding the cache, so every change bumps a
+// version: what was loaded before a change is not cached, as it may miss
+// what was stored in the meantime.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use super::Insight;
+use super::graph::Graph;
+
+// Owner and topic
+type TopicKey = (String, String);
+
+struct Entry {
+    insights: Vec<Insight>,
+    bytes: usize,
+    last_used: u64,
+}
+
+// What is known about one owner as a whole, each part loaded when first needed
+#[derive(Default)]
+struct OwnerIndex {
+    topics: Option<BTreeSet<String>>,
+    promoted: Option<Vec<Insight>>,
+    graph: Option<Graph>,
+}
+
+pub(super) struct InsightCache {
+    max_topics: usize,
+    max_bytes: usize,
+    bytes: usize,
+    // Bumped on every use, so entries can be ordered by it
+    clock: u64,
+    // Bumped on every change
+    version: u64,
+    entries: HashMap<TopicKey, Entry>,
+    // Last use to topic, least recent first
+    order: BTreeMap<u64, TopicKey>,
+    owners: HashMap<String, OwnerIndex>,
+}
+
+// Roughly what an insight takes up in memory
+fn size_of(insight: &Insight) -> usize {
+    let fact = insight.fact.as_ref().map_or(0, |f| f.subject.len() + f.predicate.len() + f.object.len());
+    std::mem::size_of::<Insight>() + insight.topic.len() + insight.context.len() + insight.source.len() + fact
+}
+
+impl InsightCache {
+    pub fn new(max_topics: usize, max_bytes: usize) -> Self {
+        Self {
+            max_topics,
+            max_bytes,
+            bytes: 0,
+            clock: 0,
+            version: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            owners: HashMap::new(),
+        }
+    }
+
+    /// Read before loading from the database, and handed back when caching
+    /// what was loaded.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The cached insights of `owner` on `topic`, marking them as used.
+    pub fn get(&mut self, owner: &str, topic: &str) -> Option<Vec<Insight>> {
+        let key = (owner.to_string(), topic.to_string());
+        let tick = self.tick();
+        let entry = self.entries.get_mut(&key)?;
+        self.order.remove(&entry.last_used);
+        entry.last_used = tick;
+        self.order.insert(tick, key);
+        Some(entry.insights.clone())
+    }
+
+    /// Caches everything stored for `owner` on `topic`, loaded at `version`.
+    pub fn insert(&mut self, owner: &str, topic: &str, insights: Vec<Insight>, version: u64) {
+        if version != self.version {
+            return;
+        }
+        let key = (owner.to_string(), topic.to_string());
+        self.remove(&key);
+        let tick = self.tick();
+        let bytes = insights.iter().map(size_of).sum();
+        self.bytes += bytes;
+        self.entries.insert(key.clone(), Entry { insights, bytes, last_used: tick });
+        self.order.insert(tick, key);
+        self.evict();
+    }
+
+    /// Adds a newly stored insight to its topic, if that topic is cached.
+    /// Uncached topics are loaded whole the next time they are needed.
+    pub fn add(&mut self, owner: &str, insight: &Insight) {
+        self.version += 1;
+        if let Some(topics) = self.owners.get_mut(owner).and_then(|o| o.topics.as_mut()) {
+            topics.insert(insight.topic.clone());
+        }
+        let key = (owner.to_string(), insight.topic.clone());
+        if let Some(entry) = self.entries.get_mut(&key) {
+            // Already there if the topic was loaded after it was stored
+            if entry.insights.iter().any(|i| i.timestamp == insight.timestamp && i.context == insight.context) {
+                return;
+            }
+            let bytes = size_of(insight);
+            entry.insights.push(insight.clone());
+            entry.bytes += bytes;
+            self.bytes += bytes;
+            self.evict();
+        }
+    }
+
+    /// Drops cached insights of `owner` that `remove` picks, e.g. ones
+    /// that were forgotten.
+    pub fn remove_where(&mut self, owner: &str, remove: impl Fn(&Insight) -> bool) {
+        self.version += 1;
+        for (_, entry) in self.entries.iter_mut().filter(|((o, _), _)| o == owner) {
+            entry.insights.retain(|i| !remove(i));
+            let bytes = entry.insights.iter().map(size_of).sum();
+            self.bytes = self.bytes - entry.bytes + bytes;
+            entry.bytes = bytes;
+        }
+        self.evict();
+    }
+
+    /// Drops whole topics holding an insight `stale` picks, so they are
+    /// loaded again with what the database has now.
+    pub fn invalidate_where(&mut self, stale: impl Fn(&Insight) -> bool) {
+        self.version += 1;
+        let keys: Vec<TopicKey> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.insights.iter().any(&stale))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    /// Topics `owner` has insights on, if known.
+    pub fn topics(&self, owner: &str) -> Option<Vec<String>> {
+        let topics = self.owners.get(owner)?.topics.as_ref()?;
+        Some(topics.iter().cloned().collect())
+    }
+
+    pub fn set_topics(&mut self, owner: &str, topics: Vec<String>, version: u64) {
+        if version == self.version {
+            self.owners.entry(owner.to_string()).or_default().topics = Some(topics.into_iter().collect());
+        }
+    }
+
+    /// Promoted content of `owner`, if loaded.
+    pub fn promoted(&self, owner: &str) -> Option<Vec<Insight>> {
+        self.owners.get(owner)?.promoted.clone()
+    }
+
+    pub fn set_promoted(&mut self, owner: &str, promoted: Vec<Insight>, version: u64) {
+        if version == self.version {
+            self.owners.entry(owner.to_string()).or_default().promoted = Some(promoted);
+        }
+    }
+
+    /// Drops the promoted content of `owner` after something was promoted.
+    pub fn forget_promoted(&mut self, owner: &str) {
+        self.version += 1;
+        if let Some(index) = self.owners.get_mut(owner) {
+            index.promoted = None;
+        }
+    }
+
+    /// The graph of `owner` alone, if loaded.
+    pub fn graph(&self, owner: &str) -> Option<Graph> {
+        self.owners.get(owner)?.graph.clone()
+    }
+
+    pub fn set_graph(&mut self, owner: &str, graph: Graph, version: u64) {
+        if version == self.version {
+            self.owners.entry(owner.to_string()).or_default().graph = Some(graph);
+        }
+    }
+
+    /// Drops the graph of `owner` after it changed.
+    pub fn forget_graph(&mut self, owner: &str) {
+        self.version += 1;
+        if let Some(index) = self.owners.get_mut(owner) {
+            index.graph = None;
+        }
+    }
+
+    /// Drops everything, e.g. after another process changed the database.
+    pub fn clear(&mut self) {
+        self.version += 1;
+        self.entries.clear();
+        self.order.clear();
+        self.owners.clear();
+        self.bytes = 0;
+    }
+
+    /// Cached topics and roughly how many bytes they take.
+    pub fn usage(&self) -> (usize, usize) {
+        (self.entries.len(), self.bytes)
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, key: &TopicKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.last_used);
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    // Drops least recently used topics until both limits are met
+    fn evict(&mut self) {
+        while self.entries.len() > self.max_topics || self.bytes > self.max_bytes {
+            let Some((_, key)) = self.order.pop_first() else { break };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.bytes;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insight(topic: &str, context: &str) -> Insight {
+        Insight {
+            topic: topic.to_string(),
+            context: context.to_string(),
+            confidence: 0.7,
+            source: "user_input".to_string(),
+            timestamp: chrono::Utc::now(),
+            provenance: None,
+            fact: None,
+            base_confidence: None,
+        }
+    }
+
+    fn cache_topic(cache: &mut InsightCache, topic: &str) {
+        let version = cache.version();
+        cache.insert("alice", topic, vec![insight(topic, topic)], version);
+    }
+
+    #[test]
+    fn least_recently_used_topic_goes_first() {
+        let mut cache = InsightCache::new(2, usize::MAX);
+        cache_topic(&mut cache, "rust");
+        cache_topic(&mut cache, "sqlite");
+        // Using rust makes sqlite the least recently used
+        assert!(cache.get("alice", "rust").is_some());
+        cache_topic(&mut cache, "tokio");
+        assert!(cache.get("alice", "sqlite").is_none());
+        assert!(cache.get("alice", "rust").is_some());
+        assert!(cache.get("alice", "tokio").is_some());
+        assert_eq!(cache.usage().0, 2);
+    }
+
+    #[test]
+    fn byte_limit_evicts_too() {
+        let size = size_of(&insight("rust", "rust"));
+        let mut cache = InsightCache::new(10, size * 2);
+        for topic in ["rust", "sqlite", "tokio"] {
+            cache_topic(&mut cache, topic);
+        }
+        assert!(cache.get("alice", "rust").is_none());
+        assert!(cache.usage().1 <= size * 2);
+    }
+
+    #[test]
+    fn stale_inserts_are_dropped() {
+        let mut cache = InsightCache::new(10, usize::MAX);
+        let version = cache.version();
+        // Stored while "rust" was being loaded, so the load may miss it
+        cache.add("alice", &insight("rust", "new"));
+        cache.insert("alice", "rust", vec![insight("rust", "old")], version);
+        assert!(cache.get("alice", "rust").is_none());
+
+        cache.set_topics("alice", vec!["rust".to_string()], version);
+        assert!(cache.topics("alice").is_none());
+    }
+
+    #[test]
+    fn removing_keeps_the_byte_count() {
+        let mut cache = InsightCache::new(10, usize::MAX);
+        let version = cache.version();
+        cache.insert("alice", "rust", vec![insight("rust", "keep"), insight("rust", "forget")], version);
+        cache.remove_where("alice", |i| i.context == "forget");
+        let kept = cache.get("alice", "rust").unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(cache.usage(), (1, size_of(&kept[0])));
+    }
+}
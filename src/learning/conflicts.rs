@@ -0,0 +1,275 @@
+// src/learning/conflicts.rs
+// Catches learned insights that contradict each other ("deploys are on
+// Tuesday" vs "deploys are on Thursday"), so the newer one doesn't just
+// sit next to the older with equal weight. Insights on the same topic
+// conflict when they say nearly the same thing but one is negated, or
+// they name different values (numbers, days, months).
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+use log::info;
+use crate::database::ConflictRecord;
+use crate::knowledge_base::embedding;
+use super::{Insight, LearningManager};
+
+// Texts this similar, apart from their values, are about the same thing
+const CONFLICT_SIMILARITY: f32 = 0.7;
+
+const NEGATIONS: &[&str] = &["cannot", "never", "no", "nobody", "none", "not", "nothing", "nowhere"];
+const VALUE_WORDS: &[&str] = &[
+    "april", "august", "december", "false", "february", "friday", "january", "july", "june",
+    "march", "monday", "november", "october", "saturday", "september", "sunday",
+    "thursday", "true", "tuesday", "wednesday",
+];
+
+/// How a conflict between a new insight and an older one is settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// The newer insight wins
+    #[default]
+    Recency,
+    /// The more confident insight wins, the newer one on a tie
+    Confidence,
+    /// Both are kept until the user picks one with /conflicts
+    Ask,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "recency" => Ok(ConflictPolicy::Recency),
+            "confidence" => Ok(ConflictPolicy::Confidence),
+            "ask" => Ok(ConflictPolicy::Ask),
+            _ => Err(format!("Unknown conflict policy '{}': use recency, confidence or ask", s)),
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictPolicy::Recency => write!(f, "recency"),
+            ConflictPolicy::Confidence => write!(f, "confidence"),
+            ConflictPolicy::Ask => write!(f, "ask"),
+        }
+    }
+}
+
+impl ConflictPolicy {
+    // Some(true) when the new insight should replace the old one, None
+    // when the user decides. Corrections are the user's own word, so they
+    // beat anything else whatever the policy.
+    fn prefers_new(&self, old: &Insight, new: &Insight) -> Option<bool> {
+        let (old_corrects, new_corrects) = (old.source == "correction", new.source == "correction");
+        match self {
+            ConflictPolicy::Ask => None,
+            _ if old_corrects != new_corrects => Some(new_corrects),
+            ConflictPolicy::Recency => Some(new.timestamp >= old.timestamp),
+            ConflictPolicy::Confidence if new.confidence != old.confidence => Some(new.confidence > old.confidence),
+            ConflictPolicy::Confidence => Some(new.timestamp >= old.timestamp),
+        }
+    }
+}
+
+/// Why two insights contradict each other, if they do.
+pub(super) fn conflict(a: &str, b: &str) -> Option<&'static str> {
+    let (values_a, values_b) = (values(a), values(b));
+    let similarity = embedding::similarity(&embedding::embed(&without_values(a)), &embedding::embed(&without_values(b)));
+    if similarity < CONFLICT_SIMILARITY {
+        return None;
+    }
+
+    if negated(a) != negated(b) {
+        Some("negation")
+    } else if !values_a.is_empty() && !values_b.is_empty() && values_a != values_b {
+        Some("different values")
+    } else {
+        None
+    }
+}
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'').to_lowercase())
+}
+
+// An odd number of negations, so "not never" reads as positive
+fn negated(text: &str) -> bool {
+    words(text)
+        .filter(|w| w.ends_with("n't") || NEGATIONS.binary_search(&w.as_str()).is_ok())
+        .count()
+        % 2
+        == 1
+}
+
+fn is_value(word: &str) -> bool {
+    word.chars().any(|c| c.is_ascii_digit()) || VALUE_WORDS.binary_search(&word).is_ok()
+}
+
+fn values(text: &str) -> BTreeSet<String> {
+    words(text).filter(|w| is_value(w)).collect()
+}
+
+fn without_values(text: &str) -> String {
+    words(text).filter(|w| !is_value(w)).collect::<Vec<_>>().join(" ")
+}
+
+// Only what the user said or the model extracted is checked; heuristic
+// insights (whole inputs, response sentences) are too loose to compare
+fn is_checked(insight: &Insight) -> bool {
+    matches!(insight.source.as_str(), "fact" | "preference" | "correction")
+}
+
+impl LearningManager {
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        self.conflict_policy
+    }
+
+    /// Compares a new insight about to be stored under `owner` with the
+    /// ones already there on its topic, records any conflicts and settles
+    /// them by policy. Returns false if the new insight lost and should
+    /// not be stored.
+    pub(super) async fn settle_conflicts(
+        &self,
+        user_id: &str,
+        owner: &str,
+        key: &str,
+        insight: &Insight,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        if !is_checked(insight) {
+            return Ok(true);
+        }
+
+        let prefix = format!("insight:{}:", insight.topic);
+        for record in self.db.get_knowledge_by_prefix(owner.to_string(), prefix).await? {
+            let Ok(old) = serde_json::from_str::<Insight>(&record.value) else { continue };
+            if record.key == key || !is_checked(&old) {
+                continue;
+            }
+            let Some(reason) = conflict(&old.context, &insight.context) else { continue };
+
+            let keep_new = self.conflict_policy.prefers_new(&old, insight);
+            let status = match keep_new {
+                None => "open",
+                Some(true) => "kept_new",
+                Some(false) => "kept_old",
+            };
+            self.db
+                .conflict_add(ConflictRecord {
+                    id: 0,
+                    user_id: user_id.to_string(),
+                    owner: owner.to_string(),
+                    topic: insight.topic.clone(),
+                    old_key: record.key.clone(),
+                    old_text: old.context.clone(),
+                    new_key: key.to_string(),
+                    new_text: insight.context.clone(),
+                    reason: reason.to_string(),
+                    status: status.to_string(),
+                })
+                .await?;
+            info!("Learned insight conflicts with {} ({}): {}", record.key, reason, status);
+
+            match keep_new {
+                Some(true) => self.forget_insight(owner, &old.context).await?,
+                Some(false) => return Ok(false),
+                None => {}
+            }
+        }
+        Ok(true)
+    }
+
+    /// Conflicts waiting for `user_id` to pick a side.
+    pub async fn open_conflicts(&self, user_id: &str) -> Result<Vec<ConflictRecord>, Box<dyn std::error::Error>> {
+        Ok(self.db.conflicts_open(user_id.to_string()).await?)
+    }
+
+    /// Keeps one side of an open conflict and forgets the other. Returns
+    /// false if there is no such open conflict for this user.
+    pub async fn resolve_conflict(&self, user_id: &str, id: i64, keep_new: bool) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(conflict) = self.db.conflict_get(id).await?.filter(|c| c.status == "open" && c.user_id == user_id) else {
+            return Ok(false);
+        };
+        let (status, loser_text) = if keep_new {
+            ("kept_new", conflict.old_text)
+        } else {
+            ("kept_old", conflict.new_text)
+        };
+        if !self.db.conflict_resolve(id, status).await? {
+            return Ok(false);
+        }
+
+        self.forget_insight(&conflict.owner, &loser_text).await.map_err(|e| e as Box<dyn std::error::Error>)?;
+        Ok(true)
+    }
+
+    // Drops an insight that lost a conflict, with its copies under other topics
+    pub(super) async fn forget_insight(
+        &self,
+        owner: &str,
+        text: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for record in self.db.get_knowledge_by_prefix(owner.to_string(), "insight:".to_string()).await? {
+            let Ok(insight) = serde_json::from_str::<Insight>(&record.value) else { continue };
+            if insight.context == text {
+                self.db.delete_knowledge(owner.to_string(), record.key).await?;
+                if let Some(fact) = &insight.fact {
+                    self.remove_from_graph(owner, fact).await?;
+                }
+            }
+        }
+
+        self.cache.lock().await.remove_where(owner, |i| i.context == text);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_lists_are_sorted() {
+        // Both are looked up with a binary search
+        assert!(NEGATIONS.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(VALUE_WORDS.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn different_days_conflict() {
+        assert_eq!(conflict("Deploys are on Tuesday", "Deploys are on Thursday"), Some("different values"));
+        assert_eq!(conflict("The limit is 10 requests", "The limit is 20 requests"), Some("different values"));
+    }
+
+    #[test]
+    fn negation_conflicts() {
+        assert_eq!(conflict("The cache is shared", "The cache is not shared"), Some("negation"));
+        assert_eq!(conflict("Tests run in parallel", "Tests don't run in parallel"), Some("negation"));
+    }
+
+    #[test]
+    fn agreeing_or_unrelated_texts_dont_conflict() {
+        assert_eq!(conflict("Deploys are on Tuesday", "Deploys are on Tuesday."), None);
+        assert_eq!(conflict("Deploys are on Tuesday", "The cafeteria serves pasta"), None);
+        // Only one side names a value, so there is nothing to disagree on
+        assert_eq!(conflict("Deploys are on Tuesday", "Deploys are scheduled"), None);
+    }
+
+    #[test]
+    fn negations_are_counted() {
+        assert!(negated("It is not done"));
+        assert!(negated("It isn't done"));
+        assert!(!negated("It is done"));
+        // Two negations cancel out
+        assert!(!negated("It is not never done"));
+    }
+
+    #[test]
+    fn values_are_numbers_days_and_months() {
+        let found: Vec<_> = values("On Tuesday, 3 March, it was true; version v2 shipped").into_iter().collect();
+        assert_eq!(found, ["3", "march", "true", "tuesday", "v2"]);
+        assert!(values("Nothing to see here").is_empty());
+    }
+}
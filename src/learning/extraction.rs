@@ -0,0 +1,172 @@
+// src/learning/extraction.rs
+// Asks the model to pull structured knowledge out of an exchange: facts
+// as subject/predicate/object, the user's preferences, and corrections.
+// Costs one extra completion per exchange, so it can be turned off.
+use serde::{Serialize, Deserialize};
+use crate::keyphrase;
+use crate::knowledge_base::knowledge_base::Provenance;
+use super::Insight;
+use super::profile::UserProfile;
+
+// Extracted items the model is less sure about than this are dropped
+const MIN_CONFIDENCE: f32 = 0.5;
+
+/// A fact as subject, predicate and object, e.g. ("Tokio", "is", "an async runtime").
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Fact {
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+    /// What kind of entity the subject is: person, project, service or tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject_kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_kind: Option<String>,
+}
+
+// Entity kinds kept in the graph; anything else the model says is dropped
+const ENTITY_KINDS: &[&str] = &["person", "project", "service", "tool"];
+
+fn entity_kind(kind: Option<String>) -> Option<String> {
+    kind.map(|k| k.trim().to_lowercase()).filter(|k| ENTITY_KINDS.contains(&k.as_str()))
+}
+
+// The JSON the model is asked to reply with
+#[derive(Deserialize)]
+struct Extraction {
+    #[serde(default)]
+    facts: Vec<ExtractedFact>,
+    #[serde(default)]
+    preferences: Vec<Statement>,
+    #[serde(default)]
+    corrections: Vec<Statement>,
+    #[serde(default)]
+    profile: ExtractedProfile,
+}
+
+// Read leniently: a value the model made up is skipped, not an error
+#[derive(Deserialize, Default)]
+struct ExtractedProfile {
+    name: Option<String>,
+    expertise: Option<String>,
+    verbosity: Option<String>,
+    language: Option<String>,
+    #[serde(default)]
+    interests: Vec<String>,
+    #[serde(default)]
+    projects: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ExtractedFact {
+    subject: String,
+    predicate: String,
+    object: String,
+    subject_type: Option<String>,
+    object_type: Option<String>,
+    #[serde(default)]
+    confidence: f32,
+}
+
+#[derive(Deserialize)]
+struct Statement {
+    text: String,
+    #[serde(default)]
+    confidence: f32,
+}
+
+pub(super) fn prompt(user_input: &str, ai_response: &str) -> String {
+    format!(
+        "Extract knowledge from this exchange between a user and an assistant. \
+        Reply with JSON only, in this form:\n\
+        {{\"facts\": [{{\"subject\": \"...\", \"subject_type\": \"tool\", \"predicate\": \"...\", \"object\": \"...\", \"object_type\": null, \"confidence\": 0.9}}], \
+        \"preferences\": [{{\"text\": \"...\", \"confidence\": 0.9}}], \
+        \"corrections\": [{{\"text\": \"...\", \"confidence\": 0.9}}], \
+        \"profile\": {{\"name\": null, \"expertise\": null, \"verbosity\": null, \"language\": null, \"interests\": [], \"projects\": []}}}}\n\
+        facts: things the exchange states as true about the world, short and self-contained.\n\
+        subject_type, object_type: person, project, service or tool when the subject or object is one, null otherwise.\n\
+        preferences: what the user likes or wants, or how they want to be answered.\n\
+        corrections: things the user says were wrong, written as the corrected fact.\n\
+        confidence: 0 to 1, how sure you are the item is correct and worth remembering.\n\
+        profile: only what the user revealed about themselves, null or empty otherwise: \
+        their name, expertise (beginner, intermediate or expert), verbosity they want \
+        (brief, normal or detailed), the language they want answers in, their interests \
+        and the projects they work on.\n\
+        Use empty lists when there is nothing to extract.\n\n\
+        User: {}\n\nAssistant: {}",
+        user_input, ai_response
+    )
+}
+
+/// Turns the model's reply into insights and what it says about the user.
+/// Facts come from the model, preferences and corrections from what the
+/// user said.
+pub(super) fn parse(reply: &str, said: &Provenance, generated: &Provenance) -> Result<(Vec<Insight>, UserProfile), serde_json::Error> {
+    // Models like to wrap JSON in code fences or a sentence
+    let json = match (reply.find('{'), reply.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => "{}",
+    };
+    let extraction: Extraction = serde_json::from_str(json)?;
+    let now = chrono::Utc::now();
+
+    let mut insights = Vec::new();
+    for extracted in extraction.facts {
+        let fact = Fact {
+            subject: extracted.subject.trim().to_string(),
+            predicate: extracted.predicate.trim().to_string(),
+            object: extracted.object.trim().to_string(),
+            subject_kind: entity_kind(extracted.subject_type),
+            object_kind: entity_kind(extracted.object_type),
+        };
+        if fact.subject.is_empty() || fact.object.is_empty() || extracted.confidence < MIN_CONFIDENCE {
+            continue;
+        }
+        insights.push(Insight {
+            topic: topic_of(&fact.subject),
+            context: format!("{} {} {}", fact.subject, fact.predicate, fact.object),
+            confidence: extracted.confidence.min(1.0),
+            source: "fact".to_string(),
+            timestamp: now,
+            provenance: Some(generated.clone()),
+            fact: Some(fact),
+            base_confidence: None,
+        });
+    }
+    for (source, statements) in [("preference", extraction.preferences), ("correction", extraction.corrections)] {
+        for statement in statements {
+            let text = statement.text.trim();
+            if text.is_empty() || statement.confidence < MIN_CONFIDENCE {
+                continue;
+            }
+            insights.push(Insight {
+                topic: topic_of(text),
+                context: text.to_string(),
+                confidence: statement.confidence.min(1.0),
+                source: source.to_string(),
+                timestamp: now,
+                provenance: Some(said.clone()),
+                fact: None,
+                base_confidence: None,
+            });
+        }
+    }
+
+    let extracted = extraction.profile;
+    let profile = UserProfile {
+        name: extracted.name.filter(|n| !n.trim().is_empty()),
+        expertise: extracted.expertise.and_then(|e| e.parse().ok()),
+        verbosity: extracted.verbosity.and_then(|v| v.parse().ok()),
+        language: extracted.language.filter(|l| !l.trim().is_empty()),
+        interests: extracted.interests,
+        projects: extracted.projects,
+        ..UserProfile::default()
+    };
+
+    Ok((insights, profile))
+}
+
+// The main keyphrase of a text, or all of it if it has none
+fn topic_of(text: &str) -> String {
+    keyphrase::extract(text, 1).pop().unwrap_or_else(|| text.trim().to_lowercase())
+}
@@ -0,0 +1,221 @@
+// src/learning/feedback.rs
+// Feedback on answers makes learning follow signal instead of taking in
+// everything: what was learned from a well-rated turn gains confidence,
+// what came from a poorly rated one loses it, and corrections are kept
+// as trusted facts.
+use std::collections::HashMap;
+use crate::knowledge_base::knowledge_base::Provenance;
+use crate::keyphrase;
+use super::{Fact, Insight, LearningManager};
+
+// Topics a correction is remembered under
+const CORRECTION_TOPICS: usize = 3;
+
+/// What a user thought of an answer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Feedback {
+    Up,
+    Down,
+    /// What the answer should have said
+    Correction(String),
+    /// Asked for another answer
+    Regenerate,
+}
+
+impl Feedback {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Feedback::Up => "up",
+            Feedback::Down => "down",
+            Feedback::Correction(_) => "correction",
+            Feedback::Regenerate => "regenerate",
+        }
+    }
+
+    /// Feedback of a stored kind; corrections come back without their text.
+    pub fn from_kind(kind: &str) -> Option<Feedback> {
+        match kind {
+            "up" => Some(Feedback::Up),
+            "down" => Some(Feedback::Down),
+            "correction" => Some(Feedback::Correction(String::new())),
+            "regenerate" => Some(Feedback::Regenerate),
+            _ => None,
+        }
+    }
+
+    // How far this moves the confidence of a turn's insights. Asking for
+    // another answer is a weaker signal than a thumbs down.
+    fn confidence_change(&self) -> f32 {
+        match self {
+            Feedback::Up => 0.2,
+            Feedback::Down | Feedback::Correction(_) => -0.3,
+            Feedback::Regenerate => -0.1,
+        }
+    }
+
+    /// Whether it takes what the answer said out of the review queue.
+    pub(super) fn rejects(&self) -> bool {
+        matches!(self, Feedback::Down | Feedback::Correction(_) | Feedback::Regenerate)
+    }
+}
+
+// How far all the feedback on an answer moves the confidence of its
+// insights. Only each user's current vote is stored, so changing a vote
+// replaces what it did instead of adding to it.
+pub(super) fn net_change(kinds: &[String]) -> f32 {
+    kinds
+        .iter()
+        .filter_map(|kind| Feedback::from_kind(kind))
+        .map(|feedback| feedback.confidence_change())
+        .sum()
+}
+
+// Sets the confidence of insights from an answer to where its feedback
+// puts them, from what they had before any
+pub(super) fn apply_feedback(insights: &mut [Insight], kinds: &[String]) {
+    let change = net_change(kinds);
+    for insight in insights {
+        let base = *insight.base_confidence.get_or_insert(insight.confidence);
+        insight.confidence = (base + change).clamp(0.0, 1.0);
+    }
+}
+
+impl LearningManager {
+    /// Records feedback on the answer of a conversation and applies it to
+    /// what was learned from that turn. Each user has one vote per answer:
+    /// voting again replaces it, and repeating it changes nothing (`None`).
+    /// Returns how many insights changed.
+    pub async fn record_feedback(
+        &self,
+        user_id: &str,
+        conversation_id: i64,
+        feedback: Feedback,
+    ) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+        let correction = match &feedback {
+            Feedback::Correction(text) => Some(text.trim().to_string()),
+            _ => None,
+        };
+        let changed = self
+            .db
+            .feedback_apply(conversation_id, user_id.to_string(), feedback.kind(), correction.clone(), net_change)
+            .await?;
+        let Some(changed) = changed else { return Ok(None) };
+        // Relations of the facts among them follow their new confidence
+        let mut facts: HashMap<String, Vec<Fact>> = HashMap::new();
+        for (owner, json) in &changed {
+            if let Some(fact) = serde_json::from_str::<Insight>(json).ok().and_then(|i| i.fact) {
+                facts.entry(owner.clone()).or_default().push(fact);
+            }
+        }
+        for (owner, facts) in facts {
+            self.rescore_relations(&owner, &facts).await.map_err(|e| e as Box<dyn std::error::Error>)?;
+        }
+        // Cached topics with insights from this turn are reloaded with the new confidence
+        self.cache.lock().await.invalidate_where(|insight| {
+            insight.provenance.as_ref().and_then(|p| p.conversation_id()) == Some(conversation_id)
+        });
+
+        // A bad answer, or one asked again, takes what it said out of review.
+        // A good rating is not approval: only `/review approve` or
+        // independent corroboration promotes an item.
+        if feedback.rejects() {
+            for item in self.db.pending_for_conversation(conversation_id).await? {
+                self.db.pending_set_status(item.id, "rejected").await?;
+            }
+        }
+
+        // Filed under each of its main topics, so it turns up whichever one comes up again
+        if let Some(text) = correction.filter(|t| !t.is_empty()) {
+            let now = chrono::Utc::now();
+            let mut topics = keyphrase::extract(&text, CORRECTION_TOPICS);
+            if topics.is_empty() {
+                topics.push(text.to_lowercase());
+            }
+            let insights: Vec<Insight> = topics
+                .into_iter()
+                .map(|topic| Insight {
+                    topic,
+                    context: text.clone(),
+                    confidence: 1.0,
+                    source: "correction".to_string(),
+                    timestamp: now,
+                    provenance: Some(Provenance::Conversation {
+                        conversation_id: Some(conversation_id),
+                        user_id: user_id.to_string(),
+                    }),
+                    fact: None,
+                    base_confidence: None,
+                })
+                .collect();
+            self.store_insights(user_id, &insights, "feedback").await.map_err(|e| e as Box<dyn std::error::Error>)?;
+        }
+
+        Ok(Some(changed.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+
+    fn kinds(kinds: &[&str]) -> Vec<String> {
+        kinds.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[test]
+    fn kinds_round_trip() {
+        for feedback in [Feedback::Up, Feedback::Down, Feedback::Correction(String::new()), Feedback::Regenerate] {
+            assert_eq!(Feedback::from_kind(feedback.kind()), Some(feedback));
+        }
+        assert_eq!(Feedback::from_kind("sideways"), None);
+    }
+
+    #[test]
+    fn net_change_adds_up_all_feedback() {
+        assert_eq!(net_change(&[]), 0.0);
+        assert!((net_change(&kinds(&["up"])) - 0.2).abs() < 1e-6);
+        assert!((net_change(&kinds(&["down", "regenerate", "sideways"])) + 0.4).abs() < 1e-6);
+    }
+
+    async fn confidence(db: &Database, user_id: &str, key: &str) -> f32 {
+        let json = db.get_knowledge(user_id.to_string(), key.to_string()).await.unwrap().unwrap();
+        serde_json::from_str::<Insight>(&json).unwrap().confidence
+    }
+
+    #[tokio::test]
+    async fn changed_votes_leave_no_trace() {
+        let db = Database::new(":memory:").await.unwrap();
+        let id = db
+            .save_conversation("alice".to_string(), "q".to_string(), "a".to_string(), "default".to_string(), "default".to_string())
+            .await
+            .unwrap();
+        let insight = Insight {
+            topic: "rust".to_string(),
+            context: "Rust has no garbage collector".to_string(),
+            confidence: 0.9,
+            source: "ai_response".to_string(),
+            timestamp: chrono::Utc::now(),
+            provenance: Some(Provenance::Conversation { conversation_id: Some(id), user_id: "alice".to_string() }),
+            fact: None,
+            base_confidence: None,
+        };
+        let key = "insight:rust:1:0";
+        db.save_knowledge("alice".to_string(), key.to_string(), serde_json::to_string(&insight).unwrap()).await.unwrap();
+
+        let vote = |kind| db.feedback_apply(id, "alice".to_string(), kind, None, net_change);
+        assert!(vote("down").await.unwrap().is_some());
+        let after_down = confidence(&db, "alice", key).await;
+        assert!((after_down - 0.6).abs() < 1e-6);
+
+        // Up is clamped at 1.0, which must not stop down from coming back to the same place
+        vote("up").await.unwrap();
+        assert_eq!(confidence(&db, "alice", key).await, 1.0);
+        vote("down").await.unwrap();
+        assert!((confidence(&db, "alice", key).await - after_down).abs() < 1e-6);
+
+        // The same vote again changes nothing
+        assert!(vote("down").await.unwrap().is_none());
+        assert!((confidence(&db, "alice", key).await - after_down).abs() < 1e-6);
+    }
+}
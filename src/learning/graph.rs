@@ -0,0 +1,284 @@
+// src/learning/graph.rs
+// A small knowledge graph built from extracted facts: entities (people,
+// projects, services, tools) and the relations between them. Stored as
+// `entity:` and `relation:` rows of the knowledge_base table next to the
+// insights they came from.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use crate::database::GLOBAL_USER;
+use crate::knowledge_base::knowledge_base::Provenance;
+use super::{Fact, Insight, LearningManager};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entity {
+    /// As first seen
+    pub name: String,
+    /// person, project, service or tool; None for plain values
+    pub kind: Option<String>,
+    pub mentions: u32,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relation {
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+    /// Highest confidence it was stated with
+    pub confidence: f32,
+    pub mentions: u32,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Where it was first learned
+    pub provenance: Option<Provenance>,
+}
+
+impl Relation {
+    /// The relation as a sentence, the way the fact was stated.
+    pub fn text(&self) -> String {
+        format!("{} {} {}", self.subject, self.predicate, self.object)
+    }
+}
+
+/// The entities and relations a user can see.
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    pub entities: Vec<Entity>,
+    pub relations: Vec<Relation>,
+}
+
+// Names are matched ignoring case and spacing
+fn normalize(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn entity_key(name: &str) -> String {
+    format!("entity:{}", normalize(name))
+}
+
+fn relation_key(fact: &Fact) -> String {
+    format!("relation:{}|{}|{}", normalize(&fact.subject), normalize(&fact.predicate), normalize(&fact.object))
+}
+
+impl Graph {
+    pub fn entity(&self, name: &str) -> Option<&Entity> {
+        let name = normalize(name);
+        self.entities.iter().find(|e| normalize(&e.name) == name)
+    }
+
+    /// Relations from and to an entity.
+    pub fn relations_of(&self, name: &str) -> (Vec<&Relation>, Vec<&Relation>) {
+        let name = normalize(name);
+        let outgoing = self.relations.iter().filter(|r| normalize(&r.subject) == name).collect();
+        let incoming = self.relations.iter().filter(|r| normalize(&r.object) == name).collect();
+        (outgoing, incoming)
+    }
+
+    /// Typed entities (not plain values) whose name appears in `text` as whole words.
+    pub fn mentioned_in(&self, text: &str) -> Vec<&Entity> {
+        let text = format!(" {} ", crate::text::words(text).join(" "));
+        self.entities
+            .iter()
+            .filter(|e| e.kind.is_some())
+            .filter(|e| {
+                let name = crate::text::words(&e.name).join(" ");
+                !name.is_empty() && text.contains(&format!(" {} ", name))
+            })
+            .collect()
+    }
+
+    /// What is known about an entity as a tree, or None if it is unknown.
+    pub fn tree(&self, name: &str) -> Option<String> {
+        let entity = self.entity(name)?;
+        let (outgoing, incoming) = self.relations_of(name);
+        let label = |name: &str| match self.entity(name).and_then(|e| e.kind.as_ref()) {
+            Some(kind) => format!("{} ({})", name, kind),
+            None => name.to_string(),
+        };
+
+        let mut lines = vec![label(&entity.name)];
+        let branches: Vec<String> = outgoing
+            .iter()
+            .map(|r| format!("{} → {} [{:.2}]", r.predicate, label(&r.object), r.confidence))
+            .chain(incoming.iter().map(|r| format!("← {} {} [{:.2}]", label(&r.subject), r.predicate, r.confidence)))
+            .collect();
+        for (i, branch) in branches.iter().enumerate() {
+            let joint = if i + 1 == branches.len() { "└──" } else { "├──" };
+            lines.push(format!("{} {}", joint, branch));
+        }
+        Some(lines.join("\n"))
+    }
+
+    /// The graph in Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        let names: BTreeSet<String> = self.relations.iter().flat_map(|r| [normalize(&r.subject), normalize(&r.object)]).collect();
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+
+        let mut dot = String::from("digraph knowledge {\n    rankdir=LR;\n    node [shape=box];\n");
+        for name in &names {
+            let label = match self.entity(name) {
+                Some(Entity { name, kind: Some(kind), .. }) => format!("{} ({})", name, kind),
+                Some(entity) => entity.name.clone(),
+                None => name.clone(),
+            };
+            dot.push_str(&format!("    {} [label={}];\n", quote(name), quote(&label)));
+        }
+        for r in &self.relations {
+            dot.push_str(&format!(
+                "    {} -> {} [label={}];\n",
+                quote(&normalize(&r.subject)),
+                quote(&normalize(&r.object)),
+                quote(&r.predicate)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl LearningManager {
+    // Adds the entities and relation of an extracted fact to the owner's graph
+    pub(super) async fn add_to_graph(
+        &self,
+        owner: &str,
+        insight: &Insight,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(fact) = &insight.fact else { return Ok(()) };
+        let now = insight.timestamp;
+
+        for (name, kind) in [(&fact.subject, &fact.subject_kind), (&fact.object, &fact.object_kind)] {
+            let key = entity_key(name);
+            let entity = match self.db.get_knowledge(owner.to_string(), key.clone()).await? {
+                Some(json) => {
+                    let mut entity: Entity = serde_json::from_str(&json)?;
+                    entity.mentions += 1;
+                    entity.last_seen = now;
+                    entity.kind = entity.kind.or(kind.clone());
+                    entity
+                }
+                None => Entity { name: name.clone(), kind: kind.clone(), mentions: 1, first_seen: now, last_seen: now },
+            };
+            self.db.save_knowledge(owner.to_string(), key, serde_json::to_string(&entity)?).await?;
+        }
+
+        let key = relation_key(fact);
+        let relation = match self.db.get_knowledge(owner.to_string(), key.clone()).await? {
+            Some(json) => {
+                let mut relation: Relation = serde_json::from_str(&json)?;
+                relation.mentions += 1;
+                relation.last_seen = now;
+                relation.confidence = relation.confidence.max(insight.confidence);
+                relation
+            }
+            None => Relation {
+                subject: fact.subject.clone(),
+                predicate: fact.predicate.clone(),
+                object: fact.object.clone(),
+                confidence: insight.confidence,
+                mentions: 1,
+                first_seen: now,
+                last_seen: now,
+                provenance: insight.provenance.clone(),
+            },
+        };
+        self.db.save_knowledge(owner.to_string(), key, serde_json::to_string(&relation)?).await?;
+        self.cache.lock().await.forget_graph(owner);
+        Ok(())
+    }
+
+    // Takes back one mention of a fact that turned out to be wrong: its
+    // relation and entities go once nothing else mentions them
+    pub(super) async fn remove_from_graph(&self, owner: &str, fact: &Fact) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let key = relation_key(fact);
+        if let Some(json) = self.db.get_knowledge(owner.to_string(), key.clone()).await? {
+            let mut relation: Relation = serde_json::from_str(&json)?;
+            relation.mentions = relation.mentions.saturating_sub(1);
+            if relation.mentions == 0 {
+                self.db.delete_knowledge(owner.to_string(), key).await?;
+            } else {
+                self.db.save_knowledge(owner.to_string(), key, serde_json::to_string(&relation)?).await?;
+            }
+        }
+
+        for name in [&fact.subject, &fact.object] {
+            let key = entity_key(name);
+            let Some(json) = self.db.get_knowledge(owner.to_string(), key.clone()).await? else { continue };
+            let mut entity: Entity = serde_json::from_str(&json)?;
+            entity.mentions = entity.mentions.saturating_sub(1);
+            if entity.mentions == 0 {
+                self.db.delete_knowledge(owner.to_string(), key).await?;
+            } else {
+                self.db.save_knowledge(owner.to_string(), key, serde_json::to_string(&entity)?).await?;
+            }
+        }
+        self.cache.lock().await.forget_graph(owner);
+        Ok(())
+    }
+
+    // Sets the confidence of the relations of `facts` to the highest of
+    // the insights stating them, after feedback moved theirs
+    pub(super) async fn rescore_relations(&self, owner: &str, facts: &[Fact]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let keys: HashSet<String> = facts.iter().map(relation_key).collect();
+        let mut best: HashMap<String, f32> = HashMap::new();
+        for record in self.db.get_knowledge_by_prefix(owner.to_string(), "insight:".to_string()).await? {
+            let Ok(insight) = serde_json::from_str::<Insight>(&record.value) else { continue };
+            let Some(key) = insight.fact.as_ref().map(relation_key).filter(|k| keys.contains(k)) else { continue };
+            let confidence = best.entry(key).or_insert(0.0);
+            *confidence = confidence.max(insight.confidence);
+        }
+
+        for (key, confidence) in best {
+            let Some(json) = self.db.get_knowledge(owner.to_string(), key.clone()).await? else { continue };
+            let mut relation: Relation = serde_json::from_str(&json)?;
+            relation.confidence = confidence;
+            self.db.save_knowledge(owner.to_string(), key, serde_json::to_string(&relation)?).await?;
+        }
+        self.cache.lock().await.forget_graph(owner);
+        Ok(())
+    }
+
+    /// The graph `user_id` can see: their own, plus the shared one when
+    /// knowledge is shared.
+    pub async fn graph(&self, user_id: &str) -> Result<Graph, Box<dyn std::error::Error>> {
+        let mut owners = vec![user_id.to_string()];
+        if self.shared_knowledge {
+            owners.push(GLOBAL_USER.to_string());
+        }
+
+        let mut graph = Graph::default();
+        for owner in owners {
+            let own = self.owner_graph(&owner).await?;
+            for entity in own.entities {
+                if graph.entity(&entity.name).is_none() {
+                    graph.entities.push(entity);
+                }
+            }
+            graph.relations.extend(own.relations);
+        }
+        graph.entities.sort_by(|a, b| b.mentions.cmp(&a.mentions).then_with(|| a.name.cmp(&b.name)));
+        Ok(graph)
+    }
+
+    // The graph of one owner, from the cache or else the database
+    async fn owner_graph(&self, owner: &str) -> Result<Graph, Box<dyn std::error::Error>> {
+        let version = {
+            let cache = self.cache.lock().await;
+            if let Some(graph) = cache.graph(owner) {
+                return Ok(graph);
+            }
+            cache.version()
+        };
+
+        let mut graph = Graph::default();
+        for record in self.db.get_knowledge_by_prefix(owner.to_string(), "entity:".to_string()).await? {
+            graph.entities.push(serde_json::from_str(&record.value)?);
+        }
+        for record in self.db.get_knowledge_by_prefix(owner.to_string(), "relation:".to_string()).await? {
+            graph.relations.push(serde_json::from_str(&record.value)?);
+        }
+        self.cache.lock().await.set_graph(owner, graph.clone(), version);
+        Ok(graph)
+    }
+}
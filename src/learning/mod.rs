@@ -1,11 +1,71 @@
-use crate::database::Database;
-use crate::knowledge_base::knowledge_base::KnowledgeBaseHandler;
-use log::{info, error};
+use crate::completion::CompletionProvider;
+use crate::database::{Database, LearnedTurn, GLOBAL_USER};
+use crate::keyphrase;
+use crate::text;
+use crate::knowledge_base::embedding::{self, EMBEDDING_MODEL};
+use crate::knowledge_base::knowledge_base::{KnowledgeBaseHandler, Provenance};
+use log::{info, warn};
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+mod cache;
+mod conflicts;
+mod extraction;
+mod feedback;
+mod graph;
+mod profile;
+mod report;
+mod review;
+mod worker;
+
+pub use conflicts::ConflictPolicy;
+pub use extraction::Fact;
+pub use feedback::Feedback;
+pub use profile::UserProfile;
+pub use report::{LearningReport, ReportOptions, ReportSection};
+pub use review::TrustPolicy;
+pub use worker::{Interaction, LearningWorker};
+
+// Topics kept per text
+const MAX_TOPICS: usize = 5;
+// Learned items added to a prompt
+const MAX_LEARNED_CONTEXT: usize = 5;
+// Share of an insight's topic words that must appear in the input
+const MIN_TOPIC_MATCH: f32 = 0.5;
+// Learned items lose half their weight every this many days
+const RECENCY_HALF_LIFE_DAYS: f32 = 30.0;
+// Limits of the insight cache: topics held, and roughly how much memory
+const CACHE_MAX_TOPICS: usize = 256;
+const CACHE_MAX_BYTES: usize = 4 * 1024 * 1024;
+// Topics listed in the learning summary
+const SUMMARY_TOPICS: usize = 20;
+
+/// Something learned earlier that bears on the current input.
+#[derive(Debug, Clone)]
+pub struct LearnedContext {
+    /// "correction", "preference", "learned" (promoted content), "relation"
+    /// (from the entity graph) or "fact"
+    pub kind: String,
+    pub text: String,
+    pub score: f32,
+}
+
+// How far each kind of insight is trusted in a prompt. The heuristic
+// ones (whole inputs, response sentences) are left out: they are
+// unverified and only repeat the conversation history.
+fn kind_weight(kind: &str) -> Option<f32> {
+    match kind {
+        "correction" => Some(1.0),
+        "preference" => Some(0.9),
+        "learned" => Some(0.8),
+        "relation" => Some(0.7),
+        "fact" => Some(0.6),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Insight {
     pub topic: String,
@@ -13,6 +73,16 @@ pub struct Insight {
     pub confidence: f32,
     pub source: String,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Missing on insights stored before provenance was recorded
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+    /// Set on facts extracted by the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fact: Option<Fact>,
+    /// Confidence before feedback on its answer, which is applied to this
+    /// afresh each time, so votes taken back leave no trace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_confidence: Option<f32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -31,7 +101,9 @@ impl LearningContext {
         }
     }
 
-    pub fn extract_from_interaction(user_input: &str, ai_response: &str) -> Self {
+    /// `said` and `generated` record where the user input and the AI
+    /// response came from.
+    pub fn extract_from_interaction(user_input: &str, ai_response: &str, said: &Provenance, generated: &Provenance) -> Self {
         let mut context = Self::new();
         let now = chrono::Utc::now();
 
@@ -45,6 +117,9 @@ impl LearningContext {
                 confidence: 0.7,
                 source: "user_input".to_string(),
                 timestamp: now,
+                provenance: Some(said.clone()),
+                fact: None,
+                base_confidence: None,
             });
         }
 
@@ -57,6 +132,9 @@ impl LearningContext {
                 confidence: 0.8,
                 source: "ai_response".to_string(),
                 timestamp: now,
+                provenance: Some(generated.clone()),
+                fact: None,
+                base_confidence: None,
             });
         }
 
@@ -64,17 +142,7 @@ impl LearningContext {
     }
 
     fn extract_topics(text: &str) -> Vec<String> {
-        let mut topics = Vec::new();
-        let words: Vec<&str> = text.split_whitespace().collect();
-        
-        // Simple topic extraction (can be enhanced with NLP)
-        for window in words.windows(2) {
-            if window[0].len() > 3 && window[1].len() > 3 {
-                topics.push(format!("{} {}", window[0], window[1]));
-            }
-        }
-
-        topics
+        keyphrase::extract(text, MAX_TOPICS)
     }
 
     fn extract_insights(text: &str) -> Vec<(String, String)> {
@@ -94,127 +162,415 @@ impl LearningContext {
     }
 
     fn identify_main_topic(sentence: &str) -> Option<String> {
-        let words: Vec<&str> = sentence.split_whitespace().collect();
-        words.first().map(|w| w.to_string())
+        keyphrase::extract(sentence, 1).pop()
     }
 }
 
+#[derive(Clone)]
 pub struct LearningManager {
     db: Arc<Database>,
     knowledge_base: Arc<KnowledgeBaseHandler>,
-    context_cache: Arc<Mutex<HashMap<String, LearningContext>>>,
+    // Recently used insights by owner and topic, backed by the database
+    cache: Arc<Mutex<cache::InsightCache>>,
+    // When set, learned topics go to the shared knowledge base and global
+    // facts are visible to every user. Otherwise users are fully isolated.
+    shared_knowledge: bool,
+    // When learned content leaves the review queue
+    policy: TrustPolicy,
+    // How contradicting insights are settled
+    conflict_policy: ConflictPolicy,
 }
 
 impl LearningManager {
-    pub fn new(db: Database, knowledge_base: KnowledgeBaseHandler) -> Self {
+    pub fn new(
+        db: Database,
+        knowledge_base: KnowledgeBaseHandler,
+        shared_knowledge: bool,
+        policy: TrustPolicy,
+        conflict_policy: ConflictPolicy,
+    ) -> Self {
         Self {
             db: Arc::new(db),
             knowledge_base: Arc::new(knowledge_base),
-            context_cache: Arc::new(Mutex::new(HashMap::new())),
+            cache: Arc::new(Mutex::new(cache::InsightCache::new(CACHE_MAX_TOPICS, CACHE_MAX_BYTES))),
+            shared_knowledge,
+            policy,
+            conflict_policy,
         }
     }
 
-    pub async fn learn_from_interaction(
+    /// Learns from a batch of exchanges: their insights, what they say
+    /// about the user and their content for the review queue are stored
+    /// in one transaction. An exchange that fails is logged and skipped;
+    /// returns how many did.
+    pub async fn learn_from_interactions(&self, interactions: &[Interaction]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut failed = 0;
+        let mut turns = Vec::new();
+        let mut learned = Vec::new();
+        for (i, interaction) in interactions.iter().enumerate() {
+            match self.learned_turn(interaction, i).await {
+                Ok((turn, context)) => {
+                    turns.push(turn);
+                    learned.push((interaction, context));
+                }
+                Err(e) => {
+                    warn!("Failed to learn from an interaction: {}", e);
+                    failed += 1;
+                }
+            }
+        }
+
+        self.embed_pending().await?;
+        let results = self.db.save_learned(turns, EMBEDDING_MODEL.to_string(), review::similar_pending).await?;
+        for ((interaction, context), result) in learned.into_iter().zip(results) {
+            let backed = match result {
+                Ok(backed) => backed,
+                Err(e) => {
+                    warn!("Failed to learn from an interaction: {}", e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            {
+                let mut cache = self.cache.lock().await;
+                for insight in &context.insights {
+                    cache.add(&interaction.user_id, insight);
+                }
+            }
+            // Promoting writes to the knowledge base, so it happens after the batch
+            for (id, corroborations) in backed {
+                if let Err(e) = self.promote_if_trusted(id, corroborations).await {
+                    warn!("Failed to promote learned item {}: {}", id, e);
+                }
+            }
+
+            info!("Learned from interaction: {} insights, {} topics",
+                context.insights.len(),
+                context.related_topics.len()
+            );
+        }
+
+        Ok(failed)
+    }
+
+    // What one exchange taught, with feedback already given on it applied.
+    // `index` keeps keys of insights on the same topic at the same moment apart.
+    async fn learned_turn(&self, interaction: &Interaction, index: usize) -> Result<(LearnedTurn, LearningContext), Box<dyn std::error::Error>> {
+        let said = Provenance::Conversation {
+            conversation_id: interaction.conversation_id,
+            user_id: interaction.user_id.clone(),
+        };
+        let generated = Provenance::Model {
+            conversation_id: interaction.conversation_id,
+            user_id: interaction.user_id.clone(),
+            model: interaction.model.clone(),
+        };
+        let mut context = LearningContext::extract_from_interaction(&interaction.user_input, &interaction.ai_response, &said, &generated);
+
+        // The user may have rated the answer before it got here
+        let feedback = match interaction.conversation_id {
+            Some(id) => self.db.feedback_for(id).await?,
+            None => Vec::new(),
+        };
+        feedback::apply_feedback(&mut context.insights, &feedback);
+
+        let mut insights = Vec::new();
+        for insight in &context.insights {
+            let key = format!("insight:{}:{}:{}-{}", insight.topic, insight.timestamp.timestamp_micros(), index, insights.len());
+            insights.push((key, serde_json::to_string(&insight)?));
+        }
+
+        // The response is unverified, so it goes through the review queue
+        // instead of straight into the knowledge base, unless it was already
+        // rated badly or asked again
+        let rejected = feedback.iter().filter_map(|kind| Feedback::from_kind(kind)).any(|f| f.rejects());
+        let queue = if rejected {
+            None
+        } else {
+            Self::queued_content(&context.related_topics, &interaction.ai_response, generated)
+        };
+        // Questions don't back anything
+        let input = interaction.user_input.trim();
+        let stated = Some(input)
+            .filter(|s| !s.is_empty() && !s.ends_with('?'))
+            .map(|s| embedding::to_bytes(&embedding::embed(s)));
+
+        let turn = LearnedTurn {
+            user_id: interaction.user_id.clone(),
+            conversation_id: interaction.conversation_id,
+            insights,
+            profile: profile::from_input(&interaction.user_input),
+            queue_owner: self.owner(&interaction.user_id),
+            stated,
+            queue,
+        };
+        Ok((turn, context))
+    }
+
+    /// Has the model extract facts, preferences and corrections from an
+    /// exchange and stores them as insights. Meant to run in the
+    /// background after the reply is shown. Returns how many were stored.
+    pub async fn learn_with_model<P: CompletionProvider + Sync>(
         &self,
+        provider: &P,
+        user_id: &str,
+        conversation_id: Option<i64>,
         user_input: &str,
         ai_response: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // Extract learning context
-        let context = LearningContext::extract_from_interaction(user_input, ai_response);
-        
-        // Store insights in database
-        for insight in &context.insights {
-            self.db.save_knowledge(
-                format!("insight:{}:{}", insight.topic, insight.timestamp.timestamp()),
-                serde_json::to_string(&insight)?,
-            ).await?;
-        }
-
-        // Update knowledge base
-        for topic in &context.related_topics {
-            if let Some(existing) = self.knowledge_base.get_entry(topic).await? {
-                let mut entry = existing;
-                entry.push_str("\n");
-                entry.push_str(ai_response);
-                self.knowledge_base.update_entry(topic, &entry).await?;
-            } else {
-                self.knowledge_base.add_entry(topic, ai_response).await?;
-            }
-        }
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let said = Provenance::Conversation { conversation_id, user_id: user_id.to_string() };
+        let generated = Provenance::Model {
+            conversation_id,
+            user_id: user_id.to_string(),
+            model: provider.model().to_string(),
+        };
+
+        let reply = provider.complete(&extraction::prompt(user_input, ai_response)).await?;
+        let (mut insights, profile) = extraction::parse(&reply, &said, &generated)?;
+        self.learn_profile(user_id, profile).await?;
 
-        // Update context cache
-        let mut cache = self.context_cache.lock().await;
-        for topic in &context.related_topics {
-            cache.insert(topic.clone(), context.clone());
+        // The user may have rated the answer before extraction finished
+        if let Some(id) = conversation_id {
+            feedback::apply_feedback(&mut insights, &self.db.feedback_for(id).await?);
         }
 
-        info!("Learned from interaction: {} insights, {} topics", 
-            context.insights.len(), 
-            context.related_topics.len()
-        );
+        self.store_insights(user_id, &insights, "").await?;
+        info!("Extracted {} insights with {}", insights.len(), provider.model());
+        Ok(insights.len())
+    }
 
+    // Saves insights, and adds them to the cache if their topic is in it.
+    // `tag` keeps keys of insights stored at the same moment apart.
+    // Insights that lose a conflict with older ones are dropped, along
+    // with their copies under other topics.
+    async fn store_insights(&self, user_id: &str, insights: &[Insight], tag: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut lost: HashSet<&str> = HashSet::new();
+        for (i, insight) in insights.iter().enumerate() {
+            if lost.contains(insight.context.as_str()) {
+                continue;
+            }
+            // Facts about the world are shared when knowledge is; preferences never are
+            let owner = if self.shared_knowledge && insight.fact.is_some() { GLOBAL_USER } else { user_id };
+            let key = format!("insight:{}:{}:{}{}", insight.topic, insight.timestamp.timestamp_micros(), tag, i);
+            if !self.settle_conflicts(user_id, owner, &key, insight).await? {
+                lost.insert(&insight.context);
+                self.forget_insight(owner, &insight.context).await?;
+                continue;
+            }
+            self.db.save_knowledge(owner.to_string(), key, serde_json::to_string(&insight)?).await?;
+            self.add_to_graph(owner, insight).await?;
+            self.cache.lock().await.add(owner, insight);
+        }
         Ok(())
     }
 
+    /// What was learned earlier about the topics of `query`, best first:
+    /// corrections, preferences, promoted content, extracted facts and
+    /// relations of the entities it names,
+    /// ranked by topic match, trust, confidence and age, without
+    /// duplicates. Learned content in the shared knowledge base is left
+    /// to knowledge base search.
     pub async fn get_relevant_context(
         &self,
+        user_id: &str,
         query: &str,
-    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let mut context = Vec::new();
-
-        // Check context cache first
-        let cache = self.context_cache.lock().await;
-        for topic in LearningContext::extract_topics(query) {
-            if let Some(cached_context) = cache.get(&topic) {
-                for insight in &cached_context.insights {
-                    context.push(insight.context.clone());
+    ) -> Result<Vec<LearnedContext>, Box<dyn std::error::Error>> {
+        let query_terms: HashSet<String> = text::tokenize(query).into_iter().collect();
+        if query_terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Insights on matching topics: the user's own, and shared facts
+        // when knowledge is shared
+        let mut candidates: Vec<Insight> = Vec::new();
+        let mut owners = vec![user_id.to_string()];
+        if self.shared_knowledge {
+            owners.push(GLOBAL_USER.to_string());
+        }
+        // Whose insight each text is, to record its use; the user's own first
+        let mut owner_of: HashMap<String, String> = HashMap::new();
+        for owner in owners {
+            for topic in self.topics_of(&owner).await? {
+                if topic_match(&topic, &query_terms) >= MIN_TOPIC_MATCH {
+                    for insight in self.insights_on(&owner, &topic).await? {
+                        owner_of.entry(insight.context.clone()).or_insert_with(|| owner.clone());
+                        candidates.push(insight);
+                    }
                 }
             }
+            candidates.extend(self.promoted_of(&owner).await?);
         }
 
-        // Get insights from database
-        let topics = LearningContext::extract_topics(query);
-        for topic in &topics {
-            if let Some(value) = self.db.get_knowledge(format!("topic:{}", topic)).await? {
-                if let Ok(insight) = serde_json::from_str::<Insight>(&value) {
-                    context.push(insight.context);
+        let now = chrono::Utc::now();
+        let mut ranked: Vec<LearnedContext> = candidates
+            .into_iter()
+            .filter_map(|insight| {
+                let weight = kind_weight(&insight.source)?;
+                let matched = topic_match(&insight.topic, &query_terms);
+                if matched < MIN_TOPIC_MATCH {
+                    return None;
                 }
+                let age_days = (now - insight.timestamp).num_seconds().max(0) as f32 / 86_400.0;
+                let recency = 0.5f32.powf(age_days / RECENCY_HALF_LIFE_DAYS);
+                Some(LearnedContext {
+                    kind: insight.source,
+                    text: insight.context,
+                    score: matched * weight * insight.confidence * recency,
+                })
+            })
+            .collect();
+
+        // What the graph knows about entities the input names
+        let graph = self.graph(user_id).await?;
+        let weight = kind_weight("relation").unwrap_or_default();
+        for entity in graph.mentioned_in(query) {
+            let (outgoing, incoming) = graph.relations_of(&entity.name);
+            for relation in outgoing.into_iter().chain(incoming) {
+                let age_days = (now - relation.last_seen).num_seconds().max(0) as f32 / 86_400.0;
+                ranked.push(LearnedContext {
+                    kind: "relation".to_string(),
+                    text: relation.text(),
+                    score: weight * relation.confidence * 0.5f32.powf(age_days / RECENCY_HALF_LIFE_DAYS),
+                });
             }
         }
 
-        // Get knowledge base entries
-        for topic in &topics {
-            if let Some(entry) = self.knowledge_base.get_entry(topic).await? {
-                context.push(entry);
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let mut seen = HashSet::new();
+        ranked.retain(|item| seen.insert(item.text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()));
+        ranked.truncate(MAX_LEARNED_CONTEXT);
+
+        // Counted for the learning report; not worth failing the prompt over
+        let used: Vec<(String, String)> = ranked
+            .iter()
+            .filter_map(|item| Some((owner_of.get(&item.text)?.clone(), item.text.clone())))
+            .collect();
+        if !used.is_empty() {
+            if let Err(e) = self.db.record_insight_use(used).await {
+                warn!("Failed to record which insights were used: {}", e);
             }
         }
+        Ok(ranked)
+    }
 
-        Ok(context)
+    // Topics `owner` has insights on, from the cache or else the database
+    async fn topics_of(&self, owner: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let version = {
+            let cache = self.cache.lock().await;
+            if let Some(topics) = cache.topics(owner) {
+                return Ok(topics);
+            }
+            cache.version()
+        };
+        let topics: Vec<String> = self.db.insight_topics(owner.to_string()).await?.into_iter().map(|(topic, _)| topic).collect();
+        self.cache.lock().await.set_topics(owner, topics.clone(), version);
+        Ok(topics)
     }
 
-    pub async fn get_learning_summary(&self) -> Result<String, Box<dyn std::error::Error>> {
-        let cache = self.context_cache.lock().await;
-        let mut summary = String::new();
+    // Promoted content of a user who doesn't share knowledge, as insights
+    async fn promoted_of(&self, owner: &str) -> Result<Vec<Insight>, Box<dyn std::error::Error>> {
+        let version = {
+            let cache = self.cache.lock().await;
+            if let Some(promoted) = cache.promoted(owner) {
+                return Ok(promoted);
+            }
+            cache.version()
+        };
+        let promoted: Vec<Insight> = self.db
+            .get_knowledge_by_prefix(owner.to_string(), "kb:".to_string())
+            .await?
+            .into_iter()
+            .map(|record| {
+                let timestamp = chrono::NaiveDateTime::parse_from_str(&record.timestamp, "%Y-%m-%d %H:%M:%S")
+                    .map(|t| t.and_utc())
+                    .unwrap_or_else(|_| chrono::Utc::now());
+                Insight {
+                    topic: record.key["kb:".len()..].to_string(),
+                    context: record.value,
+                    confidence: 1.0,
+                    source: "learned".to_string(),
+                    timestamp,
+                    provenance: None,
+                    fact: None,
+                    base_confidence: None,
+                }
+            })
+            .collect();
+        self.cache.lock().await.set_promoted(owner, promoted.clone(), version);
+        Ok(promoted)
+    }
 
-        summary.push_str("Learning Summary:\n\n");
+    // Everything `owner` has stored on `topic`, from the cache or else the database
+    async fn insights_on(&self, owner: &str, topic: &str) -> Result<Vec<Insight>, Box<dyn std::error::Error>> {
+        let version = {
+            let mut cache = self.cache.lock().await;
+            if let Some(insights) = cache.get(owner, topic) {
+                return Ok(insights);
+            }
+            cache.version()
+        };
+        let insights: Vec<Insight> = self.db
+            .get_knowledge_by_prefix(owner.to_string(), format!("insight:{}:", topic))
+            .await?
+            .iter()
+            .filter_map(|record| serde_json::from_str(&record.value).ok())
+            .filter(|insight: &Insight| insight.topic == topic)
+            .collect();
+        self.cache.lock().await.insert(owner, topic, insights.clone(), version);
+        Ok(insights)
+    }
 
-        // Summarize topics
-        let mut all_topics = Vec::new();
-        for context in cache.values() {
-            all_topics.extend(context.related_topics.clone());
+    /// Forgets everything cached, e.g. after another process changed the database.
+    pub async fn clear_cache(&self) {
+        self.cache.lock().await.clear();
+    }
+
+    /// What has been learned about `user_id`, read from the database so it
+    /// covers earlier sessions too.
+    pub async fn get_learning_summary(&self, user_id: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let mut owners = vec![user_id.to_string()];
+        if self.shared_knowledge {
+            owners.push(GLOBAL_USER.to_string());
+        }
+        let mut counts: HashMap<String, i64> = HashMap::new();
+        for owner in owners {
+            for (topic, count) in self.db.insight_topics(owner).await? {
+                *counts.entry(topic).or_default() += count;
+            }
         }
-        all_topics.sort();
-        all_topics.dedup();
+        let mut topics: Vec<(String, i64)> = counts.into_iter().collect();
+        topics.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let total_insights: i64 = topics.iter().map(|(_, count)| count).sum();
 
-        summary.push_str(&format!("Topics Learned: {}\n", all_topics.join(", ")));
+        let mut summary = String::new();
+        summary.push_str("Learning Summary:\n\n");
 
-        // Summarize insights
-        let mut total_insights = 0;
-        for context in cache.values() {
-            total_insights += context.insights.len();
+        // Summarize topics, the ones with most insights first
+        let mut listed: Vec<String> = topics
+            .iter()
+            .take(SUMMARY_TOPICS)
+            .map(|(topic, count)| format!("{} ({})", topic, count))
+            .collect();
+        if topics.len() > SUMMARY_TOPICS {
+            listed.push(format!("and {} more", topics.len() - SUMMARY_TOPICS));
         }
+        summary.push_str(&format!("Topics Learned: {}\n", listed.join(", ")));
+
+        // Summarize insights
         summary.push_str(&format!("\nTotal Insights: {}\n", total_insights));
 
+        let (cached_topics, cached_bytes) = self.cache.lock().await.usage();
+        summary.push_str(&format!("Cached: {} topics, {:.1} KB\n", cached_topics, cached_bytes as f64 / 1024.0));
+
         Ok(summary)
     }
 }
+
+// Share of a topic's words that appear in the input
+fn topic_match(topic: &str, query_terms: &HashSet<String>) -> f32 {
+    let topic_terms = text::tokenize(topic);
+    if topic_terms.is_empty() {
+        return 0.0;
+    }
+    topic_terms.iter().filter(|t| query_terms.contains(*t)).count() as f32 / topic_terms.len() as f32
+}
@@ -0,0 +1,393 @@
+// src/learning/profile.rs
+// What the agent knows about the user: learned from conversations,
+// summarized into the system prompt, and editable with /profile. Fields
+// the user set by hand are never overwritten by learning.
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+use log::info;
+use serde::{Serialize, Deserialize};
+use super::LearningManager;
+
+// Interests and projects kept at most, newest last
+const MAX_LIST: usize = 10;
+
+const FIELDS: &[&str] = &["name", "expertise", "verbosity", "language", "interests", "projects"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Expertise {
+    Beginner,
+    Intermediate,
+    Expert,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    Brief,
+    Normal,
+    Detailed,
+}
+
+impl FromStr for Expertise {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "beginner" => Ok(Expertise::Beginner),
+            "intermediate" => Ok(Expertise::Intermediate),
+            "expert" => Ok(Expertise::Expert),
+            _ => Err(format!("Unknown expertise '{}': use beginner, intermediate or expert", s)),
+        }
+    }
+}
+
+impl FromStr for Verbosity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "brief" => Ok(Verbosity::Brief),
+            "normal" => Ok(Verbosity::Normal),
+            "detailed" => Ok(Verbosity::Detailed),
+            _ => Err(format!("Unknown verbosity '{}': use brief, normal or detailed", s)),
+        }
+    }
+}
+
+impl fmt::Display for Expertise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expertise::Beginner => write!(f, "beginner"),
+            Expertise::Intermediate => write!(f, "intermediate"),
+            Expertise::Expert => write!(f, "expert"),
+        }
+    }
+}
+
+impl fmt::Display for Verbosity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verbosity::Brief => write!(f, "brief"),
+            Verbosity::Normal => write!(f, "normal"),
+            Verbosity::Detailed => write!(f, "detailed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub name: Option<String>,
+    pub expertise: Option<Expertise>,
+    pub verbosity: Option<Verbosity>,
+    /// Language the user wants answers in
+    pub language: Option<String>,
+    #[serde(default)]
+    pub interests: Vec<String>,
+    /// Projects the user keeps coming back to
+    #[serde(default)]
+    pub projects: Vec<String>,
+    /// Fields the user set by hand
+    #[serde(default)]
+    pub manual: BTreeSet<String>,
+}
+
+impl UserProfile {
+    /// Takes what was learned in `update`, leaving fields the user set by
+    /// hand alone. Returns true if anything changed.
+    pub fn merge(&mut self, update: UserProfile) -> bool {
+        let before = self.clone();
+        let learnable = |field: &str| !self.manual.contains(field);
+
+        let name = update.name.filter(|_| learnable("name"));
+        let expertise = update.expertise.filter(|_| learnable("expertise"));
+        let verbosity = update.verbosity.filter(|_| learnable("verbosity"));
+        let language = update.language.filter(|_| learnable("language"));
+        let (learn_interests, learn_projects) = (learnable("interests"), learnable("projects"));
+
+        self.name = name.or(self.name.take());
+        self.expertise = expertise.or(self.expertise);
+        self.verbosity = verbosity.or(self.verbosity);
+        self.language = language.or(self.language.take());
+        if learn_interests {
+            add_to_list(&mut self.interests, update.interests);
+        }
+        if learn_projects {
+            add_to_list(&mut self.projects, update.projects);
+        }
+        *self != before
+    }
+
+    /// Sets a field by hand; lists take comma-separated values.
+    pub fn set(&mut self, field: &str, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(format!("No value given for {}", field));
+        }
+        match field {
+            "name" => self.name = Some(value.to_string()),
+            "expertise" => self.expertise = Some(value.parse()?),
+            "verbosity" => self.verbosity = Some(value.parse()?),
+            "language" => self.language = Some(value.to_string()),
+            "interests" => self.interests = split_list(value),
+            "projects" => self.projects = split_list(value),
+            _ => return Err(unknown_field(field)),
+        }
+        self.manual.insert(field.to_string());
+        Ok(())
+    }
+
+    /// Forgets a field, letting it be learned again.
+    pub fn clear(&mut self, field: &str) -> Result<(), String> {
+        match field {
+            "name" => self.name = None,
+            "expertise" => self.expertise = None,
+            "verbosity" => self.verbosity = None,
+            "language" => self.language = None,
+            "interests" => self.interests.clear(),
+            "projects" => self.projects.clear(),
+            _ => return Err(unknown_field(field)),
+        }
+        self.manual.remove(field);
+        Ok(())
+    }
+
+    /// A few sentences for the system prompt; empty if nothing is known.
+    pub fn summary(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(name) = &self.name {
+            lines.push(format!("The user's name is {}.", name));
+        }
+        match self.expertise {
+            Some(Expertise::Beginner) => lines.push("They are a beginner: avoid jargon and explain the basics.".to_string()),
+            Some(Expertise::Intermediate) => lines.push("They have some experience: skip the basics.".to_string()),
+            Some(Expertise::Expert) => lines.push("They are an expert: be precise and technical.".to_string()),
+            None => {}
+        }
+        match self.verbosity {
+            Some(Verbosity::Brief) => lines.push("They prefer short answers.".to_string()),
+            Some(Verbosity::Detailed) => lines.push("They prefer detailed answers.".to_string()),
+            _ => {}
+        }
+        if let Some(language) = &self.language {
+            lines.push(format!("Answer in {}.", language));
+        }
+        if !self.interests.is_empty() {
+            lines.push(format!("Their interests: {}.", self.interests.join(", ")));
+        }
+        if !self.projects.is_empty() {
+            lines.push(format!("Projects they work on: {}.", self.projects.join(", ")));
+        }
+        lines.join(" ")
+    }
+}
+
+impl fmt::Display for UserProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = || "-".to_string();
+        let rows = [
+            ("name", self.name.clone().unwrap_or_else(unknown)),
+            ("expertise", self.expertise.map(|e| e.to_string()).unwrap_or_else(unknown)),
+            ("verbosity", self.verbosity.map(|v| v.to_string()).unwrap_or_else(unknown)),
+            ("language", self.language.clone().unwrap_or_else(unknown)),
+            ("interests", if self.interests.is_empty() { unknown() } else { self.interests.join(", ") }),
+            ("projects", if self.projects.is_empty() { unknown() } else { self.projects.join(", ") }),
+        ];
+        for (i, (field, value)) in rows.iter().enumerate() {
+            let by_hand = if self.manual.contains(*field) { " (set by you)" } else { "" };
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:<10} {}{}", field, value, by_hand)?;
+        }
+        Ok(())
+    }
+}
+
+/// What an input says about the user outright: their name ("my name is",
+/// "call me") and how long they want answers to be.
+pub fn from_input(input: &str) -> UserProfile {
+    let lower = input.to_lowercase();
+    let mut profile = UserProfile::default();
+
+    for marker in ["my name is ", "call me "] {
+        // Same byte offsets, as long as lowercasing kept the length
+        let Some(start) = lower.find(marker).filter(|_| lower.len() == input.len()) else { continue };
+        let name = input[start + marker.len()..]
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .trim_matches(|c: char| !c.is_alphanumeric());
+        if name.chars().next().is_some_and(|c| c.is_uppercase()) {
+            profile.name = Some(name.to_string());
+            break;
+        }
+    }
+
+    if ["shorter answers", "be brief", "keep it short", "too long"].iter().any(|p| lower.contains(p)) {
+        profile.verbosity = Some(Verbosity::Brief);
+    } else if ["more detail", "in detail", "too short", "explain more"].iter().any(|p| lower.contains(p)) {
+        profile.verbosity = Some(Verbosity::Detailed);
+    }
+    profile
+}
+
+// Appends new items, skipping ones already there in any case
+fn add_to_list(list: &mut Vec<String>, items: Vec<String>) {
+    for item in items {
+        let item = item.trim().to_string();
+        if !item.is_empty() && !list.iter().any(|i| i.eq_ignore_ascii_case(&item)) {
+            list.push(item);
+        }
+    }
+    if list.len() > MAX_LIST {
+        list.drain(..list.len() - MAX_LIST);
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    let mut list = Vec::new();
+    add_to_list(&mut list, value.split(',').map(str::to_string).collect());
+    list
+}
+
+fn unknown_field(field: &str) -> String {
+    format!("Unknown profile field '{}': use {}", field, FIELDS.join(", "))
+}
+
+impl LearningManager {
+    /// What is known about `user_id`; empty if nothing yet.
+    pub async fn profile(&self, user_id: &str) -> Result<UserProfile, Box<dyn std::error::Error>> {
+        Ok(self.db.get_profile(user_id.to_string()).await?.unwrap_or_default())
+    }
+
+    /// Edits the stored profile by hand and returns it.
+    pub async fn edit_profile<F>(&self, user_id: &str, edit: F) -> Result<UserProfile, Box<dyn std::error::Error>>
+    where
+        F: FnOnce(&mut UserProfile) + Send + 'static,
+    {
+        let profile = self.db
+            .update_profile(user_id.to_string(), |profile| {
+                edit(profile);
+                true
+            })
+            .await?;
+        Ok(profile.unwrap_or_default())
+    }
+
+    // Merges what an interaction revealed about the user into their profile
+    pub(super) async fn learn_profile(
+        &self,
+        user_id: &str,
+        update: UserProfile,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if update == UserProfile::default() {
+            return Ok(());
+        }
+        if self.db.update_profile(user_id.to_string(), |profile| profile.merge(update)).await?.is_some() {
+            info!("Updated the profile of {}", user_id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[&str]) -> Vec<String> {
+        items.iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn merge_adds_to_lists_and_overwrites_scalars() {
+        let mut profile = UserProfile {
+            name: Some("Sam".to_string()),
+            verbosity: Some(Verbosity::Brief),
+            interests: list(&["rust"]),
+            ..Default::default()
+        };
+        let update = UserProfile {
+            name: Some("Alex".to_string()),
+            expertise: Some(Expertise::Expert),
+            interests: list(&["Rust", "sqlite"]),
+            ..Default::default()
+        };
+        assert!(profile.merge(update));
+        assert_eq!(profile.name.as_deref(), Some("Alex"));
+        assert_eq!(profile.expertise, Some(Expertise::Expert));
+        // Left alone when the update doesn't know it
+        assert_eq!(profile.verbosity, Some(Verbosity::Brief));
+        assert_eq!(profile.interests, list(&["rust", "sqlite"]));
+
+        assert!(!profile.clone().merge(UserProfile::default()));
+    }
+
+    #[test]
+    fn merge_keeps_fields_set_by_hand() {
+        let mut profile = UserProfile::default();
+        profile.set("name", "Sam").unwrap();
+        profile.set("interests", "rust").unwrap();
+        let update = UserProfile {
+            name: Some("Alex".to_string()),
+            language: Some("French".to_string()),
+            interests: list(&["sqlite"]),
+            ..Default::default()
+        };
+        assert!(profile.merge(update));
+        assert_eq!(profile.name.as_deref(), Some("Sam"));
+        assert_eq!(profile.interests, list(&["rust"]));
+        assert_eq!(profile.language.as_deref(), Some("French"));
+    }
+
+    #[test]
+    fn lists_keep_the_newest() {
+        let mut profile = UserProfile::default();
+        let update = UserProfile { interests: (0..15).map(|i| format!("topic {}", i)).collect(), ..Default::default() };
+        profile.merge(update);
+        assert_eq!(profile.interests.len(), MAX_LIST);
+        assert_eq!(profile.interests.last().map(String::as_str), Some("topic 14"));
+    }
+
+    #[test]
+    fn set_marks_fields_as_manual() {
+        let mut profile = UserProfile::default();
+        profile.set("expertise", "Beginner").unwrap();
+        profile.set("projects", "agent, , Agent, website").unwrap();
+        assert_eq!(profile.expertise, Some(Expertise::Beginner));
+        assert_eq!(profile.projects, list(&["agent", "website"]));
+        assert!(profile.manual.contains("expertise") && profile.manual.contains("projects"));
+
+        assert!(profile.set("verbosity", "chatty").is_err());
+        assert!(profile.set("name", "  ").is_err());
+        assert!(profile.set("shoe size", "9").is_err());
+        assert!(!profile.manual.contains("verbosity"));
+    }
+
+    #[test]
+    fn clear_forgets_and_unlocks() {
+        let mut profile = UserProfile::default();
+        profile.set("name", "Sam").unwrap();
+        profile.clear("name").unwrap();
+        assert_eq!(profile.name, None);
+        assert!(!profile.manual.contains("name"));
+        assert!(profile.clear("shoe size").is_err());
+    }
+
+    #[test]
+    fn names_come_from_the_input() {
+        assert_eq!(from_input("Hi, my name is Sam.").name.as_deref(), Some("Sam"));
+        assert_eq!(from_input("Please call me Alex, thanks").name.as_deref(), Some("Alex"));
+        // Lowercase words after the marker are not names
+        assert_eq!(from_input("call me later").name, None);
+        assert_eq!(from_input("What is the weather?").name, None);
+    }
+
+    #[test]
+    fn verbosity_comes_from_the_input() {
+        assert_eq!(from_input("That was too long, be brief").verbosity, Some(Verbosity::Brief));
+        assert_eq!(from_input("Can you explain more?").verbosity, Some(Verbosity::Detailed));
+        assert_eq!(from_input("Thanks").verbosity, None);
+    }
+}
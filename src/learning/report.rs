@@ -0,0 +1,324 @@
+// src/learning/report.rs
+// What the agent has learned, in numbers: top topics per week, insights
+// by source and confidence, growth of what was learned and of the
+// knowledge base, facts used most often, facts not used for a while,
+// and what each personality's conversations taught it. Built from the
+// database, so it covers every session.
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use crate::database::{Database, FactUsage, PersonalityCount, SourceBand, TopicWeek, GLOBAL_USER};
+use crate::output::{OutputFormat, Table};
+use super::LearningManager;
+
+// Confidence bands, in the order the database numbers them
+const BANDS: [&str; 4] = ["<0.5", "0.5-0.7", "0.7-0.9", ">=0.9"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportSection {
+    Topics,
+    Sources,
+    Growth,
+    Reused,
+    Stale,
+    Personalities,
+}
+
+impl ReportSection {
+    const ALL: [ReportSection; 6] = [
+        ReportSection::Topics,
+        ReportSection::Sources,
+        ReportSection::Growth,
+        ReportSection::Reused,
+        ReportSection::Stale,
+        ReportSection::Personalities,
+    ];
+
+    // Also the section's key in the JSON report
+    fn name(&self) -> &'static str {
+        match self {
+            ReportSection::Topics => "topics",
+            ReportSection::Sources => "sources",
+            ReportSection::Growth => "growth",
+            ReportSection::Reused => "reused",
+            ReportSection::Stale => "stale",
+            ReportSection::Personalities => "personalities",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportOptions {
+    /// Weeks shown in the topics and growth sections
+    pub weeks: usize,
+    /// Topics per week, and facts in the reused and stale sections
+    pub top: usize,
+    /// Facts not used in a prompt for this many days are stale
+    pub stale_days: u32,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        Self { weeks: 8, top: 10, stale_days: 90 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Growth {
+    /// Monday of the week
+    pub week: String,
+    pub new_insights: i64,
+    pub insights: i64,
+    pub new_kb_entries: i64,
+    pub kb_entries: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LearningReport {
+    pub user_id: String,
+    pub generated_at: DateTime<Utc>,
+    pub total_insights: i64,
+    pub topics: Vec<TopicWeek>,
+    pub sources: Vec<SourceBand>,
+    pub growth: Vec<Growth>,
+    pub reused: Vec<FactUsage>,
+    pub stale: Vec<FactUsage>,
+    pub personalities: Vec<PersonalityCount>,
+}
+
+impl LearningReport {
+    /// The report for `user_id`, including shared facts when knowledge is shared.
+    pub async fn build(
+        db: &Database,
+        user_id: &str,
+        shared_knowledge: bool,
+        options: &ReportOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut owners = vec![user_id.to_string()];
+        if shared_knowledge {
+            owners.push(GLOBAL_USER.to_string());
+        }
+
+        let sources = db.insights_by_source(&owners).await?;
+        let growth = growth(
+            db.insights_per_week(&owners).await?.into_iter().map(|w| (w.week, w.count)),
+            db.kb_entries_per_week().await?.into_iter().map(|w| (w.week, w.count)),
+            options.weeks,
+        );
+
+        Ok(Self {
+            user_id: user_id.to_string(),
+            generated_at: Utc::now(),
+            total_insights: sources.iter().map(|s| s.insights).sum(),
+            topics: db.topics_by_week(&owners, options.weeks, options.top).await?,
+            sources,
+            growth,
+            reused: db.fact_usage(&owners, None, options.top).await?,
+            stale: db.fact_usage(&owners, Some(options.stale_days), options.top).await?,
+            personalities: db.insights_by_personality(&owners).await?,
+        })
+    }
+
+    fn title(&self, section: ReportSection) -> String {
+        match section {
+            ReportSection::Topics => "Top topics per week".to_string(),
+            ReportSection::Sources => format!("Insights by source and confidence ({} in all)", self.total_insights),
+            ReportSection::Growth => "Growth per week".to_string(),
+            ReportSection::Reused => "Facts used most often".to_string(),
+            ReportSection::Stale => "Stale facts".to_string(),
+            ReportSection::Personalities => "By personality".to_string(),
+        }
+    }
+
+    fn table(&self, section: ReportSection) -> Table {
+        match section {
+            ReportSection::Topics => {
+                let mut table = Table::new(&["WEEK", "TOPIC", "INSIGHTS"]);
+                for row in &self.topics {
+                    table.add_row(vec![row.week.clone(), row.topic.clone(), row.insights.to_string()]);
+                }
+                table
+            }
+            ReportSection::Sources => {
+                let mut by_source: BTreeMap<&str, [i64; BANDS.len()]> = BTreeMap::new();
+                for row in &self.sources {
+                    by_source.entry(&row.source).or_default()[row.band] += row.insights;
+                }
+                let mut headers = vec!["SOURCE"];
+                headers.extend(BANDS);
+                headers.push("TOTAL");
+                let mut table = Table::new(&headers);
+                for (source, counts) in by_source {
+                    let mut row = vec![source.to_string()];
+                    row.extend(counts.iter().map(|c| c.to_string()));
+                    row.push(counts.iter().sum::<i64>().to_string());
+                    table.add_row(row);
+                }
+                table
+            }
+            ReportSection::Growth => {
+                let mut table = Table::new(&["WEEK", "NEW INSIGHTS", "INSIGHTS", "NEW KB ENTRIES", "KB ENTRIES"]);
+                for row in &self.growth {
+                    table.add_row(vec![
+                        row.week.clone(),
+                        row.new_insights.to_string(),
+                        row.insights.to_string(),
+                        row.new_kb_entries.to_string(),
+                        row.kb_entries.to_string(),
+                    ]);
+                }
+                table
+            }
+            ReportSection::Reused | ReportSection::Stale => {
+                let facts = if section == ReportSection::Reused { &self.reused } else { &self.stale };
+                let mut table = Table::new(&["FACT", "USES", "LAST USED", "MENTIONS", "CONFIDENCE", "LAST SEEN", "IDLE DAYS"]);
+                let day = |date: &str| date.get(..10).unwrap_or(date).to_string();
+                for fact in facts {
+                    table.add_row(vec![
+                        fact.fact.clone(),
+                        fact.uses.to_string(),
+                        fact.last_used.as_deref().map(day).unwrap_or_else(|| "-".to_string()),
+                        fact.mentions.to_string(),
+                        format!("{:.2}", fact.confidence),
+                        day(&fact.last_seen),
+                        format!("{:.0}", fact.idle_days),
+                    ]);
+                }
+                table
+            }
+            ReportSection::Personalities => {
+                let mut table = Table::new(&[
+                    "PERSONALITY",
+                    "CONVERSATIONS",
+                    "INSIGHTS",
+                    "FACTS",
+                    "PREFERENCES/CORRECTIONS",
+                    "AVG CONFIDENCE",
+                ]);
+                for row in &self.personalities {
+                    table.add_row(vec![
+                        row.personality.clone(),
+                        row.conversations.to_string(),
+                        row.insights.to_string(),
+                        row.facts.to_string(),
+                        row.user_statements.to_string(),
+                        format!("{:.2}", row.average_confidence),
+                    ]);
+                }
+                table
+            }
+        }
+    }
+
+    /// Prints one section, or all of them. As CSV, each section of a full
+    /// report starts with a `# name` line.
+    pub fn print(&self, format: OutputFormat, section: Option<ReportSection>) -> Result<(), serde_json::Error> {
+        let sections: Vec<ReportSection> = match section {
+            Some(section) => vec![section],
+            None => ReportSection::ALL.to_vec(),
+        };
+        match format {
+            OutputFormat::Json => {
+                let json = match section {
+                    Some(section) => serde_json::to_string_pretty(&serde_json::to_value(self)?[section.name()])?,
+                    None => serde_json::to_string_pretty(self)?,
+                };
+                println!("{}", json);
+            }
+            OutputFormat::Csv => {
+                for (i, section) in sections.iter().enumerate() {
+                    if sections.len() > 1 {
+                        if i > 0 {
+                            println!();
+                        }
+                        println!("# {}", section.name());
+                    }
+                    self.table(*section).print(format);
+                }
+            }
+            OutputFormat::Table => {
+                println!("Learning report for '{}'", self.user_id);
+                for section in sections {
+                    let table = self.table(section);
+                    println!("\n{}", self.title(section));
+                    if table.is_empty() {
+                        println!("  nothing yet");
+                    } else {
+                        table.print(format);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Weekly new and running totals from counts per week, keeping the last `weeks`
+fn growth(
+    insights: impl Iterator<Item = (String, i64)>,
+    kb_entries: impl Iterator<Item = (String, i64)>,
+    weeks: usize,
+) -> Vec<Growth> {
+    let mut by_week: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+    for (week, count) in insights {
+        by_week.entry(week).or_default().0 += count;
+    }
+    for (week, count) in kb_entries {
+        by_week.entry(week).or_default().1 += count;
+    }
+
+    let (mut insights, mut kb_entries) = (0, 0);
+    let mut rows: Vec<Growth> = by_week
+        .into_iter()
+        .map(|(week, (new_insights, new_kb_entries))| {
+            insights += new_insights;
+            kb_entries += new_kb_entries;
+            Growth { week, new_insights, insights, new_kb_entries, kb_entries }
+        })
+        .collect();
+    rows.drain(..rows.len().saturating_sub(weeks));
+    rows
+}
+
+impl LearningManager {
+    pub async fn report(&self, user_id: &str, options: &ReportOptions) -> Result<LearningReport, Box<dyn std::error::Error>> {
+        LearningReport::build(&self.db, user_id, self.shared_knowledge, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weeks(counts: &[(&str, i64)]) -> std::vec::IntoIter<(String, i64)> {
+        counts.iter().map(|(week, count)| (week.to_string(), *count)).collect::<Vec<_>>().into_iter()
+    }
+
+    fn totals(rows: &[Growth]) -> Vec<(&str, i64, i64, i64, i64)> {
+        rows.iter().map(|r| (r.week.as_str(), r.new_insights, r.insights, r.new_kb_entries, r.kb_entries)).collect()
+    }
+
+    #[test]
+    fn growth_keeps_running_totals() {
+        let insights = [("2026-01-05", 3), ("2026-01-12", 2), ("2026-01-26", 1)];
+        let kb_entries = [("2026-01-12", 4), ("2026-01-19", 1)];
+        let rows = growth(weeks(&insights), weeks(&kb_entries), 10);
+        assert_eq!(
+            totals(&rows),
+            [
+                ("2026-01-05", 3, 3, 0, 0),
+                ("2026-01-12", 2, 5, 4, 4),
+                ("2026-01-19", 0, 5, 1, 5),
+                ("2026-01-26", 1, 6, 0, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn growth_keeps_the_last_weeks_with_totals_from_before() {
+        let insights = [("2026-01-05", 3), ("2026-01-12", 2), ("2026-01-19", 1)];
+        let rows = growth(weeks(&insights), weeks(&[]), 2);
+        assert_eq!(totals(&rows), [("2026-01-12", 2, 5, 0, 0), ("2026-01-19", 1, 6, 0, 0)]);
+        assert!(growth(weeks(&insights), weeks(&[]), 0).is_empty());
+    }
+}
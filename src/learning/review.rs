@@ -0,0 +1,292 @@
+// src/learning/review.rs
+// Learned content is unverified model output, so it waits in a review
+// queue and only reaches the knowledge base once it is approved by a
+// user, corroborated by independent sources, or trusted by policy.
+use std::fmt;
+use std::str::FromStr;
+use log::info;
+use crate::database::{PendingRecord, QueuedContent};
+use crate::knowledge_base::embedding::{self, EMBEDDING_MODEL};
+use crate::knowledge_base::knowledge_base::{KnowledgeEntry, Provenance};
+use super::LearningManager;
+
+// Queued content this similar to new content counts as the same thing said again
+const CORROBORATION_SIMILARITY: f32 = 0.8;
+const DEFAULT_CORROBORATIONS: u32 = 3;
+
+/// When learned content is promoted from the review queue to the knowledge base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrustPolicy {
+    /// Only when a user approves it
+    #[default]
+    Review,
+    /// Once this many independent sources said the same thing, or a user
+    /// approves it. The model repeating itself to one user counts once;
+    /// other users, and a user saying it in their own words, count again.
+    Corroborated(u32),
+    /// Straight away
+    Trust,
+}
+
+impl TrustPolicy {
+    fn promotes(&self, corroborations: u32) -> bool {
+        match self {
+            TrustPolicy::Review => false,
+            TrustPolicy::Corroborated(needed) => corroborations >= *needed,
+            TrustPolicy::Trust => true,
+        }
+    }
+}
+
+impl FromStr for TrustPolicy {
+    type Err = String;
+
+    /// "review", "trust", "corroborate" or "corroborate:N"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "review" => Ok(TrustPolicy::Review),
+            "trust" => Ok(TrustPolicy::Trust),
+            "corroborate" => Ok(TrustPolicy::Corroborated(DEFAULT_CORROBORATIONS)),
+            other => other
+                .strip_prefix("corroborate:")
+                .and_then(|n| n.parse().ok())
+                .filter(|n| *n > 0)
+                .map(TrustPolicy::Corroborated)
+                .ok_or_else(|| format!("Unknown learning policy '{}': use review, trust, corroborate or corroborate:N", s)),
+        }
+    }
+}
+
+/// The queued item whose vector is most like `vector`, if any is close
+/// enough to count as the same thing said again.
+pub(super) fn similar_pending(vector: &[u8], pending: &[(i64, Vec<u8>)]) -> Option<i64> {
+    let vector = embedding::from_bytes(vector);
+    pending
+        .iter()
+        .map(|(id, other)| (embedding::similarity(&vector, &embedding::from_bytes(other)), *id))
+        .filter(|(similarity, _)| *similarity >= CORROBORATION_SIMILARITY)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, id)| id)
+}
+
+impl fmt::Display for TrustPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrustPolicy::Review => write!(f, "review"),
+            TrustPolicy::Corroborated(needed) => write!(f, "corroborate:{}", needed),
+            TrustPolicy::Trust => write!(f, "trust"),
+        }
+    }
+}
+
+impl LearningManager {
+    pub fn policy(&self) -> TrustPolicy {
+        self.policy
+    }
+
+    /// Content learned from an interaction, ready for the review queue, or
+    /// None if there is nothing to queue.
+    pub(super) fn queued_content(topics: &[String], content: &str, provenance: Provenance) -> Option<QueuedContent> {
+        let content = content.trim();
+        if topics.is_empty() || content.is_empty() {
+            return None;
+        }
+        Some(QueuedContent {
+            vector: embedding::to_bytes(&embedding::embed(content)),
+            topics: topics.to_vec(),
+            content: content.to_string(),
+            provenance,
+        })
+    }
+
+    pub(super) async fn promote_if_trusted(&self, id: i64, corroborations: u32) -> Result<(), Box<dyn std::error::Error>> {
+        if self.policy.promotes(corroborations) {
+            if let Some(item) = self.db.pending_get(id).await? {
+                self.promote(&item).await?;
+                info!("Promoted learned item {} after {} corroborations", id, corroborations);
+            }
+        }
+        Ok(())
+    }
+
+    /// Items waiting for review that `user_id` may approve or reject.
+    pub async fn pending(&self, user_id: &str) -> Result<Vec<PendingRecord>, Box<dyn std::error::Error>> {
+        Ok(self.db.pending_list(self.owner(user_id)).await?)
+    }
+
+    /// Moves a queued item into the knowledge base. Returns false if there
+    /// is no such pending item for this user.
+    pub async fn approve(&self, user_id: &str, id: i64) -> Result<bool, Box<dyn std::error::Error>> {
+        match self.reviewable(user_id, id).await? {
+            Some(item) => self.promote(&item).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Drops a queued item. Returns false if there is no such pending item
+    /// for this user.
+    pub async fn reject(&self, user_id: &str, id: i64) -> Result<bool, Box<dyn std::error::Error>> {
+        match self.reviewable(user_id, id).await? {
+            Some(item) => Ok(self.db.pending_set_status(item.id, "rejected").await?),
+            None => Ok(false),
+        }
+    }
+
+    // With shared knowledge everyone reviews one queue, otherwise their own
+    pub(super) fn owner(&self, user_id: &str) -> Option<String> {
+        if self.shared_knowledge {
+            None
+        } else {
+            Some(user_id.to_string())
+        }
+    }
+
+    /// Vectorizes queued items that have no vector yet, such as those
+    /// queued before vectors were stored.
+    pub(super) async fn embed_pending(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let missing = self.db.pending_missing_embeddings(EMBEDDING_MODEL.to_string()).await?;
+        if !missing.is_empty() {
+            let vectors = missing
+                .iter()
+                .map(|(id, content)| (*id, embedding::to_bytes(&embedding::embed(content))))
+                .collect();
+            self.db.pending_store_embeddings(EMBEDDING_MODEL.to_string(), vectors).await?;
+        }
+        Ok(())
+    }
+
+    async fn reviewable(&self, user_id: &str, id: i64) -> Result<Option<PendingRecord>, Box<dyn std::error::Error>> {
+        Ok(self
+            .db
+            .pending_get(id)
+            .await?
+            .filter(|item| item.status == "pending")
+            .filter(|item| self.shared_knowledge || item.user_id == user_id))
+    }
+
+    // Writes the item to the shared knowledge base, or to its user's topic
+    // entries when knowledge is not shared
+    async fn promote(&self, item: &PendingRecord) -> Result<bool, Box<dyn std::error::Error>> {
+        // Claim it first, so it can't be promoted twice
+        if !self.db.pending_set_status(item.id, "promoted").await? {
+            return Ok(false);
+        }
+
+        if self.shared_knowledge {
+            let mut entry = KnowledgeEntry::new(item.topics.clone(), &item.content, "learned");
+            entry.provenance = item.provenance.clone();
+            self.knowledge_base.insert(entry).await?;
+        } else {
+            for topic in &item.topics {
+                let key = format!("kb:{}", topic);
+                let entry = match self.db.get_knowledge(item.user_id.clone(), key.clone()).await? {
+                    Some(existing) if existing.contains(&item.content) => continue,
+                    Some(existing) => format!("{}\n{}", existing, item.content),
+                    None => item.content.clone(),
+                };
+                self.db.save_knowledge(item.user_id.clone(), key, entry).await?;
+            }
+            self.cache.lock().await.forget_promoted(&item.user_id);
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, LearnedTurn};
+    use crate::learning::UserProfile;
+
+    #[test]
+    fn policies_parse() {
+        assert_eq!("review".parse(), Ok(TrustPolicy::Review));
+        assert_eq!(" Trust ".parse(), Ok(TrustPolicy::Trust));
+        assert_eq!("corroborate".parse(), Ok(TrustPolicy::Corroborated(DEFAULT_CORROBORATIONS)));
+        assert_eq!("corroborate:5".parse(), Ok(TrustPolicy::Corroborated(5)));
+        for bad in ["corroborate:0", "corroborate:x", "corroborate:", "sometimes", ""] {
+            assert!(bad.parse::<TrustPolicy>().is_err(), "{} parsed", bad);
+        }
+    }
+
+    #[test]
+    fn review_is_the_default() {
+        assert_eq!(TrustPolicy::default(), TrustPolicy::Review);
+    }
+
+    #[test]
+    fn promotion_threshold() {
+        assert!(!TrustPolicy::Review.promotes(100));
+        assert!(TrustPolicy::Trust.promotes(1));
+        assert!(!TrustPolicy::Corroborated(3).promotes(2));
+        assert!(TrustPolicy::Corroborated(3).promotes(3));
+        assert!(TrustPolicy::Corroborated(3).promotes(4));
+    }
+
+    fn turn(user_id: &str, conversation_id: i64, stated: Option<&str>, answer: Option<&str>) -> LearnedTurn {
+        LearnedTurn {
+            user_id: user_id.to_string(),
+            conversation_id: Some(conversation_id),
+            insights: Vec::new(),
+            profile: UserProfile::default(),
+            queue_owner: None,
+            stated: stated.map(|s| embedding::to_bytes(&embedding::embed(s))),
+            queue: answer.and_then(|answer| {
+                let provenance = Provenance::Model {
+                    conversation_id: Some(conversation_id),
+                    user_id: user_id.to_string(),
+                    model: "test".to_string(),
+                };
+                LearningManager::queued_content(&["rust".to_string()], answer, provenance)
+            }),
+        }
+    }
+
+    // Everything in the queue says the same thing here
+    fn first(_: &[u8], pending: &[(i64, Vec<u8>)]) -> Option<i64> {
+        pending.first().map(|(id, _)| *id)
+    }
+
+    async fn corroborations(db: &Database, turns: Vec<LearnedTurn>) -> u32 {
+        let results = db.save_learned(turns, EMBEDDING_MODEL.to_string(), first).await.unwrap();
+        results.into_iter().filter_map(|r| r.unwrap().last().map(|(_, n)| *n)).last().unwrap()
+    }
+
+    #[tokio::test]
+    async fn one_user_hearing_the_same_answer_counts_once() {
+        let db = Database::new(":memory:").await.unwrap();
+        let answer = Some("Rust has no garbage collector.");
+        let turns = (1..=5).map(|id| turn("alice", id, None, answer)).collect();
+        assert_eq!(corroborations(&db, turns).await, 1);
+    }
+
+    #[tokio::test]
+    async fn other_users_and_statements_count() {
+        let db = Database::new(":memory:").await.unwrap();
+        let answer = Some("Rust has no garbage collector.");
+        let turns = vec![
+            turn("alice", 1, None, answer),
+            turn("bob", 2, None, answer),
+            // Alice stating it counts, once per conversation
+            turn("alice", 3, Some("Rust has no garbage collector"), None),
+            turn("alice", 3, Some("Rust has no garbage collector"), None),
+        ];
+        assert_eq!(corroborations(&db, turns).await, 3);
+    }
+
+    #[tokio::test]
+    async fn queued_vectors_are_stored_and_matched() {
+        let db = Database::new(":memory:").await.unwrap();
+        let turns = vec![
+            turn("alice", 1, None, Some("Rust has no garbage collector.")),
+            turn("bob", 2, None, Some("Rust has no garbage collector")),
+            turn("bob", 3, None, Some("Python uses reference counting.")),
+        ];
+        let results = db.save_learned(turns, EMBEDDING_MODEL.to_string(), similar_pending).await.unwrap();
+        let backed: Vec<_> = results.into_iter().map(|r| r.unwrap()).collect();
+        assert_eq!(backed[1], vec![(backed[0][0].0, 2)]);
+        assert_eq!(backed[2].len(), 1);
+        assert_ne!(backed[2][0].0, backed[0][0].0);
+        assert!(db.pending_missing_embeddings(EMBEDDING_MODEL.to_string()).await.unwrap().is_empty());
+    }
+}
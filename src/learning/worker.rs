@@ -0,0 +1,182 @@
+// src/learning/worker.rs
+// Learns from exchanges in the background, so the answer is shown
+// without waiting for database writes or fact extraction. Exchanges wait
+// in a bounded queue: when it is full, handing one over waits for room
+// instead of dropping it. Whatever has queued up is learned as one batch,
+// and the queue is drained before the program exits.
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use log::{info, warn};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::completion::CompletionProvider;
+use super::LearningManager;
+
+// Exchanges waiting to be learned at most
+const QUEUE_CAPACITY: usize = 64;
+// Exchanges learned together, their insights in one transaction
+const MAX_BATCH: usize = 16;
+
+/// One exchange to learn from.
+#[derive(Debug, Clone)]
+pub struct Interaction {
+    pub user_id: String,
+    pub conversation_id: Option<i64>,
+    /// The model that wrote the response
+    pub model: String,
+    pub user_input: String,
+    pub ai_response: String,
+}
+
+#[derive(Default)]
+struct Counters {
+    in_progress: AtomicUsize,
+    learned: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+/// What the worker is up to.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueStatus {
+    pub queued: usize,
+    pub capacity: usize,
+    /// In the batch being learned right now
+    pub in_progress: usize,
+    pub learned: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for QueueStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Queued:      {} of {}", self.queued, self.capacity)?;
+        writeln!(f, "In progress: {}", self.in_progress)?;
+        writeln!(f, "Learned:     {}", self.learned)?;
+        write!(f, "Failed:      {}", self.failed)
+    }
+}
+
+pub struct LearningWorker {
+    sender: mpsc::Sender<Interaction>,
+    counters: Arc<Counters>,
+    handle: JoinHandle<()>,
+}
+
+impl LearningWorker {
+    /// Starts the worker. With an `extractor`, facts and preferences are
+    /// also extracted by the model from each exchange.
+    pub fn start<P>(manager: LearningManager, extractor: Option<P>) -> Self
+    where
+        P: CompletionProvider + Send + Sync + 'static,
+    {
+        let (sender, mut receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let counters = Arc::new(Counters::default());
+        let worker_counters = counters.clone();
+
+        let handle = tokio::spawn(async move {
+            let counters = worker_counters;
+            let mut batch: Vec<Interaction> = Vec::with_capacity(MAX_BATCH);
+            // Ends once the sender is dropped and the queue is empty
+            while receiver.recv_many(&mut batch, MAX_BATCH).await > 0 {
+                counters.in_progress.store(batch.len(), Ordering::Relaxed);
+
+                let failed = match manager.learn_from_interactions(&batch).await {
+                    Ok(failed) => failed,
+                    Err(e) => {
+                        warn!("Failed to learn from {} interactions: {}", batch.len(), e);
+                        batch.len()
+                    }
+                };
+                if let Some(provider) = &extractor {
+                    for interaction in &batch {
+                        let result = manager
+                            .learn_with_model(
+                                provider,
+                                &interaction.user_id,
+                                interaction.conversation_id,
+                                &interaction.user_input,
+                                &interaction.ai_response,
+                            )
+                            .await;
+                        if let Err(e) = result {
+                            warn!("Failed to extract facts from the exchange: {}", e);
+                        }
+                    }
+                }
+
+                counters.learned.fetch_add(batch.len() - failed, Ordering::Relaxed);
+                counters.failed.fetch_add(failed, Ordering::Relaxed);
+                counters.in_progress.store(0, Ordering::Relaxed);
+                batch.clear();
+            }
+        });
+
+        Self { sender, counters, handle }
+    }
+
+    /// Queues an exchange, waiting for room if the queue is full.
+    pub async fn send(&self, interaction: Interaction) {
+        if self.sender.send(interaction).await.is_err() {
+            warn!("The learning worker has stopped; not learning from this exchange");
+        }
+    }
+
+    pub fn status(&self) -> QueueStatus {
+        QueueStatus {
+            queued: self.sender.max_capacity() - self.sender.capacity(),
+            capacity: self.sender.max_capacity(),
+            in_progress: self.counters.in_progress.load(Ordering::Relaxed),
+            learned: self.counters.learned.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Learns from everything still queued, then stops the worker.
+    pub async fn shutdown(self) {
+        let status = self.status();
+        if status.queued + status.in_progress > 0 {
+            info!("Learning from {} queued interactions before exiting", status.queued + status.in_progress);
+        }
+        drop(self.sender);
+        if let Err(e) = self.handle.await {
+            warn!("The learning worker failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::knowledge_base::knowledge_base::KnowledgeBaseHandler;
+    use crate::learning::{ConflictPolicy, TrustPolicy};
+    use crate::providers::deepseek::DeepSeekProvider;
+
+    #[tokio::test]
+    async fn shutdown_learns_everything_queued() {
+        let db = Database::new(":memory:").await.unwrap();
+        let knowledge_base = KnowledgeBaseHandler::with_database(db.clone());
+        let manager = LearningManager::new(db.clone(), knowledge_base, false, TrustPolicy::Review, ConflictPolicy::Recency);
+        let worker = LearningWorker::start(manager, None::<DeepSeekProvider>);
+
+        // More than one batch, and nothing is waited for before shutting down
+        let inputs: Vec<String> = (0..MAX_BATCH * 2 + 3).map(|i| format!("Tell me about topic{} databases", i)).collect();
+        for input in &inputs {
+            worker
+                .send(Interaction {
+                    user_id: "alice".to_string(),
+                    conversation_id: None,
+                    model: "test".to_string(),
+                    user_input: input.clone(),
+                    ai_response: "Databases store data.".to_string(),
+                })
+                .await;
+        }
+        worker.shutdown().await;
+
+        let knowledge = db.get_all_knowledge("alice".to_string()).await.unwrap();
+        for input in &inputs {
+            assert!(knowledge.iter().any(|record| record.value.contains(input.as_str())), "{} was not learned", input);
+        }
+    }
+}
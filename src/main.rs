@@ -1,18 +1,36 @@
+
 use std::env;
 use std::io::Write;
-use clap::Parser;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use clap::{Parser, Subcommand};
 use colored::Colorize;
 use dotenv::dotenv;
-use log::{info, error};
+use log::{info, error, warn};
+use sha2::{Digest, Sha256};
 
-use crate::memory::{ShortTermMemory, LongTermMemory};
+use crate::memory::{ScopedMemory, LongTermMemory};
+use crate::memory::bundle::{self, ImportMode};
 use crate::providers::deepseek::DeepSeekProvider;
 use crate::completion::CompletionProvider;
-use crate::knowledge_base::knowledge_base::KnowledgeBaseHandler;
-use crate::database::Database;
-use crate::learning::LearningManager;
+use crate::knowledge_base::knowledge_base::{KnowledgeBaseHandler, KnowledgeEntry, Provenance, RetrievedEntry, SearchOptions};
+use crate::knowledge_base::ingest::{self, IngestOptions};
+use crate::knowledge_base::benchmark;
+use crate::knowledge_base::cli::{self as kb_cli, KbCommand};
+use crate::output::OutputFormat;
+use crate::database::{Database, DEFAULT_USER, GLOBAL_USER};
+use crate::learning::{
+    ConflictPolicy, Feedback, Interaction, LearningManager, LearningReport, LearningWorker, ReportOptions, ReportSection,
+    TrustPolicy, UserProfile,
+};
 use crate::personality::{Personality, PersonalityProfile};
+use crate::commands::{Command, preview};
+use crate::crypto::Cipher;
+use crate::prompt::Prompt;
+use crate::watcher::FileWatcher;
 
+mod commands;
+mod crypto;
 mod memory;
 mod providers;
 mod completion;
@@ -20,12 +38,147 @@ mod knowledge_base;
 mod database;
 mod learning;
 mod personality;
+mod output;
+mod prompt;
+mod text;
+mod keyphrase;
+mod watcher;
+
+const DATABASE_PATH: &str = "data/agent.db";
+const MEMORY_PATH: &str = "memory.json";
+const KNOWLEDGE_BASE_PATH: &str = "data/knowledge_base.json";
+const CHARACTERS_DIR: &str = "characters";
+const SEARCH_BENCHMARK_PATH: &str = "data/benchmarks/kb_search.json";
+const TOPIC_BENCHMARK_PATH: &str = "data/benchmarks/keyphrases.json";
+// Knowledge base entries injected into each prompt at most
+const REFERENCE_LIMIT: usize = 3;
+// BM25 score below which a knowledge base match is treated as noise
+const REFERENCE_MIN_SCORE: f32 = 0.5;
+
+// What went into the last answer, for inspection and feedback commands
+#[derive(Default)]
+struct LastTurn {
+    input: Option<String>,
+    prompt: Option<String>,
+    references: Vec<RetrievedEntry>,
+    // Saved conversation row of the answer, for feedback
+    conversation_id: Option<i64>,
+}
 
 // Command-line arguments
 #[derive(Parser, Debug)]
 struct Args {
     #[arg(short, long)]
     api_key: Option<String>,
+
+    /// Who is talking to the agent; history and learned facts are kept per user
+    #[arg(short, long)]
+    user: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+// Subcommands run once and exit instead of starting the chat
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Export or import the user's memory
+    #[command(subcommand)]
+    Memory(MemoryCommand),
+    /// Manage the encryption key for data at rest
+    #[command(subcommand)]
+    Crypto(CryptoCommand),
+    /// Curate the knowledge base
+    Kb {
+        #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+        #[command(subcommand)]
+        command: KbCommand,
+    },
+    /// Load documents or whole directories into the knowledge base
+    Ingest {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Target chunk length in bytes
+        #[arg(long, default_value_t = IngestOptions::default().chunk_size)]
+        chunk_size: usize,
+        /// Bytes repeated between consecutive chunks
+        #[arg(long, default_value_t = IngestOptions::default().overlap)]
+        overlap: usize,
+    },
+    /// Measure knowledge base search quality on a benchmark corpus
+    SearchBench {
+        #[arg(long, default_value = SEARCH_BENCHMARK_PATH)]
+        corpus: String,
+        #[arg(long, default_value_t = 5)]
+        top_k: usize,
+    },
+    /// Report on what has been learned
+    Learning {
+        #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+        #[command(subcommand)]
+        command: LearningCommand,
+    },
+    /// Measure topic extraction against texts with expected topics
+    TopicBench {
+        #[arg(long, default_value = TOPIC_BENCHMARK_PATH)]
+        corpus: String,
+        #[arg(long, default_value_t = 5)]
+        top_k: usize,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CryptoCommand {
+    /// Generate a new random key
+    Keygen {
+        /// Write the key to this file instead of printing it
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Re-encrypt all stores from the current key to a new one
+    Rotate {
+        /// File holding the new key (see `crypto keygen`)
+        #[arg(long, required_unless_present = "plaintext")]
+        new_key_file: Option<String>,
+        /// Decrypt everything instead of switching to a new key
+        #[arg(long, conflicts_with = "new_key_file")]
+        plaintext: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum LearningCommand {
+    /// Topics, sources, growth, reused and stale facts, and personalities
+    Report {
+        /// Weeks shown for topics and growth
+        #[arg(long, default_value_t = ReportOptions::default().weeks)]
+        weeks: usize,
+        /// Topics per week, and facts listed
+        #[arg(long, default_value_t = ReportOptions::default().top)]
+        top: usize,
+        /// Facts not used in a prompt for this many days count as stale
+        #[arg(long, default_value_t = ReportOptions::default().stale_days)]
+        stale_days: u32,
+        /// Only this section (handy for CSV)
+        #[arg(long, value_enum)]
+        section: Option<ReportSection>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MemoryCommand {
+    /// Write every memory layer to a JSONL bundle
+    Export {
+        path: String,
+    },
+    /// Load a bundle written by `memory export`
+    Import {
+        path: String,
+        #[arg(long, value_enum, default_value_t = ImportMode::Merge)]
+        mode: ImportMode,
+    },
 }
 
 // Main function
@@ -41,67 +194,260 @@ async fn main() {
     // Parse command-line arguments
     let args = Args::parse();
 
+    // Set up encryption at rest before any store is opened
+    match Cipher::from_env() {
+        Ok(cipher) => crypto::init(cipher),
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red(), e);
+            std::process::exit(1);
+        }
+    }
+
+    // Use user from command line or environment variable
+    let user_id = args.user
+        .or_else(|| env::var("AGENT_USER").ok())
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty())
+        .unwrap_or_else(|| DEFAULT_USER.to_string());
+    if user_id == GLOBAL_USER {
+        eprintln!("'{}' is reserved for shared knowledge, pick another user name", GLOBAL_USER);
+        std::process::exit(1);
+    }
+    let shared_knowledge = env::var("AGENT_SHARED_KNOWLEDGE")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    // Off unless turned on: one extra completion per exchange, which costs
+    // money and sends the conversation to the model a second time
+    let llm_extraction = env::var("AGENT_LLM_EXTRACTION")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    let trust_policy = match env::var("AGENT_LEARNING_POLICY") {
+        Ok(value) => value.parse().unwrap_or_else(|e: String| {
+            eprintln!("{} {}", "Error:".red(), e);
+            std::process::exit(1);
+        }),
+        Err(_) => TrustPolicy::default(),
+    };
+    let conflict_policy = match env::var("AGENT_CONFLICT_POLICY") {
+        Ok(value) => value.parse().unwrap_or_else(|e: String| {
+            eprintln!("{} {}", "Error:".red(), e);
+            std::process::exit(1);
+        }),
+        Err(_) => ConflictPolicy::default(),
+    };
+
+    // Subcommands don't talk to the model, so they need no API key
+    if let Some(command) = args.command {
+        if let Err(e) = run_cli_command(command, &user_id, shared_knowledge).await {
+            eprintln!("{} {}", "Error:".red(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Use API key from command line or environment variable
     let api_key = args.api_key
         .or_else(|| env::var("DEEPSEEK_API_KEY").ok())
         .expect("DeepSeek API key not provided")
         .clone(); // Clone the api_key here
 
-    // Initialize memories
-    let mut short_term_memory = ShortTermMemory::new();
-    let long_term_memory = LongTermMemory::new();
-
     // Initialize with default personality
     let mut current_personality = Personality::HelpfulAssistant.clone();
     let mut deepseek_provider = DeepSeekProvider::new(api_key.clone(), current_personality.clone());
 
     // Initialize database
-    let database = Database::new("data/agent.db")
-        .await
-        .expect("Failed to initialize database");
+    let database = match Database::new(DATABASE_PATH).await {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red(), e);
+            std::process::exit(1);
+        }
+    };
+
+    // Initialize memories, restoring this user's recent history
+    let mut short_term_memory = ScopedMemory::new(&user_id);
+    activate_character_memory(&database, &mut short_term_memory, &current_personality).await;
+    let long_term_memory = LongTermMemory::load_from_file(MEMORY_PATH, &user_id)
+        .unwrap_or_else(|e| {
+            error!("Failed to load long-term memory: {}", e);
+            LongTermMemory::new(&user_id)
+        });
 
     // Initialize knowledge base handler
-    let knowledge_base_handler = KnowledgeBaseHandler::new("data/knowledge_base.json");
+    let knowledge_base_handler = KnowledgeBaseHandler::new(database.clone(), KNOWLEDGE_BASE_PATH)
+        .await
+        .expect("Failed to initialize knowledge base");
 
     // Initialize learning manager
-    let learning_manager = LearningManager::new(database.clone(), knowledge_base_handler.clone());
+    let learning_manager = LearningManager::new(database.clone(), knowledge_base_handler.clone(), shared_knowledge, trust_policy, conflict_policy);
+    // Fact extraction gets a neutral persona of its own
+    let extraction_provider = llm_extraction.then(|| DeepSeekProvider::new(api_key.clone(), Personality::HelpfulAssistant));
+    // Learns from each exchange in the background, after the reply is shown
+    let learning_worker = LearningWorker::start(learning_manager.clone(), extraction_provider);
 
     // Welcome message with colored output
     println!("{}", "Welcome to the Rust AI Agent!".green());
+    println!("Chatting as user '{}'.", user_id.cyan());
     println!("Type '{}' to quit.", "exit".red());
     println!("Available personalities:");
     println!("  - Type '{}' for Helpful Assistant", "helpful".cyan());
     println!("  - Type '{}' for Friendly Chat", "friendly".cyan());
     println!("  - Type '{}' for Expert Advisor", "expert".cyan());
     println!("  - Type a personality filename (e.g., 'masterchef_scientist.json') to load a custom personality");
+    println!("Type '{}' for memory and other commands.", "/help".cyan());
+
+    let mut last_turn = LastTurn::default();
+
+    // Pick up edits to the knowledge base and character files while chatting
+    let mut file_watcher = FileWatcher::start(&["data", CHARACTERS_DIR])
+        .map_err(|e| warn!("Not watching for file changes: {}", e))
+        .ok();
+    let mut active_character_file: Option<String> = None;
+    // Our own writes to the database leave this unchanged
+    let mut database_version = database.data_version().await.unwrap_or_default();
+    let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+    // Made once, so a Ctrl-C while waiting for an answer still quits at the next prompt
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
 
     loop {
         // User input prompt
         print!("{} ", "You:".cyan());
         std::io::stdout().flush().unwrap();
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        let input = input.trim();
+
+        // File changes are applied as they happen, also while waiting for input
+        let line = loop {
+            let changed = async {
+                match file_watcher.as_mut() {
+                    Some(watcher) => watcher.changed().await,
+                    None => std::future::pending().await,
+                }
+            };
+            let paths = tokio::select! {
+                line = stdin.next_line() => break line,
+                paths = changed => paths,
+                // Quits like "exit", so memory is saved and queued learning finishes
+                _ = &mut interrupted => {
+                    println!();
+                    break Ok(None);
+                }
+            };
+
+            let mut messages = Vec::new();
+            for path in paths {
+                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+                if path.ends_with(KNOWLEDGE_BASE_PATH) {
+                    match knowledge_base_handler.sync_file(KNOWLEDGE_BASE_PATH).await {
+                        Ok((0, 0, 0)) => {}
+                        Ok((added, updated, removed)) => messages.push(format!(
+                            "{} {} added, {} updated, {} removed",
+                            "Reloaded the knowledge base:".blue(),
+                            added,
+                            updated,
+                            removed
+                        )),
+                        Err(e) => messages.push(format!("{} {}", "Knowledge base file is invalid, keeping the current entries:".red(), e)),
+                    }
+                } else if name.starts_with("agent.db") {
+                    // Only when another process (e.g. `kb edit`) changed entries
+                    match database.data_version().await {
+                        Ok(version) if version != database_version => {
+                            database_version = version;
+                            knowledge_base_handler.reload();
+                            learning_manager.clear_cache().await;
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("Failed to check the database for changes: {}", e),
+                    }
+                } else if name.ends_with(".json") && path.parent().is_some_and(|p| p.ends_with(CHARACTERS_DIR)) {
+                    match read_character_file(&path) {
+                        Ok(profile) if active_character_file.as_deref() == Some(name.as_str()) => {
+                            current_personality = Personality::Custom(Box::new(profile));
+                            deepseek_provider = DeepSeekProvider::new(api_key.clone(), current_personality.clone());
+                            activate_character_memory(&database, &mut short_term_memory, &current_personality).await;
+                            messages.push(format!("{} {}", "Reloaded personality:".blue(), current_personality.to_string().cyan()));
+                        }
+                        Ok(_) => {}
+                        Err(e) => messages.push(e.red().to_string()),
+                    }
+                }
+            }
+
+            // Shown below the pending prompt, which is then shown again
+            if !messages.is_empty() {
+                println!();
+                for message in messages {
+                    println!("{}", message);
+                }
+                print!("{} ", "You:".cyan());
+                std::io::stdout().flush().unwrap();
+            }
+        };
+        // End of input and Ctrl-C quit like "exit"
+        let line = match line {
+            Ok(Some(line)) => line,
+            Ok(None) => "exit".to_string(),
+            Err(e) => {
+                error!("Failed to read input: {}", e);
+                "exit".to_string()
+            }
+        };
+        let mut input = line.trim();
 
         // Exit command
         if input.eq_ignore_ascii_case("exit") {
             println!("{}", "Goodbye!".green());
             // Save long-term memory to file before exiting
-            if let Err(e) = long_term_memory.save_to_file("memory.json") {
+            if let Err(e) = long_term_memory.save_to_file(MEMORY_PATH) {
                 error!("Failed to save long-term memory: {}", e);
             }
+            learning_worker.shutdown().await;
             break;
         }
 
+        // Slash commands are handled locally and never reach the model
+        let regenerate;
+        if let Some(command) = Command::parse(input) {
+            match command {
+                // Answers the previous message again instead
+                Ok(Command::Regenerate) => match last_turn.input.clone() {
+                    Some(previous) => {
+                        if let Some(id) = last_turn.conversation_id {
+                            if let Err(e) = learning_manager.record_feedback(&user_id, id, Feedback::Regenerate).await {
+                                error!("Failed to record feedback: {}", e);
+                            }
+                        }
+                        regenerate = previous;
+                        input = &regenerate;
+                    }
+                    None => {
+                        println!("{}", "There is no answer to regenerate yet.".red());
+                        continue;
+                    }
+                },
+                Ok(command) => {
+                    handle_command(command, &mut short_term_memory, &database, &learning_manager, &learning_worker, &last_turn).await;
+                    continue;
+                }
+                Err(message) => {
+                    println!("{}", message.red());
+                    continue;
+                }
+            }
+        }
+
         // Check for personality filename loading
         if input.ends_with(".json") {
             if let Some(custom_personality) = load_personality_from_filename(input) {
                 current_personality = custom_personality.clone();
+                active_character_file = Some(input.to_string());
                 deepseek_provider = DeepSeekProvider::new(api_key.clone(), current_personality.clone());
+                activate_character_memory(&database, &mut short_term_memory, &current_personality).await;
                 println!(
-                    "{} {}", 
+                    "{} {} ({} memory)", 
                     "Loaded custom personality:".blue(),
-                    current_personality.to_string().cyan()
+                    current_personality.to_string().cyan(),
+                    current_personality.memory_scope()
                 );
                 continue;
             }
@@ -110,7 +456,9 @@ async fn main() {
         // Check for personality switch
         if let Some(new_personality) = Personality::from_input(input) {
             current_personality = new_personality.clone();
+            active_character_file = None;
             deepseek_provider = DeepSeekProvider::new(api_key.clone(), current_personality.clone());
+            activate_character_memory(&database, &mut short_term_memory, &current_personality).await;
             println!(
                 "{} {}", 
                 "Switching personality to:".blue(),
@@ -119,39 +467,67 @@ async fn main() {
             continue;
         }
 
-        // Process the input and get AI response
-        let context = short_term_memory.get_context(&input);
-        let prompt = if !context.is_empty() {
-            format!(
-                "Previous relevant context:\n{}\n\nCurrent conversation:\nUser: {}\n\nRemember that you are acting as: {}", 
-                context, 
-                input,
-                current_personality.system_message()
-            )
-        } else {
-            format!(
-                "You are acting as: {}\n\nUser: {}", 
-                current_personality.system_message(),
-                input
-            )
+        // Process the input and get AI response, with matching knowledge
+        // base entries as reference material
+        let search_options = SearchOptions {
+            top_k: REFERENCE_LIMIT,
+            min_keyword_score: REFERENCE_MIN_SCORE,
+            ..SearchOptions::default()
+        };
+        let references = knowledge_base_handler
+            .search(input, search_options)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to search the knowledge base: {}", e);
+                Vec::new()
+            });
+        // Plus what was learned about the same topics in earlier conversations
+        let learned = learning_manager
+            .get_relevant_context(&user_id, input)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to look up learned context: {}", e);
+                Vec::new()
+            });
+        // Read every turn, since learning may have changed it in the background
+        match learning_manager.profile(&user_id).await {
+            Ok(profile) => deepseek_provider.set_user_profile(profile.summary()),
+            Err(e) => error!("Failed to load the user profile: {}", e),
+        }
+        let prompt = Prompt {
+            system_message: current_personality.system_message(),
+            input,
+            context: short_term_memory.get_context(input),
+            references: &references,
+            learned: &learned,
+        }
+        .render();
+
+        let result = deepseek_provider.complete(&prompt).await;
+        last_turn = LastTurn {
+            input: Some(input.to_string()),
+            prompt: Some(prompt),
+            references,
+            conversation_id: None,
         };
 
-        match deepseek_provider.complete(&prompt).await {
+        match result {
             Ok(response) => {
                 let response = response.to_string();
                 
-                // Store in short-term memory with topic tracking
-                short_term_memory.add_interaction(&input, &response);
-                
                 // Save to database
-                if let Err(e) = database.save_conversation(input.to_string(), response.clone(), current_personality.to_string()).await {
-                    error!("Failed to save conversation to database: {}", e);
-                }
+                let conversation_id = match database.save_conversation(user_id.clone(), input.to_string(), response.clone(), current_personality.to_string(), short_term_memory.active_partition().to_string()).await {
+                    Ok(id) => Some(id),
+                    Err(e) => {
+                        error!("Failed to save conversation to database: {}", e);
+                        None
+                    }
+                };
 
-                // Learn from the interaction
-                if let Err(e) = learning_manager.learn_from_interaction(&input, &response).await {
-                    error!("Failed to learn from interaction: {}", e);
-                }
+                last_turn.conversation_id = conversation_id;
+
+                // Store in short-term memory with topic tracking
+                short_term_memory.add_interaction(conversation_id, input, &response);
 
                 // Display the response with current personality
                 println!("{} [{}] {}", 
@@ -159,81 +535,520 @@ async fn main() {
                     current_personality.to_string().cyan(),
                     response
                 );
-
-                // Show memory status if requested
-                if input.contains("memory status") {
-                    println!("\n{}", short_term_memory.get_memory_stats());
-                    
-                    if let Ok(summary) = learning_manager.get_learning_summary().await {
-                        println!("\n{}", summary);
+                // Numbered like the references in the prompt, so [n] in the answer matches
+                if !last_turn.references.is_empty() {
+                    println!("{}", "Sources:".dimmed());
+                    for (i, hit) in last_turn.references.iter().enumerate() {
+                        println!("  {}", format!("[{}] {} ({})", i + 1, hit.entry.origin(), hit.entry.id).dimmed());
                     }
+                    println!("{}", "Type /source N to open one.".dimmed());
                 }
+
+                // Learn from the interaction in the background
+                learning_worker
+                    .send(Interaction {
+                        user_id: user_id.clone(),
+                        conversation_id,
+                        model: deepseek_provider.model().to_string(),
+                        user_input: input.to_string(),
+                        ai_response: response,
+                    })
+                    .await;
             }
             Err(e) => {
                 error!("Failed to prompt DeepSeek: {}", e);
-                println!("{} {}", "Assistant:".yellow(), "Error: Failed to get response from DeepSeek");
+                println!("{} Error: Failed to get response from DeepSeek", "Assistant:".yellow());
             }
         }
     }
+
+    // After Ctrl-C a read of stdin is still waiting on its own thread,
+    // which the runtime would wait for until Enter is pressed
+    std::process::exit(0);
 }
 
 use std::fs;
 
-impl std::fmt::Display for Personality {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Personality::HelpfulAssistant => write!(f, "Helpful Assistant"),
-            Personality::FriendlyChat => write!(f, "Friendly Chat"),
-            Personality::ExpertAdvisor => write!(f, "Expert Advisor"),
-            Personality::Custom(profile) => write!(f, "{}", profile.name),
+async fn run_cli_command(command: CliCommand, user_id: &str, shared_knowledge: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        CliCommand::Memory(command) => run_memory_command(command, user_id).await,
+        CliCommand::Crypto(command) => run_crypto_command(command),
+        CliCommand::Kb { format, command } => {
+            let database = Database::new(DATABASE_PATH).await?;
+            let knowledge_base = KnowledgeBaseHandler::new(database, KNOWLEDGE_BASE_PATH).await?;
+            kb_cli::run(command, format, &knowledge_base, user_id).await
+        }
+        CliCommand::Learning { format, command: LearningCommand::Report { weeks, top, stale_days, section } } => {
+            let database = Database::new(DATABASE_PATH).await?;
+            let options = ReportOptions { weeks, top, stale_days };
+            let report = LearningReport::build(&database, user_id, shared_knowledge, &options).await?;
+            report.print(format, section)?;
+            Ok(())
+        }
+        CliCommand::Ingest { paths, chunk_size, overlap } => {
+            let database = Database::new(DATABASE_PATH).await?;
+            let knowledge_base = KnowledgeBaseHandler::new(database, KNOWLEDGE_BASE_PATH).await?;
+            let options = IngestOptions { chunk_size, overlap };
+            let report = ingest::ingest(&paths, &options, &knowledge_base).await?;
+            for (path, reason) in &report.skipped {
+                println!("{} {}: {}", "Skipped".yellow(), path, reason);
+            }
+            println!("{}", report);
+            Ok(())
+        }
+        CliCommand::SearchBench { corpus, top_k } => {
+            for result in benchmark::run(&corpus, top_k).await? {
+                println!("{}", result);
+                for query in &result.misses {
+                    println!("    missed: {}", query.dimmed());
+                }
+            }
+            Ok(())
+        }
+        CliCommand::TopicBench { corpus, top_k } => {
+            let result = keyphrase::benchmark::run(&corpus, top_k)?;
+            println!("{}", result);
+            for (expected, topics) in &result.misses {
+                println!("    missed: {} (got {})", expected, topics.join(", ").dimmed());
+            }
+            Ok(())
         }
     }
 }
 
-fn load_personality_from_json(path: &str) -> Option<Personality> {
-    match fs::read_to_string(path) {
-        Ok(json_str) => {
-            match PersonalityProfile::from_json(&json_str) {
-                Ok(profile) => Some(Personality::Custom(profile)),
-                Err(e) => {
-                    error!("Failed to parse personality JSON: {}", e);
-                    None
+async fn run_memory_command(command: MemoryCommand, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let database = Database::new(DATABASE_PATH).await?;
+    let mut long_term_memory = LongTermMemory::load_from_file(MEMORY_PATH, user_id)?;
+    let knowledge_base = KnowledgeBaseHandler::new(database.clone(), KNOWLEDGE_BASE_PATH).await?;
+
+    match command {
+        MemoryCommand::Export { path } => {
+            let manifest = bundle::export_bundle(&path, &database, &long_term_memory, &knowledge_base).await?;
+            println!(
+                "Exported memory of '{}' to {}: {} conversations, {} long-term entries, {} knowledge rows, {} knowledge base entries, {} learning records",
+                user_id,
+                path,
+                manifest.conversations,
+                manifest.long_term,
+                manifest.knowledge,
+                manifest.kb_entries,
+                manifest.learning
+            );
+        }
+        MemoryCommand::Import { path, mode } => {
+            let report = bundle::import_bundle(&path, mode, &database, &mut long_term_memory, &knowledge_base).await?;
+            long_term_memory.save_to_file(MEMORY_PATH)?;
+            println!("Imported into '{}': {}", user_id, report);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_crypto_command(command: CryptoCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        CryptoCommand::Keygen { output } => {
+            let key = Cipher::generate_key();
+            match output {
+                Some(path) => {
+                    fs::write(&path, &key)?;
+                    println!("Wrote new key to {}. Keep it safe: data encrypted with it is lost without it.", path);
                 }
+                None => println!("{}", key),
             }
         }
-        Err(e) => {
-            error!("Failed to read personality JSON file: {}", e);
-            None
+        CryptoCommand::Rotate { new_key_file, plaintext } => {
+            let old = crypto::cipher();
+            let new = match new_key_file {
+                Some(path) if !plaintext => Some(Cipher::from_key_file(&path)?),
+                _ => None,
+            };
+            // The database could not be opened with the new key afterwards
+            if new.is_some() && !cfg!(feature = "sqlcipher") {
+                return Err("encrypting needs a build with --features sqlcipher, so that data/agent.db is encrypted too".into());
+            }
+
+            // The database first: it is the only step that can be refused
+            // (without SQLCipher), and the JSON stores are quick to redo
+            match Database::rotate_key(DATABASE_PATH, old, new.as_ref()) {
+                Ok(()) => println!("Rotated {}", DATABASE_PATH),
+                Err(e) if !cfg!(feature = "sqlcipher") => println!("Skipped {}: {}", DATABASE_PATH, e),
+                Err(e) => return Err(e.into()),
+            }
+            for path in [MEMORY_PATH, KNOWLEDGE_BASE_PATH] {
+                if crypto::rotate_store(path, old, new.as_ref())? {
+                    println!("Rotated {}", path);
+                }
+            }
+
+            if new.is_some() {
+                println!("Done. Point AGENT_KEY_FILE (or AGENT_ENCRYPTION_KEY) at the new key before the next run.");
+            } else {
+                println!("Done. Stores are plaintext now; unset AGENT_KEY_FILE and AGENT_ENCRYPTION_KEY.");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_command(
+    command: Command,
+    memory: &mut ScopedMemory,
+    database: &Database,
+    learning_manager: &LearningManager,
+    learning_worker: &LearningWorker,
+    last_turn: &LastTurn,
+) {
+    match command {
+        Command::Help => println!("{}", Command::help()),
+        Command::MemoryStats => {
+            println!("{}", memory.get_memory_stats());
+            if let Ok(summary) = learning_manager.get_learning_summary(memory.user_id()).await {
+                println!("\n{}", summary);
+            }
+        }
+        Command::MemoryList(limit) => {
+            let entries = memory.active().recent(limit);
+            if entries.is_empty() {
+                println!("Short-term memory is empty.");
+            }
+            for (index, conv) in entries {
+                println!(
+                    "[{}] {} relevance {:.2}, {} topics\n    User: {}\n    Assistant: {}",
+                    index.to_string().cyan(),
+                    conv.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                    conv.relevance_score,
+                    conv.topics.len(),
+                    preview(&conv.user_input, 80),
+                    preview(&conv.ai_response, 80)
+                );
+            }
+        }
+        Command::MemoryTopics => {
+            let topics = memory.active().topic_counts();
+            if topics.is_empty() {
+                println!("No topics indexed.");
+            }
+            for (topic, count) in topics {
+                println!("{:>4}  {}", count, topic);
+            }
+        }
+        Command::MemorySearch(query) => {
+            let matches = memory.active().search(&query);
+            if matches.is_empty() {
+                println!("No entries mention '{}'.", query);
+            }
+            for (index, conv) in matches {
+                println!(
+                    "[{}] User: {}\n    Assistant: {}",
+                    index.to_string().cyan(),
+                    preview(&conv.user_input, 80),
+                    preview(&conv.ai_response, 80)
+                );
+            }
+        }
+        Command::MemoryDelete(index) => match memory.active_mut().remove(index) {
+            Some(conv) => {
+                // Also drop it from the database, or it would come back on restart
+                if let Some(id) = conv.id {
+                    if let Err(e) = database.delete_conversation(id).await {
+                        error!("Failed to delete conversation {}: {}", id, e);
+                    }
+                }
+                println!("Forgot entry {}: {}", index, preview(&conv.user_input, 60));
+            }
+            None => println!("{}", format!("No entry with index {}", index).red()),
+        },
+        Command::MemoryContext => match &last_turn.prompt {
+            Some(prompt) => println!("{}", prompt),
+            None => println!("No prompt has been sent yet."),
+        },
+        Command::Sources => {
+            if last_turn.references.is_empty() {
+                println!("No reference material was used for the last answer.");
+            }
+            for (i, hit) in last_turn.references.iter().enumerate() {
+                println!(
+                    "[{}] {} (score {:.2}, keywords: {})\n    From: {}\n    {}",
+                    i + 1,
+                    hit.entry.id.cyan(),
+                    hit.score,
+                    hit.entry.keywords.join(", "),
+                    hit.entry.origin(),
+                    hit.entry.content
+                );
+            }
+        }
+        Command::Review => match learning_manager.pending(memory.user_id()).await {
+            Ok(items) => {
+                if items.is_empty() {
+                    println!("Nothing is waiting for review.");
+                }
+                for item in &items {
+                    println!(
+                        "#{} seen {}x, from {} ({})\n    Topics: {}\n    {}",
+                        item.id.to_string().cyan(),
+                        item.corroborations,
+                        item.provenance.as_ref().map(|p| p.to_string()).unwrap_or_else(|| item.user_id.clone()),
+                        item.created_at,
+                        item.topics.join(", "),
+                        preview(&item.content, 100)
+                    );
+                }
+                println!(
+                    "{}",
+                    format!("Policy: {}. Use /review approve <id> or /review reject <id>.", learning_manager.policy()).dimmed()
+                );
+            }
+            Err(e) => error!("Failed to load the review queue: {}", e),
+        },
+        Command::Approve(id) => match learning_manager.approve(memory.user_id(), id).await {
+            Ok(true) => println!("Added #{} to the knowledge base.", id),
+            Ok(false) => println!("{}", format!("No pending item #{}", id).red()),
+            Err(e) => error!("Failed to approve #{}: {}", id, e),
+        },
+        Command::Reject(id) => match learning_manager.reject(memory.user_id(), id).await {
+            Ok(true) => println!("Rejected #{}.", id),
+            Ok(false) => println!("{}", format!("No pending item #{}", id).red()),
+            Err(e) => error!("Failed to reject #{}: {}", id, e),
+        },
+        Command::Up => give_feedback(memory.user_id(), learning_manager, last_turn, Feedback::Up).await,
+        Command::Down => give_feedback(memory.user_id(), learning_manager, last_turn, Feedback::Down).await,
+        Command::Correct(text) => {
+            give_feedback(memory.user_id(), learning_manager, last_turn, Feedback::Correction(text)).await
+        }
+        Command::Conflicts => match learning_manager.open_conflicts(memory.user_id()).await {
+            Ok(conflicts) => {
+                if conflicts.is_empty() {
+                    println!("No conflicts are waiting for you.");
+                }
+                for conflict in &conflicts {
+                    println!(
+                        "#{} {} ({})\n    old: {}\n    new: {}",
+                        conflict.id.to_string().cyan(),
+                        conflict.topic,
+                        conflict.reason,
+                        preview(&conflict.old_text, 100),
+                        preview(&conflict.new_text, 100)
+                    );
+                }
+                println!(
+                    "{}",
+                    format!("Policy: {}. Use /conflicts keep <id> old|new.", learning_manager.conflict_policy()).dimmed()
+                );
+            }
+            Err(e) => error!("Failed to load conflicts: {}", e),
+        },
+        Command::KeepConflict(id, keep_new) => match learning_manager.resolve_conflict(memory.user_id(), id, keep_new).await {
+            Ok(true) => println!("Kept the {} insight of #{}.", if keep_new { "new" } else { "old" }, id),
+            Ok(false) => println!("{}", format!("No open conflict #{}", id).red()),
+            Err(e) => error!("Failed to settle conflict #{}: {}", id, e),
+        },
+        Command::Profile => match learning_manager.profile(memory.user_id()).await {
+            Ok(profile) => {
+                println!("{}", profile);
+                println!("{}", "Use /profile set <field> <value> or /profile clear [field].".dimmed());
+            }
+            Err(e) => error!("Failed to load the profile: {}", e),
+        },
+        Command::ProfileSet(field, value) => {
+            edit_profile(memory.user_id(), learning_manager, move |profile| profile.set(&field, &value)).await
+        }
+        Command::ProfileClear(Some(field)) => {
+            edit_profile(memory.user_id(), learning_manager, move |profile| profile.clear(&field)).await
+        }
+        Command::ProfileClear(None) => {
+            edit_profile(memory.user_id(), learning_manager, |profile| {
+                *profile = UserProfile::default();
+                Ok(())
+            })
+            .await
+        }
+        Command::Graph(None) => match learning_manager.graph(memory.user_id()).await {
+            Ok(graph) => {
+                let entities: Vec<_> = graph.entities.iter().filter(|e| e.kind.is_some()).collect();
+                if entities.is_empty() {
+                    println!("No entities have been learned yet.");
+                }
+                for entity in entities {
+                    let (outgoing, incoming) = graph.relations_of(&entity.name);
+                    println!(
+                        "{} ({}) {} relations, last seen {}",
+                        entity.name.cyan(),
+                        entity.kind.as_deref().unwrap_or_default(),
+                        outgoing.len() + incoming.len(),
+                        entity.last_seen.format("%Y-%m-%d")
+                    );
+                }
+            }
+            Err(e) => error!("Failed to load the graph: {}", e),
+        },
+        Command::Graph(Some(name)) => match learning_manager.graph(memory.user_id()).await {
+            Ok(graph) => match graph.tree(&name) {
+                Some(tree) => println!("{}", tree),
+                None => println!("{}", format!("Nothing is known about {}", name).red()),
+            },
+            Err(e) => error!("Failed to load the graph: {}", e),
+        },
+        Command::GraphDot(path) => match learning_manager.graph(memory.user_id()).await {
+            Ok(graph) => match path {
+                Some(path) => match fs::write(&path, graph.to_dot()) {
+                    Ok(()) => println!("Wrote the graph to {} (render it with: dot -Tpng {} -o graph.png)", path, path),
+                    Err(e) => println!("{}", format!("Can't write {}: {}", path, e).red()),
+                },
+                None => print!("{}", graph.to_dot()),
+            },
+            Err(e) => error!("Failed to load the graph: {}", e),
+        },
+        Command::LearningStatus => println!("{}\n{}", "Learning queue:".blue(), learning_worker.status()),
+        Command::LearningReport => match learning_manager.report(memory.user_id(), &ReportOptions::default()).await {
+            Ok(report) => {
+                if let Err(e) = report.print(OutputFormat::Table, None) {
+                    error!("Failed to print the learning report: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to build the learning report: {}", e),
+        },
+        // Handled by the chat loop, which has the model
+        Command::Regenerate => {}
+        Command::Source(number) => match number.checked_sub(1).and_then(|i| last_turn.references.get(i)) {
+            Some(hit) => show_source(database, &hit.entry).await,
+            None => println!("{}", format!("No source [{}] for the last answer (see /sources)", number).red()),
+        },
+    }
+}
+
+async fn edit_profile(
+    user_id: &str,
+    learning_manager: &LearningManager,
+    edit: impl Fn(&mut UserProfile) -> Result<(), String> + Send + 'static,
+) {
+    // Whether the edit is valid doesn't depend on what the profile holds
+    if let Err(message) = edit(&mut UserProfile::default()) {
+        println!("{}", message.red());
+        return;
+    }
+    let edited = learning_manager.edit_profile(user_id, move |profile| {
+        let _ = edit(profile);
+    });
+    match edited.await {
+        Ok(profile) => println!("{}", profile),
+        Err(e) => error!("Failed to save the profile: {}", e),
+    }
+}
+
+async fn give_feedback(user_id: &str, learning_manager: &LearningManager, last_turn: &LastTurn, feedback: Feedback) {
+    let Some(id) = last_turn.conversation_id else {
+        println!("{}", "There is no saved answer to give feedback on yet.".red());
+        return;
+    };
+    let correction = matches!(feedback, Feedback::Correction(_));
+    match learning_manager.record_feedback(user_id, id, feedback).await {
+        Ok(None) => println!("You already rated that answer that way."),
+        Ok(Some(changed)) if correction => {
+            println!("Thanks, I'll remember that. {} items learned from that answer were adjusted.", changed)
+        }
+        Ok(Some(changed)) => println!("Thanks for the feedback. {} items learned from that answer were adjusted.", changed),
+        Err(e) => error!("Failed to record feedback: {}", e),
+    }
+}
+
+// Prints a cited entry and, where it can, the original it came from
+async fn show_source(database: &Database, entry: &KnowledgeEntry) {
+    println!("{} {}", entry.id.cyan(), entry.origin());
+    println!("{}", entry.content);
+
+    match &entry.provenance {
+        Some(Provenance::File { path, .. }) => {
+            // Ingested chunks know their exact bytes, as long as the file is unchanged
+            let Some(span) = &entry.document else { return };
+            match fs::read(path) {
+                Ok(bytes) if format!("{:x}", Sha256::digest(&bytes)) == span.hash => {
+                    let text = String::from_utf8_lossy(&bytes[span.start..span.end.min(bytes.len())]);
+                    println!("\n{} {}\n{}", "From".dimmed(), path.dimmed(), text);
+                }
+                Ok(_) => println!("\n{}", format!("{} has changed since it was ingested.", path).yellow()),
+                Err(e) => println!("\n{}", format!("Can't open {}: {}", path, e).yellow()),
+            }
+        }
+        Some(Provenance::Conversation { conversation_id: Some(id), .. })
+        | Some(Provenance::Model { conversation_id: Some(id), .. }) => match database.get_conversation(*id).await {
+            Ok(Some(conv)) => println!(
+                "\n{} #{} ({})\n    User: {}\n    Assistant: {}",
+                "From conversation".dimmed(),
+                conv.id,
+                conv.timestamp,
+                conv.user_input,
+                conv.ai_response
+            ),
+            Ok(None) => println!("\n{}", format!("Conversation #{} has been deleted.", id).yellow()),
+            Err(e) => error!("Failed to load conversation {}: {}", id, e),
+        },
+        _ => {}
+    }
+}
+
+// Switches memory to the personality's partition, reloading that
+// partition's most recent conversations the first time it is used
+async fn activate_character_memory(database: &Database, memory: &mut ScopedMemory, personality: &Personality) {
+    let user_id = memory.user_id().to_string();
+    for partition in memory.activate(&personality.to_string(), personality.memory_scope()) {
+        let conversations = match database.get_partition_conversations(user_id.clone(), partition.clone(), 20).await {
+            Ok(conversations) => conversations,
+            Err(e) => {
+                error!("Failed to restore conversation history: {}", e);
+                continue;
+            }
+        };
+        let Some(target) = memory.partition_mut(&partition) else { continue };
+
+        // Oldest first, so recency ordering is preserved
+        for record in conversations.into_iter().rev() {
+            let timestamp = chrono::NaiveDateTime::parse_from_str(&record.timestamp, "%Y-%m-%d %H:%M:%S")
+                .map(|t| t.and_utc())
+                .unwrap_or_else(|_| chrono::Utc::now());
+            target.restore_interaction(Some(record.id), &record.user_input, &record.ai_response, timestamp);
+        }
+    }
+}
+
+impl std::fmt::Display for Personality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Personality::HelpfulAssistant => write!(f, "Helpful Assistant"),
+            Personality::FriendlyChat => write!(f, "Friendly Chat"),
+            Personality::ExpertAdvisor => write!(f, "Expert Advisor"),
+            Personality::Custom(profile) => write!(f, "{}", profile.name),
         }
     }
 }
 
 fn load_personality_from_filename(filename: &str) -> Option<Personality> {
-    // Define the base directory for characters
-    let base_path = "/root/Rust-Ai-Agent---Deepseek/characters/";
-    
-    // Construct full path
-    let full_path = format!("{}{}", base_path, filename);
-    
     // Ensure the filename ends with .json
     if !filename.ends_with(".json") {
         error!("Character file must end with .json");
         return None;
     }
 
-    match fs::read_to_string(&full_path) {
-        Ok(json_str) => {
-            match PersonalityProfile::from_json(&json_str) {
-                Ok(profile) => Some(Personality::Custom(profile)),
-                Err(e) => {
-                    error!("Failed to parse character JSON: {}", e);
-                    None
-                }
-            }
-        }
+    let full_path = Path::new(CHARACTERS_DIR).join(filename);
+    match read_character_file(&full_path) {
+        Ok(profile) => Some(Personality::Custom(Box::new(profile))),
         Err(e) => {
-            error!("Failed to read character file '{}': {}", filename, e);
+            error!("{}", e);
             None
         }
     }
 }
+
+// Reads and checks a character file, describing what is wrong with it
+fn read_character_file(path: &Path) -> Result<PersonalityProfile, String> {
+    let json_str = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read character file '{}': {}", path.display(), e))?;
+    let profile = PersonalityProfile::from_json(&json_str)
+        .map_err(|e| format!("Failed to parse character file '{}': {}", path.display(), e))?;
+    profile
+        .validate()
+        .map_err(|e| format!("Invalid character file '{}': {}", path.display(), e))?;
+    Ok(profile)
+}
@@ -0,0 +1,388 @@
+// src/memory/bundle.rs
+// Portable memory bundles: a JSONL file whose first line is a manifest,
+// followed by one record per line for every memory layer. Like the other
+// JSON stores, a bundle is encrypted when a key is configured.
+use serde::{Serialize, Deserialize};
+use crate::crypto;
+use crate::database::{
+    remap_provenance, ConflictRow, ConversationRecord, Database, FeedbackRow, KnowledgeRecord, LearningRecords, PendingRow,
+    UsageRow,
+};
+use crate::knowledge_base::knowledge_base::{KnowledgeBaseHandler, KnowledgeEntry};
+use crate::learning::UserProfile;
+use crate::memory::LongTermMemory;
+
+pub const BUNDLE_FORMAT: &str = "rust-ai-agent-memory";
+// 2: conversation ids, and the profile, feedback, review queue, conflicts
+// and insight usage
+pub const BUNDLE_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub user_id: String,
+    pub conversations: usize,
+    pub long_term: usize,
+    pub knowledge: usize,
+    pub kb_entries: usize,
+    /// Profile, feedback, queued, conflict and usage records
+    #[serde(default)]
+    pub learning: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Manifest(Manifest),
+    Conversation {
+        /// Its id where it was exported, which other records refer to
+        #[serde(default)]
+        id: i64,
+        timestamp: String,
+        user_input: String,
+        ai_response: String,
+        personality: String,
+        #[serde(default)]
+        memory_partition: String,
+    },
+    LongTerm {
+        key: String,
+        value: String,
+    },
+    Knowledge {
+        key: String,
+        value: String,
+        timestamp: String,
+    },
+    KbEntry(KnowledgeEntry),
+    Profile(UserProfile),
+    Feedback(FeedbackRow),
+    Pending(PendingRow),
+    Conflict(ConflictRow),
+    Usage(UsageRow),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImportMode {
+    /// Add what is missing, keep local values on conflicts
+    Merge,
+    /// Replace the user's own memory and what was learned about them with
+    /// the bundle's content. The shared knowledge base is still only
+    /// merged into
+    Overwrite,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub conversations: usize,
+    pub long_term: usize,
+    pub knowledge: usize,
+    pub kb_entries: usize,
+    pub learning: usize,
+}
+
+impl std::fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} conversations, {} long-term entries, {} knowledge rows, {} knowledge base entries, {} learning records",
+            self.conversations, self.long_term, self.knowledge, self.kb_entries, self.learning
+        )
+    }
+}
+
+/// Writes every memory layer of the user to `path`.
+pub async fn export_bundle(
+    path: &str,
+    database: &Database,
+    long_term_memory: &LongTermMemory,
+    knowledge_base: &KnowledgeBaseHandler,
+) -> Result<Manifest, Box<dyn std::error::Error>> {
+    let user_id = long_term_memory.user_id().to_string();
+    let conversations = database.get_all_conversations(user_id.clone()).await?;
+    let knowledge = database.get_all_knowledge(user_id.clone()).await?;
+    let mut long_term: Vec<_> = long_term_memory.entries().iter().collect();
+    long_term.sort();
+    let kb_entries = knowledge_base.entries().await?;
+    let learning = database.export_learning(user_id.clone()).await?;
+
+    let manifest = Manifest {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        created_at: chrono::Utc::now(),
+        user_id,
+        conversations: conversations.len(),
+        long_term: long_term.len(),
+        knowledge: knowledge.len(),
+        kb_entries: kb_entries.len(),
+        learning: learning.count(),
+    };
+
+    let mut contents = String::new();
+    let mut write_record = |record: &Record| -> Result<(), Box<dyn std::error::Error>> {
+        contents.push_str(&serde_json::to_string(record)?);
+        contents.push('\n');
+        Ok(())
+    };
+
+    write_record(&Record::Manifest(manifest.clone()))?;
+    for record in conversations {
+        write_record(&Record::Conversation {
+            id: record.id,
+            timestamp: record.timestamp,
+            user_input: record.user_input,
+            ai_response: record.ai_response,
+            personality: record.personality,
+            memory_partition: record.memory_partition,
+        })?;
+    }
+    for (key, value) in long_term {
+        write_record(&Record::LongTerm { key: key.clone(), value: value.clone() })?;
+    }
+    for record in knowledge {
+        write_record(&Record::Knowledge {
+            key: record.key,
+            value: record.value,
+            timestamp: record.timestamp,
+        })?;
+    }
+    for entry in kb_entries {
+        write_record(&Record::KbEntry(entry))?;
+    }
+    if let Some(profile) = learning.profile {
+        write_record(&Record::Profile(profile))?;
+    }
+    for row in learning.feedback {
+        write_record(&Record::Feedback(row))?;
+    }
+    for row in learning.pending {
+        write_record(&Record::Pending(row))?;
+    }
+    for row in learning.conflicts {
+        write_record(&Record::Conflict(row))?;
+    }
+    for row in learning.usage {
+        write_record(&Record::Usage(row))?;
+    }
+    crypto::write_store(path, &contents)?;
+
+    Ok(manifest)
+}
+
+/// Loads a bundle written by `export_bundle` into the user's memory.
+/// The bundle's own user id is ignored, so memory can move between users.
+/// Knowledge base entries are shared by every user, so they are added
+/// when their content is new and never replaced, even when overwriting.
+pub async fn import_bundle(
+    path: &str,
+    mode: ImportMode,
+    database: &Database,
+    long_term_memory: &mut LongTermMemory,
+    knowledge_base: &KnowledgeBaseHandler,
+) -> Result<ImportReport, Box<dyn std::error::Error>> {
+    let contents = crypto::read_store(path)?;
+    let mut lines = contents.lines();
+
+    // Validate the manifest before touching anything
+    let first = lines.next().ok_or("Bundle is empty")?;
+    let manifest = match serde_json::from_str::<Record>(first)? {
+        Record::Manifest(manifest) => manifest,
+        _ => return Err("Bundle does not start with a manifest".into()),
+    };
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(format!("Not a memory bundle: format '{}'", manifest.format).into());
+    }
+    if manifest.version > BUNDLE_VERSION {
+        return Err(format!(
+            "Bundle version {} is newer than supported version {}",
+            manifest.version, BUNDLE_VERSION
+        ).into());
+    }
+
+    let mut conversations = Vec::new();
+    let mut long_term = Vec::new();
+    let mut knowledge = Vec::new();
+    let mut kb_entries = Vec::new();
+    let mut learning = LearningRecords::default();
+    for (number, line) in lines.enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(line)
+            .map_err(|e| format!("Line {}: {}", number + 2, e))?;
+        match record {
+            Record::Manifest(_) => return Err(format!("Line {}: unexpected manifest", number + 2).into()),
+            Record::Conversation { id, timestamp, user_input, ai_response, personality, memory_partition } => {
+                conversations.push(ConversationRecord {
+                    id,
+                    timestamp,
+                    user_input,
+                    ai_response,
+                    personality,
+                    memory_partition,
+                });
+            }
+            Record::LongTerm { key, value } => long_term.push((key, value)),
+            Record::Knowledge { key, value, timestamp } => {
+                knowledge.push(KnowledgeRecord { key, value, timestamp });
+            }
+            Record::KbEntry(entry) => kb_entries.push(entry),
+            Record::Profile(profile) => learning.profile = Some(profile),
+            Record::Feedback(row) => learning.feedback.push(row),
+            Record::Pending(row) => learning.pending.push(row),
+            Record::Conflict(row) => learning.conflicts.push(row),
+            Record::Usage(row) => learning.usage.push(row),
+        }
+    }
+
+    let user_id = long_term_memory.user_id().to_string();
+    let overwrite = mode == ImportMode::Overwrite;
+    let mut report = ImportReport::default();
+
+    if overwrite {
+        database.clear_user(user_id.clone()).await?;
+        long_term_memory.clear();
+    }
+
+    let (added, mut ids) = database.import_conversations(user_id.clone(), conversations).await?;
+    report.conversations = added;
+    // Bundles before version 2 don't carry conversation ids
+    ids.remove(&0);
+
+    // Insights point at the conversation they were learned in
+    for record in &mut knowledge {
+        if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&record.value) {
+            if let Some(provenance) = value.get_mut("provenance").filter(|p| p.is_object()) {
+                remap_provenance(provenance, &ids);
+                record.value = value.to_string();
+            }
+        }
+    }
+    report.knowledge = database.import_knowledge(user_id.clone(), knowledge, overwrite).await?;
+    report.learning = database.import_learning(user_id, learning, ids, overwrite).await?;
+
+    for (key, value) in long_term {
+        if overwrite || long_term_memory.entries().get(&key).is_none() {
+            long_term_memory.store(key, value);
+            report.long_term += 1;
+        }
+    }
+
+    report.kb_entries = knowledge_base.merge_entries(kb_entries).await?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{ConflictRecord, LearnedTurn, QueuedContent};
+    use crate::knowledge_base::knowledge_base::Provenance;
+
+    async fn conversation(db: &Database, user_id: &str, input: &str) -> i64 {
+        db.save_conversation(user_id.to_string(), input.to_string(), "answer".to_string(), "default".to_string(), "default".to_string())
+            .await
+            .unwrap()
+    }
+
+    // Everything alice's memory can hold, learned in one conversation
+    async fn alice() -> (Database, i64) {
+        let db = Database::new(":memory:").await.unwrap();
+        conversation(&db, "alice", "first").await;
+        let id = conversation(&db, "alice", "Deploys are on Tuesday").await;
+        let insight = serde_json::json!({
+            "topic": "deploys",
+            "context": "Deploys are on Tuesday",
+            "confidence": 0.7,
+            "source": "user_input",
+            "timestamp": "2026-01-01T00:00:00Z",
+            "provenance": { "kind": "conversation", "conversation_id": id, "user_id": "alice" },
+        });
+        db.save_knowledge("alice".to_string(), "insight:deploys:1:0".to_string(), insight.to_string()).await.unwrap();
+        db.update_profile("alice".to_string(), |profile| profile.set("name", "Alice").is_ok()).await.unwrap();
+        db.feedback_apply(id, "alice".to_string(), "up", None, |_| 0.0).await.unwrap();
+        let turn = LearnedTurn {
+            user_id: "alice".to_string(),
+            conversation_id: Some(id),
+            insights: Vec::new(),
+            profile: UserProfile::default(),
+            queue_owner: Some("alice".to_string()),
+            stated: None,
+            queue: Some(QueuedContent {
+                topics: vec!["deploys".to_string()],
+                content: "Deploys happen on Tuesday.".to_string(),
+                vector: Vec::new(),
+                provenance: Provenance::Model { conversation_id: Some(id), user_id: "alice".to_string(), model: "test".to_string() },
+            }),
+        };
+        db.save_learned(vec![turn], "test".to_string(), |_, _| None).await.unwrap();
+        db.conflict_add(ConflictRecord {
+            id: 0,
+            user_id: "alice".to_string(),
+            owner: "alice".to_string(),
+            topic: "deploys".to_string(),
+            old_key: "insight:deploys:0:0".to_string(),
+            old_text: "Deploys are on Thursday".to_string(),
+            new_key: "insight:deploys:1:0".to_string(),
+            new_text: "Deploys are on Tuesday".to_string(),
+            reason: "different values".to_string(),
+            status: "kept_new".to_string(),
+        })
+        .await
+        .unwrap();
+        db.record_insight_use(vec![("alice".to_string(), "Deploys are on Tuesday".to_string())]).await.unwrap();
+        (db, id)
+    }
+
+    #[tokio::test]
+    async fn overwrite_restores_everything_exported() {
+        let (source, _) = alice().await;
+        let path = std::env::temp_dir().join(format!("bundle-test-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let manifest = export_bundle(path, &source, &LongTermMemory::new("alice"), &KnowledgeBaseHandler::with_database(source.clone()))
+            .await
+            .unwrap();
+        assert_eq!(manifest.learning, 5);
+
+        // Bob has memory of his own that the bundle replaces
+        let target = Database::new(":memory:").await.unwrap();
+        for input in ["a", "b", "c"] {
+            conversation(&target, "bob", input).await;
+        }
+        let stale = conversation(&target, "bob", "stale").await;
+        target.feedback_apply(stale, "bob".to_string(), "down", None, |_| 0.0).await.unwrap();
+        target.record_insight_use(vec![("bob".to_string(), "stale".to_string())]).await.unwrap();
+        target.update_profile("bob".to_string(), |profile| profile.set("language", "French").is_ok()).await.unwrap();
+
+        let mut memory = LongTermMemory::new("bob");
+        let knowledge_base = KnowledgeBaseHandler::with_database(target.clone());
+        let report = import_bundle(path, ImportMode::Overwrite, &target, &mut memory, &knowledge_base).await.unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(report.conversations, 2);
+        assert_eq!(report.learning, 5);
+
+        let conversations = target.get_all_conversations("bob".to_string()).await.unwrap();
+        let id = conversations.iter().find(|c| c.user_input == "Deploys are on Tuesday").unwrap().id;
+        let exported = source.export_learning("alice".to_string()).await.unwrap();
+        let imported = target.export_learning("bob".to_string()).await.unwrap();
+
+        assert_eq!(imported.profile, exported.profile);
+        assert_eq!(imported.profile.unwrap().language, None);
+        assert_eq!(imported.feedback.len(), 1);
+        assert_eq!((imported.feedback[0].conversation_id, imported.feedback[0].kind.as_str()), (id, "up"));
+        assert_eq!(imported.pending.len(), 1);
+        assert_eq!(imported.pending[0].content, exported.pending[0].content);
+        assert_eq!(imported.pending[0].sources.len(), 1);
+        assert_eq!(imported.pending[0].sources[0].conversation_id, id);
+        assert_eq!(imported.pending[0].provenance.as_ref().unwrap()["conversation_id"], id);
+        assert_eq!(imported.conflicts, exported.conflicts);
+        assert_eq!(imported.usage, exported.usage);
+
+        let knowledge = target.get_all_knowledge("bob".to_string()).await.unwrap();
+        assert_eq!(knowledge.len(), 1);
+        let value: serde_json::Value = serde_json::from_str(&knowledge[0].value).unwrap();
+        assert_eq!(value["provenance"]["conversation_id"], id);
+    }
+}
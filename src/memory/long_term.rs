@@ -1,43 +1,89 @@
 // src/memory/long_term.rs
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
-use std::fs;
-use chrono;
+use std::path::Path;
+use crate::crypto;
+
+// On-disk layout of the memory file: one section per user. Knowledge
+// shared between users lives in the database, not here. Unknown sections
+// (like the old "global" one) are refused rather than silently dropped.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct MemoryFile {
+    #[serde(default)]
+    users: HashMap<String, HashMap<String, String>>,
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct LongTermMemory {
+    user_id: String,
     data: HashMap<String, String>, // Key-value pairs for persistent data
 }
 
 impl LongTermMemory {
-    pub fn new() -> Self {
+    pub fn new(user_id: &str) -> Self {
         Self {
+            user_id: user_id.to_string(),
             data: HashMap::new(),
         }
     }
 
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
     pub fn store(&mut self, key: String, value: String) {
         self.data.insert(key, value);
     }
 
-    pub fn add_memory(&mut self, user_input: &str, ai_response: &str) {
-        let key = format!("memory_{}", chrono::Utc::now().timestamp());
-        let value = format!("User: {}\nAssistant: {}", user_input, ai_response);
-        self.store(key, value);
+    /// This user's own entries.
+    pub fn entries(&self) -> &HashMap<String, String> {
+        &self.data
     }
 
-    pub fn retrieve(&self, key: &str) -> Option<&String> {
-        self.data.get(key)
+    pub fn clear(&mut self) {
+        self.data.clear();
     }
 
+    /// Writes this user's section back, leaving other users untouched.
     pub fn save_to_file(&self, path: &str) -> std::io::Result<()> {
-        let serialized = serde_json::to_string(&self.data)?;
-        fs::write(path, serialized)
+        let mut file = Self::read_file(path)?;
+        file.users.insert(self.user_id.clone(), self.data.clone());
+        let serialized = serde_json::to_string(&file)?;
+        Ok(crypto::write_store(path, &serialized)?)
+    }
+
+    pub fn load_from_file(path: &str, user_id: &str) -> std::io::Result<Self> {
+        let mut file = Self::read_file(path)?;
+        Ok(Self {
+            user_id: user_id.to_string(),
+            data: file.users.remove(user_id).unwrap_or_default(),
+        })
     }
 
-    pub fn load_from_file(path: &str) -> std::io::Result<Self> {
-        let data = fs::read_to_string(path)?;
-        let data: HashMap<String, String> = serde_json::from_str(&data)?;
-        Ok(Self { data })
+    fn read_file(path: &str) -> std::io::Result<MemoryFile> {
+        if !Path::new(path).exists() {
+            return Ok(MemoryFile::default());
+        }
+
+        let data = crypto::read_store(path)?;
+        let value: serde_json::Value = serde_json::from_str(&data)?;
+
+        // Files written before per-user memory are a flat key-value map
+        // belonging to the default user.
+        let is_sectioned = value
+            .as_object()
+            .map(|map| map.contains_key("users"))
+            .unwrap_or(false);
+        if is_sectioned {
+            Ok(serde_json::from_value(value)?)
+        } else {
+            let legacy: HashMap<String, String> = serde_json::from_value(value)?;
+            let mut file = MemoryFile::default();
+            if !legacy.is_empty() {
+                file.users.insert(crate::database::DEFAULT_USER.to_string(), legacy);
+            }
+            Ok(file)
+        }
     }
 }
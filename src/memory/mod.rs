@@ -1,6 +1,9 @@
 // src/memory/mod.rs
 pub mod short_term;
 pub mod long_term;
+pub mod scoped;
+pub mod bundle;
 
 pub use short_term::ShortTermMemory;
 pub use long_term::LongTermMemory;
+pub use scoped::{MemoryScope, ScopedMemory};
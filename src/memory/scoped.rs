@@ -0,0 +1,132 @@
+// src/memory/scoped.rs
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::memory::ShortTermMemory;
+
+/// Partition holding conversations visible to every shared character.
+pub const SHARED_PARTITION: &str = "";
+
+/// How a character's short-term memory relates to the other characters'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryScope {
+    /// Reads and writes the common pool
+    Shared,
+    /// Reads and writes only its own partition
+    #[default]
+    Isolated,
+    /// Writes its own partition, but also reads the common pool
+    ReadShared,
+}
+
+impl MemoryScope {
+    /// Partition a character with this scope writes to.
+    pub fn partition_for(&self, character: &str) -> String {
+        match self {
+            MemoryScope::Shared => SHARED_PARTITION.to_string(),
+            MemoryScope::Isolated | MemoryScope::ReadShared => character.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for MemoryScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryScope::Shared => write!(f, "shared"),
+            MemoryScope::Isolated => write!(f, "isolated"),
+            MemoryScope::ReadShared => write!(f, "read-shared"),
+        }
+    }
+}
+
+/// Short-term memory of one user, partitioned by character.
+pub struct ScopedMemory {
+    user_id: String,
+    partitions: HashMap<String, ShortTermMemory>,
+    active: String,
+    scope: MemoryScope,
+}
+
+impl ScopedMemory {
+    /// Creates an empty memory; call `activate` before using it.
+    pub fn new(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            partitions: HashMap::new(),
+            active: SHARED_PARTITION.to_string(),
+            scope: MemoryScope::Shared,
+        }
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// Switches to the memory of `character`. Returns the partitions that
+    /// were not loaded yet so the caller can restore their history.
+    pub fn activate(&mut self, character: &str, scope: MemoryScope) -> Vec<String> {
+        self.scope = scope;
+        self.active = scope.partition_for(character);
+
+        let mut needed = vec![self.active.clone()];
+        if scope == MemoryScope::ReadShared {
+            needed.push(SHARED_PARTITION.to_string());
+        }
+
+        let mut created = Vec::new();
+        for partition in needed {
+            if !self.partitions.contains_key(&partition) {
+                self.partitions.insert(partition.clone(), ShortTermMemory::new());
+                created.push(partition);
+            }
+        }
+        created
+    }
+
+    pub fn active_partition(&self) -> &str {
+        &self.active
+    }
+
+    pub fn active(&self) -> &ShortTermMemory {
+        &self.partitions[&self.active]
+    }
+
+    pub fn partition_mut(&mut self, partition: &str) -> Option<&mut ShortTermMemory> {
+        self.partitions.get_mut(partition)
+    }
+
+    pub fn active_mut(&mut self) -> &mut ShortTermMemory {
+        self.partitions.get_mut(&self.active).expect("active partition is created by activate")
+    }
+
+    pub fn add_interaction(&mut self, id: Option<i64>, user_input: &str, ai_response: &str) {
+        self.active_mut().add_interaction(id, user_input, ai_response);
+    }
+
+    pub fn get_context(&self, current_input: &str) -> String {
+        let own = self.active().get_context(current_input);
+        if self.scope != MemoryScope::ReadShared {
+            return own;
+        }
+
+        let shared = self.partitions
+            .get(SHARED_PARTITION)
+            .map(|memory| memory.get_context(current_input))
+            .unwrap_or_default();
+        [own, shared]
+            .into_iter()
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    pub fn get_memory_stats(&self) -> String {
+        let partition = if self.active == SHARED_PARTITION { "shared pool" } else { &self.active };
+        format!(
+            "Memory Scope: {} ({})\n{}",
+            self.scope,
+            partition,
+            self.active().get_memory_stats()
+        )
+    }
+}
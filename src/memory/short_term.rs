@@ -2,9 +2,15 @@
 use std::collections::{VecDeque, HashMap};
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
+use crate::keyphrase;
+
+// Topics kept per conversation
+const MAX_TOPICS: usize = 8;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Conversation {
+    #[serde(default)]
+    pub id: Option<i64>, // Row id in the conversations table, if saved
     pub timestamp: DateTime<Utc>,
     pub user_input: String,
     pub ai_response: String,
@@ -27,7 +33,16 @@ impl ShortTermMemory {
         }
     }
 
-    pub fn add_interaction(&mut self, user_input: &str, ai_response: &str) {
+    pub fn add_interaction(&mut self, id: Option<i64>, user_input: &str, ai_response: &str) {
+        self.record_interaction(id, user_input, ai_response, Utc::now());
+    }
+
+    /// Re-adds a past interaction, e.g. one loaded from the database.
+    pub fn restore_interaction(&mut self, id: Option<i64>, user_input: &str, ai_response: &str, timestamp: DateTime<Utc>) {
+        self.record_interaction(id, user_input, ai_response, timestamp);
+    }
+
+    fn record_interaction(&mut self, id: Option<i64>, user_input: &str, ai_response: &str, timestamp: DateTime<Utc>) {
         // Extract topics from the conversation
         let topics = self.extract_topics(user_input, ai_response);
         
@@ -35,7 +50,8 @@ impl ShortTermMemory {
         let relevance_score = self.calculate_relevance(&topics);
 
         let conversation = Conversation {
-            timestamp: Utc::now(),
+            id,
+            timestamp,
             user_input: user_input.to_string(),
             ai_response: ai_response.to_string(),
             topics,
@@ -47,7 +63,7 @@ impl ShortTermMemory {
         for topic in &conversation.topics {
             self.topic_index
                 .entry(topic.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(conv_index);
         }
 
@@ -58,19 +74,8 @@ impl ShortTermMemory {
     }
 
     fn extract_topics(&self, user_input: &str, ai_response: &str) -> Vec<String> {
-        let mut topics = Vec::new();
-        let combined_text = format!("{} {}", user_input, ai_response);
-        
-        // Simple topic extraction (can be enhanced with NLP)
-        let words: Vec<&str> = combined_text.split_whitespace().collect();
-        for window in words.windows(2) {
-            if window[0].len() > 3 && window[1].len() > 3 {
-                topics.push(format!("{} {}", window[0], window[1]));
-            }
-        }
-
+        let mut topics = keyphrase::extract(&format!("{}\n{}", user_input, ai_response), MAX_TOPICS);
         topics.sort();
-        topics.dedup();
         topics
     }
 
@@ -130,7 +135,7 @@ impl ShortTermMemory {
             for topic in &conv.topics {
                 self.topic_index
                     .entry(topic.clone())
-                    .or_insert_with(Vec::new)
+                    .or_default()
                     .push(i);
             }
         }
@@ -167,17 +172,57 @@ impl ShortTermMemory {
         context.join("\n\n")
     }
 
-    pub fn conversation_count(&self) -> usize {
-        self.conversations.len()
+    /// Conversations with their index, most recent first.
+    pub fn recent(&self, limit: usize) -> Vec<(usize, &Conversation)> {
+        let mut entries: Vec<_> = self.conversations.iter().enumerate().collect();
+        entries.sort_by_key(|(_, conv)| std::cmp::Reverse(conv.timestamp));
+        entries.truncate(limit);
+        entries
+    }
+
+    /// Topics with the number of conversations mentioning them, most used first.
+    pub fn topic_counts(&self) -> Vec<(&str, usize)> {
+        let mut counts: Vec<_> = self.topic_index
+            .iter()
+            .map(|(topic, indices)| (topic.as_str(), indices.len()))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts
+    }
+
+    /// Conversations whose text or topics contain `query`, ignoring case.
+    pub fn search(&self, query: &str) -> Vec<(usize, &Conversation)> {
+        let query = query.to_lowercase();
+        self.conversations
+            .iter()
+            .enumerate()
+            .filter(|(_, conv)| {
+                conv.user_input.to_lowercase().contains(&query)
+                    || conv.ai_response.to_lowercase().contains(&query)
+                    || conv.topics.iter().any(|t| t.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Conversation> {
+        let removed = self.conversations.remove(index);
+        if removed.is_some() {
+            self.rebuild_topic_index();
+        }
+        removed
     }
 
     pub fn get_memory_stats(&self) -> String {
         let total_conversations = self.conversations.len();
         let total_topics: usize = self.topic_index.len();
-        let avg_relevance: f32 = self.conversations
-            .iter()
-            .map(|c| c.relevance_score)
-            .sum::<f32>() / total_conversations as f32;
+        let avg_relevance: f32 = if total_conversations == 0 {
+            0.0
+        } else {
+            self.conversations
+                .iter()
+                .map(|c| c.relevance_score)
+                .sum::<f32>() / total_conversations as f32
+        };
 
         format!(
             "Memory Stats:\n\
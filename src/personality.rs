@@ -1,5 +1,6 @@
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
+use crate::memory::MemoryScope;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PersonalityTraits {
@@ -23,18 +24,6 @@ pub struct EmotionalState {
     pub expressions: HashMap<String, EmotionalExpression>,
 }
 
-impl EmotionalState {
-    pub fn get_emoji_for_emotion(&self, emotion: &str) -> Option<String> {
-        self.expressions.get(emotion)
-            .and_then(|expr| expr.emojis.first().cloned())
-    }
-
-    pub fn get_emote_for_emotion(&self, emotion: &str) -> Option<String> {
-        self.expressions.get(emotion)
-            .and_then(|expr| expr.emotes.first().cloned())
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommunicationStyle {
     pub primary_style: String,
@@ -75,6 +64,8 @@ pub struct PersonalityProfile {
     pub emotions: EmotionalState,
     pub communication: CommunicationStyle,
     pub context_rules: ContextRules,
+    #[serde(default)]
+    pub memory_scope: MemoryScope,
 }
 
 impl PersonalityProfile {
@@ -82,6 +73,26 @@ impl PersonalityProfile {
         serde_json::from_str(json_str)
     }
 
+    /// Checks what the JSON types alone can't: a name, and traits in 0..=1.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is empty".to_string());
+        }
+        let traits = [
+            ("openness", self.traits.openness),
+            ("conscientiousness", self.traits.conscientiousness),
+            ("extraversion", self.traits.extraversion),
+            ("agreeableness", self.traits.agreeableness),
+            ("neuroticism", self.traits.neuroticism),
+        ];
+        for (name, value) in traits {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("trait {} is {}, expected 0 to 1", name, value));
+            }
+        }
+        Ok(())
+    }
+
     pub fn generate_system_prompt(&self) -> String {
         format!(
             "You are {}, an AI persona with a unique background. {}. \
@@ -98,26 +109,11 @@ impl PersonalityProfile {
             self.bio.interests.join(", ")
         )
     }
+}
 
-    pub fn get_emotion_for_context(&self, context: &str) -> Option<String> {
-        self.emotions.context_emotions
-            .iter()
-            .find(|(ctx, _)| ctx == context)
-            .map(|(_, emotions)| emotions.first().cloned())
-            .flatten()
-    }
-
-    pub fn to_string(&self) -> String {
-        self.name.clone()
-    }
-
-    pub fn get_expressive_response(&self, emotion: &str, base_response: &str) -> String {
-        let emoji = self.emotions.get_emoji_for_emotion(emotion)
-            .unwrap_or_default();
-        let emote = self.emotions.get_emote_for_emotion(emotion)
-            .unwrap_or_default();
-        
-        format!("{} {} {}", emote, base_response, emoji)
+impl std::fmt::Display for PersonalityProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
     }
 }
 
@@ -126,7 +122,7 @@ pub enum Personality {
     HelpfulAssistant,
     FriendlyChat,
     ExpertAdvisor,
-    Custom(PersonalityProfile),
+    Custom(Box<PersonalityProfile>),
 }
 
 impl Personality {
@@ -147,4 +143,12 @@ impl Personality {
             Self::Custom(profile) => profile.generate_system_prompt(),
         }
     }
+
+    /// Built-in personalities share one memory; characters choose their own scope.
+    pub fn memory_scope(&self) -> MemoryScope {
+        match self {
+            Self::Custom(profile) => profile.memory_scope,
+            _ => MemoryScope::Shared,
+        }
+    }
 }
@@ -0,0 +1,67 @@
+// src/prompt.rs
+// Assembles the user prompt sent to the model from the conversation context
+// and the material retrieved for the current input.
+use crate::knowledge_base::knowledge_base::RetrievedEntry;
+use crate::learning::LearnedContext;
+
+pub struct Prompt<'a> {
+    pub system_message: String,
+    pub input: &'a str,
+    pub context: String,
+    pub references: &'a [RetrievedEntry],
+    pub learned: &'a [LearnedContext],
+}
+
+impl Prompt<'_> {
+    pub fn render(&self) -> String {
+        let references = format!("{}{}", self.render_learned(), self.render_references());
+
+        if !self.context.is_empty() {
+            format!(
+                "Previous relevant context:\n{}\n\n{}Current conversation:\nUser: {}\n\nRemember that you are acting as: {}",
+                self.context,
+                references,
+                self.input,
+                self.system_message
+            )
+        } else {
+            format!(
+                "You are acting as: {}\n\n{}User: {}",
+                self.system_message,
+                references,
+                self.input
+            )
+        }
+    }
+
+    fn render_learned(&self) -> String {
+        if self.learned.is_empty() {
+            return String::new();
+        }
+
+        let mut block = String::from(
+            "What you have learned earlier (use it when relevant; corrections override older information):\n",
+        );
+        for item in self.learned {
+            block.push_str(&format!("- ({}) {}\n", item.kind, item.text));
+        }
+        block.push('\n');
+        block
+    }
+
+    // Numbered so the model can cite them as [n]
+    fn render_references(&self) -> String {
+        if self.references.is_empty() {
+            return String::new();
+        }
+
+        let mut block = String::from(
+            "Reference material (cite an item as [n] when you use it, and ignore it if it is not relevant):\n",
+        );
+        for (i, hit) in self.references.iter().enumerate() {
+            block.push_str(&format!("[{}] {}\n", i + 1, hit.entry.content));
+        }
+        block.push('\n');
+        block
+    }
+}
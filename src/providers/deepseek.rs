@@ -3,10 +3,15 @@ use serde_json::json;
 use crate::completion::{CompletionProvider, CompletionError};
 use crate::personality::Personality;
 
+const MODEL: &str = "deepseek-chat";
+
+#[derive(Clone)]
 pub struct DeepSeekProvider {
     api_key: String,
     client: Client,
     personality: Personality,
+    // Summary of what is known about the user, added to the system message
+    user_profile: String,
 }
 
 impl DeepSeekProvider {
@@ -15,24 +20,37 @@ impl DeepSeekProvider {
             api_key,
             client: Client::new(),
             personality, // Initialize with provided personality
+            user_profile: String::new(),
         }
     }
+
+    /// Sets what the model is told about the user; empty for nothing.
+    pub fn set_user_profile(&mut self, summary: String) {
+        self.user_profile = summary;
+    }
 }
 
 #[async_trait::async_trait]
 impl CompletionProvider for DeepSeekProvider {
     type Error = CompletionError;
 
+    fn model(&self) -> &str {
+        MODEL
+    }
+
     async fn complete(&self, prompt: &str) -> Result<String, Self::Error> {
         // Use the personality's system message
-        let system_message = self.personality.system_message();
+        let mut system_message = self.personality.system_message();
+        if !self.user_profile.is_empty() {
+            system_message = format!("{}\n\nAbout the user: {}", system_message, self.user_profile);
+        }
 
         let response = self.client
             .post("https://api.deepseek.com/v1/chat/completions")
             .header("Authorization", format!("Bearer {}", self.api_key))
             .header("Content-Type", "application/json")
             .json(&json!({
-                "model": "deepseek-chat",
+                "model": MODEL,
                 "messages": [
                     {"role": "system", "content": system_message}, // Use personality's system message
                     {"role": "user", "content": prompt}
@@ -0,0 +1,121 @@
+// src/text.rs
+// Text normalization shared by search and topic extraction.
+use std::sync::OnceLock;
+use rust_stemmers::{Algorithm, Stemmer};
+
+// Common English words that carry no meaning on their own
+const STOPWORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "am", "an", "and", "any", "are",
+    "as", "at", "be", "because", "been", "before", "being", "below", "between", "both", "but",
+    "by", "can", "could", "did", "do", "does", "doing", "down", "during", "each", "few", "for",
+    "from", "further", "had", "has", "have", "having", "he", "her", "here", "hers", "herself",
+    "him", "himself", "his", "how", "i", "if", "in", "into", "is", "it", "its", "itself", "just",
+    "me", "more", "most", "my", "myself", "no", "nor", "not", "now", "of", "off", "on", "once",
+    "only", "or", "other", "our", "ours", "ourselves", "out", "over", "own", "same", "she",
+    "should", "so", "some", "such", "than", "that", "the", "their", "theirs", "them",
+    "themselves", "then", "there", "these", "they", "this", "those", "through", "to", "too",
+    "under", "until", "up", "very", "was", "we", "were", "what", "when", "where", "which",
+    "while", "who", "whom", "why", "will", "with", "would", "you", "your", "yours", "yourself",
+    "yourselves",
+];
+
+pub fn is_stopword(word: &str) -> bool {
+    STOPWORDS.binary_search(&word).is_ok()
+}
+
+/// Lowercased words, split on anything that is not a letter or digit.
+pub fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+pub fn stem(word: &str) -> String {
+    static STEMMER: OnceLock<Stemmer> = OnceLock::new();
+    STEMMER
+        .get_or_init(|| Stemmer::create(Algorithm::English))
+        .stem(word)
+        .into_owned()
+}
+
+// Plurals the suffix rules get wrong
+const IRREGULAR_PLURALS: &[(&str, &str)] = &[
+    ("caches", "cache"), ("calories", "calorie"), ("children", "child"), ("cookies", "cookie"),
+    ("criteria", "criterion"), ("feet", "foot"), ("headaches", "headache"), ("indices", "index"),
+    ("kubernetes", "kubernetes"), ("men", "man"), ("mice", "mouse"), ("movies", "movie"),
+    ("news", "news"), ("niches", "niche"), ("people", "person"), ("postgres", "postgres"),
+    ("series", "series"), ("species", "species"), ("teeth", "tooth"), ("windows", "windows"),
+    ("women", "woman"),
+];
+
+/// Dictionary form of a lowercase word, mostly plural to singular. Unlike
+/// `stem` the result is still a readable word, so it suits topics.
+pub fn lemma(word: &str) -> String {
+    if let Ok(i) = IRREGULAR_PLURALS.binary_search_by(|(plural, _)| plural.cmp(&word)) {
+        return IRREGULAR_PLURALS[i].1.to_string();
+    }
+    if word.len() <= 3 || !word.chars().all(|c| c.is_alphabetic()) {
+        return word.to_string();
+    }
+
+    if let Some(base) = word.strip_suffix("ies").filter(|_| word.len() > 4) {
+        format!("{}y", base)
+    } else if word.ends_with("sses") || word.ends_with("xes") || word.ends_with("ches") || word.ends_with("shes") {
+        word[..word.len() - 2].to_string()
+    } else if word.ends_with('s') && !["ss", "us", "is", "ics"].iter().any(|end| word.ends_with(end)) {
+        word[..word.len() - 1].to_string()
+    } else {
+        word.to_string()
+    }
+}
+
+/// Search terms: lowercased, stemmed words without stopwords.
+pub fn tokenize(text: &str) -> Vec<String> {
+    words(text)
+        .into_iter()
+        .filter(|w| !is_stopword(w))
+        .map(|w| stem(&w))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lemma_makes_plurals_singular() {
+        for (plural, singular) in [
+            ("tasks", "task"),
+            ("libraries", "library"),
+            ("classes", "class"),
+            ("boxes", "box"),
+            ("matches", "match"),
+            ("dishes", "dish"),
+            ("ties", "tie"),
+        ] {
+            assert_eq!(lemma(plural), singular, "{}", plural);
+        }
+    }
+
+    #[test]
+    fn lemma_uses_the_irregular_list() {
+        assert_eq!(lemma("people"), "person");
+        assert_eq!(lemma("caches"), "cache");
+        assert_eq!(lemma("indices"), "index");
+        assert_eq!(lemma("kubernetes"), "kubernetes");
+    }
+
+    #[test]
+    fn lemma_leaves_other_words_alone() {
+        for word in ["class", "status", "analysis", "physics", "gas", "rust", "c++", "k8s"] {
+            assert_eq!(lemma(word), word);
+        }
+    }
+
+    #[test]
+    fn irregular_plurals_are_sorted() {
+        // lemma looks them up with a binary search
+        assert!(IRREGULAR_PLURALS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+}
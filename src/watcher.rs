@@ -0,0 +1,62 @@
+// src/watcher.rs
+// Watches directories for edited files so the REPL can pick up changes
+// to the knowledge base and characters without a restart, even while it
+// waits for input.
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use log::{error, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+// How long a burst of writes to a file may take
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+pub struct FileWatcher {
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: UnboundedReceiver<PathBuf>,
+}
+
+impl FileWatcher {
+    /// Starts watching each existing directory in `dirs` (not recursively).
+    pub fn start(dirs: &[&str]) -> notify::Result<Self> {
+        let (sender, events) = unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+            Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                for path in event.paths {
+                    // The receiver is gone once the REPL exits
+                    let _ = sender.send(path);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => error!("File watcher error: {}", e),
+        })?;
+
+        for dir in dirs {
+            if Path::new(dir).is_dir() {
+                watcher.watch(Path::new(dir), RecursiveMode::NonRecursive)?;
+            } else {
+                warn!("Not watching {}: no such directory", dir);
+            }
+        }
+
+        Ok(Self { _watcher: watcher, events })
+    }
+
+    /// Waits for a file to change, then returns every file changed since
+    /// the last call, each listed once.
+    pub async fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = BTreeSet::new();
+        // The sender lives in the watcher, so this never ends the stream
+        if let Some(path) = self.events.recv().await {
+            changed.insert(path);
+        }
+        // Editors often write a file in several steps; let them finish
+        tokio::time::sleep(SETTLE_TIME).await;
+        while let Ok(path) = self.events.try_recv() {
+            changed.insert(path);
+        }
+        changed.into_iter().collect()
+    }
+}